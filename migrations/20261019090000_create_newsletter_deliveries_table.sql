@@ -0,0 +1,12 @@
+CREATE TABLE newsletter_deliveries (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    outcome TEXT NOT NULL,
+    n_attempts SMALLINT NOT NULL,
+    sent_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
+
+-- Support looking up the delivery history of a single subscriber
+CREATE INDEX newsletter_deliveries_subscriber_email_idx ON newsletter_deliveries (subscriber_email);
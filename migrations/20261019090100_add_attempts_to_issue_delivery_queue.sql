@@ -0,0 +1 @@
+ALTER TABLE issue_delivery_queue ADD COLUMN n_attempts SMALLINT NOT NULL DEFAULT 0;
@@ -0,0 +1,8 @@
+CREATE TABLE suppressed_emails (
+    email TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    suppressed_at timestamptz NOT NULL
+);
+
+-- Suppression must hold regardless of how the address is capitalised
+CREATE UNIQUE INDEX suppressed_emails_email_idx ON suppressed_emails (lower(email));
@@ -0,0 +1,37 @@
+-- We wrap the whole migration in a transaction to make sure it succeeds or fails atomically
+BEGIN;
+    CREATE TABLE lists (
+        list_id uuid PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        created_at timestamptz NOT NULL
+    );
+
+    -- Everybody who subscribed before lists existed belongs to the default list
+    INSERT INTO lists (list_id, name, created_at)
+    VALUES ('6149bdd6-d90e-4241-b007-62745603d135', 'Newsletter', now());
+
+    CREATE TABLE list_subscriptions (
+        subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+        list_id uuid NOT NULL REFERENCES lists (list_id),
+        status TEXT NOT NULL,
+        subscribed_at timestamptz NOT NULL,
+        PRIMARY KEY (subscriber_id, list_id)
+    );
+
+    INSERT INTO list_subscriptions (subscriber_id, list_id, status, subscribed_at)
+    SELECT
+        id,
+        '6149bdd6-d90e-4241-b007-62745603d135',
+        CASE WHEN status = 'confirmed' THEN 'confirmed' ELSE 'pending_confirmation' END,
+        subscribed_at
+    FROM subscriptions;
+
+    -- A confirmation link confirms the membership of the list it was requested for
+    ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+    UPDATE subscription_tokens SET list_id = '6149bdd6-d90e-4241-b007-62745603d135';
+    ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+    ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+    UPDATE newsletter_issues SET list_id = '6149bdd6-d90e-4241-b007-62745603d135';
+    ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
+COMMIT;
@@ -0,0 +1,11 @@
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    tag TEXT NOT NULL,
+    tagged_at timestamptz NOT NULL,
+    PRIMARY KEY (subscriber_id, tag)
+);
+
+-- Segment evaluation looks subscribers up by tag
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+ALTER TABLE newsletter_issues ADD COLUMN segment TEXT NULL;
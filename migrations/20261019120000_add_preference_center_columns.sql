@@ -0,0 +1,7 @@
+-- Subscribers can pause delivery until a given time from the preference center
+ALTER TABLE subscriptions ADD COLUMN paused_until timestamptz NULL;
+
+-- Email change confirmations reuse subscription tokens: they carry the new address
+-- and are not tied to a mailing list
+ALTER TABLE subscription_tokens ADD COLUMN new_email TEXT NULL;
+ALTER TABLE subscription_tokens ALTER COLUMN list_id DROP NOT NULL;
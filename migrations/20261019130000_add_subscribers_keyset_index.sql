@@ -0,0 +1,2 @@
+-- The admin subscriber browser pages through subscribers newest first
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at DESC, id DESC);
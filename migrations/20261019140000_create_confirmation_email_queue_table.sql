@@ -0,0 +1,7 @@
+-- Confirmation emails for bulk imports are sent by the background worker
+CREATE TABLE confirmation_email_queue (
+    subscription_token TEXT NOT NULL,
+    n_attempts SMALLINT NOT NULL DEFAULT 0,
+    enqueued_at timestamptz NOT NULL,
+    PRIMARY KEY (subscription_token)
+);
@@ -0,0 +1,9 @@
+-- Confirmation links expire, and an unexpired one is sent again instead of minting a new one
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+
+-- Every confirmation email sent to an address, to cap how many it receives per window
+CREATE TABLE confirmation_email_sends (
+    email TEXT NOT NULL,
+    sent_at timestamptz NOT NULL
+);
+CREATE INDEX confirmation_email_sends_email_idx ON confirmation_email_sends (lower(email), sent_at);
@@ -0,0 +1,14 @@
+-- Consecutive failed logins, reset on success; past the limit the account is locked for a while
+ALTER TABLE users ADD COLUMN failed_login_attempts INT NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN locked_until timestamptz NULL;
+
+-- Audit trail of authentication events for security review
+CREATE TABLE security_events (
+    event_id uuid PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    username TEXT NOT NULL,
+    user_id uuid NULL,
+    details TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX security_events_occurred_at_idx ON security_events (occurred_at);
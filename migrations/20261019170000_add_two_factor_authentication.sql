@@ -0,0 +1,12 @@
+-- TOTP secret encrypted with the configured key; it only guards logins once enrolment is confirmed
+ALTER TABLE users ADD COLUMN totp_secret BYTEA NULL;
+ALTER TABLE users ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT false;
+-- Time step of the last accepted code, so that a code cannot be replayed
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE totp_recovery_codes (
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    code_hash TEXT NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
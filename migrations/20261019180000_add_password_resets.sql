@@ -0,0 +1,13 @@
+-- Where password reset links are sent; admins without an address cannot reset their password
+ALTER TABLE users ADD COLUMN email TEXT NULL;
+-- Bumped to log out every session of the user, e.g. after a password reset
+ALTER TABLE users ADD COLUMN session_generation INT NOT NULL DEFAULT 0;
+
+CREATE TABLE password_reset_tokens(
+    token_hash TEXT NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (token_hash)
+);
+CREATE INDEX password_reset_tokens_user_id_idx ON password_reset_tokens (user_id);
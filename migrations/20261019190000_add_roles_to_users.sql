@@ -0,0 +1,16 @@
+-- What each admin may do; the existing admins keep full access
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner'
+    CHECK (role IN ('owner', 'editor', 'viewer'));
+ALTER TABLE users ALTER COLUMN role SET DEFAULT 'viewer';
+-- Disabled admins can neither log in nor use their existing sessions
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT false;
+
+-- Deleting an admin removes what only belonged to them
+ALTER TABLE idempotency
+    DROP CONSTRAINT idempotency_user_id_fkey,
+    ADD CONSTRAINT idempotency_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE;
+ALTER TABLE totp_recovery_codes
+    DROP CONSTRAINT totp_recovery_codes_user_id_fkey,
+    ADD CONSTRAINT totp_recovery_codes_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE;
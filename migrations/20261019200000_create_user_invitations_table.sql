@@ -0,0 +1,11 @@
+-- Invitations for new admins, who pick their own username and password
+CREATE TABLE user_invitations(
+    invitation_id uuid PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'editor', 'viewer')),
+    invited_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL,
+    accepted_at timestamptz NULL,
+    revoked_at timestamptz NULL
+);
@@ -0,0 +1,7 @@
+-- Hashes of the passwords users had before, so that they cannot be reused
+CREATE TABLE password_history(
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    password_hash TEXT NOT NULL,
+    replaced_at timestamptz NOT NULL
+);
+CREATE INDEX password_history_user_id_idx ON password_history (user_id, replaced_at);
@@ -0,0 +1,2 @@
+-- Failed deliveries are retried later instead of straight away
+ALTER TABLE issue_delivery_queue ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
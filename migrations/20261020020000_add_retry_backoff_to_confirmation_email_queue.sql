@@ -0,0 +1,2 @@
+-- Failed confirmation emails are retried later, so that they do not hold up newsletter deliveries
+ALTER TABLE confirmation_email_queue ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
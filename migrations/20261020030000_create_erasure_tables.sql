@@ -0,0 +1,16 @@
+-- Erased addresses stay suppressed, but only as a keyed hash so the address itself is gone
+CREATE TABLE erased_suppressed_emails (
+    email_hash TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    suppressed_at timestamptz NOT NULL,
+    PRIMARY KEY (email_hash)
+);
+
+-- Subscribers confirm from their mailbox that they want their data erased
+CREATE TABLE erasure_requests (
+    token_hash TEXT NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (token_hash)
+);
+CREATE INDEX erasure_requests_subscriber_id_idx ON erasure_requests (subscriber_id);
@@ -0,0 +1,9 @@
+-- A new recovery email only replaces the old one once a link sent to it has been followed
+CREATE TABLE recovery_email_verifications(
+    token_hash TEXT NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (token_hash)
+);
+CREATE INDEX recovery_email_verifications_user_id_idx ON recovery_email_verifications (user_id);
@@ -0,0 +1,360 @@
+use super::password_reset::{generate_secret_token, hash_secret_token};
+use super::{Role, UserId};
+use crate::routes::ApiError;
+use crate::utils::e500;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, AUTHORIZATION};
+use actix_web::http::Method;
+use actix_web::{web, HttpMessage};
+use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// Makes leaked tokens easy to spot, for people and secret scanners alike
+const API_TOKEN_PREFIX: &str = "z2p_";
+// How often the last use of a token is written, to avoid a write on every request
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+
+/// What an API token may be used for. A token never allows more than the role of its user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiScope {
+    SubscribersRead,
+    SubscribersWrite,
+    IssuesRead,
+    IssuesWrite,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 4] = [
+        ApiScope::SubscribersRead,
+        ApiScope::SubscribersWrite,
+        ApiScope::IssuesRead,
+        ApiScope::IssuesWrite,
+    ];
+
+    pub fn parse(s: &str) -> Result<ApiScope, String> {
+        match s {
+            "subscribers:read" => Ok(ApiScope::SubscribersRead),
+            "subscribers:write" => Ok(ApiScope::SubscribersWrite),
+            "issues:read" => Ok(ApiScope::IssuesRead),
+            "issues:write" => Ok(ApiScope::IssuesWrite),
+            other => Err(format!("{} is not a valid scope.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::SubscribersRead => "subscribers:read",
+            ApiScope::SubscribersWrite => "subscribers:write",
+            ApiScope::IssuesRead => "issues:read",
+            ApiScope::IssuesWrite => "issues:write",
+        }
+    }
+
+    /// The scope a token needs for an API request, or `None` if tokens cannot be used for it.
+    pub fn required_for(method: &Method, path: &str) -> Option<ApiScope> {
+        let is_read = matches!(*method, Method::GET | Method::HEAD);
+        if path.starts_with("/api/v1/subscribers") {
+            Some(if is_read {
+                ApiScope::SubscribersRead
+            } else {
+                ApiScope::SubscribersWrite
+            })
+        } else if path.starts_with("/api/v1/issues") {
+            Some(if is_read {
+                ApiScope::IssuesRead
+            } else {
+                ApiScope::IssuesWrite
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// The scopes of the API token a request was made with. Requests made with a session have none.
+#[derive(Clone, Debug)]
+pub struct ApiTokenScopes(Vec<ApiScope>);
+
+impl ApiTokenScopes {
+    pub fn allows(&self, scope: ApiScope) -> bool {
+        self.0.contains(&scope)
+    }
+}
+
+/// A token of the user, as listed on the API tokens page.
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Creates a token for the user. It is returned once: only its hash is stored.
+#[tracing::instrument(name = "Create an API token", skip(connection_pool))]
+pub async fn create_api_token(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+) -> Result<String, sqlx::Error> {
+    let token = format!("{}{}", API_TOKEN_PREFIX, generate_secret_token());
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_secret_token(&token),
+        &scopes
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(token)
+}
+
+/// Tokens of the user that were not revoked, newest first.
+#[tracing::instrument(name = "Get the API tokens of a user", skip(connection_pool))]
+pub async fn get_api_tokens(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<ApiToken>, sqlx::Error> {
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT token_id, name, scopes, created_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(connection_pool)
+    .await
+}
+
+/// Revokes one token of the user. Returns whether there was such a token.
+#[tracing::instrument(name = "Revoke an API token", skip(connection_pool))]
+pub async fn revoke_api_token(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+    token_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        token_id,
+        user_id
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Revokes every token of the user, along with their sessions when the password changes or the
+/// user is disabled.
+#[tracing::instrument(name = "Revoke all API tokens of a user", skip(transaction))]
+pub async fn revoke_all_api_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE user_id = $1 AND revoked_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// What a token is checked against on every request.
+struct TokenUser {
+    token_id: Uuid,
+    user_id: Uuid,
+    role: Role,
+    disabled: bool,
+    scopes: Vec<ApiScope>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Authenticates API requests sent with an `Authorization: Bearer` token as the user the token
+/// belongs to, if the token has the scope of the request. Requests without a token go on to the
+/// session checks.
+pub async fn authenticate_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let token = match bearer_token(req.headers()) {
+        Some(token) => token,
+        None => return next.call(req).await,
+    };
+
+    let connection_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .expect("The connection pool is missing from the application data")
+        .clone();
+    let token_user = match get_token_user(&connection_pool, &token)
+        .await
+        .map_err(e500)?
+    {
+        Some(token_user) if !token_user.disabled => token_user,
+        _ => {
+            let e = ApiError::Unauthorized("The API token is invalid or has been revoked.".into());
+            return Err(e.into());
+        }
+    };
+
+    let required_scope = ApiScope::required_for(req.method(), req.path());
+    if !required_scope.map_or(false, |scope| token_user.scopes.contains(&scope)) {
+        let message = match required_scope {
+            Some(scope) => format!("The API token does not have the {} scope.", scope),
+            None => "API tokens cannot be used for this request.".into(),
+        };
+        return Err(ApiError::Forbidden(message).into());
+    }
+    touch_api_token(&connection_pool, &token_user)
+        .await
+        .map_err(e500)?;
+
+    req.extensions_mut().insert(UserId(token_user.user_id));
+    req.extensions_mut().insert(token_user.role);
+    req.extensions_mut()
+        .insert(ApiTokenScopes(token_user.scopes));
+    next.call(req).await
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let (scheme, token) = headers.get(AUTHORIZATION)?.to_str().ok()?.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then(|| token.to_string())
+}
+
+async fn get_token_user(
+    connection_pool: &PgPool,
+    token: &str,
+) -> Result<Option<TokenUser>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT t.token_id, t.user_id, t.scopes, t.last_used_at, u.role, u.disabled
+        FROM api_tokens t
+        JOIN users u ON u.user_id = t.user_id
+        WHERE t.token_hash = $1 AND t.revoked_at IS NULL
+        "#,
+        hash_secret_token(token)
+    )
+    .fetch_optional(connection_pool)
+    .await
+    .context("Failed to get the user of the API token.")?;
+    row.map(|r| {
+        Ok(TokenUser {
+            token_id: r.token_id,
+            user_id: r.user_id,
+            role: Role::parse(&r.role).map_err(anyhow::Error::msg)?,
+            disabled: r.disabled,
+            // Scopes that are no longer known are ignored
+            scopes: r
+                .scopes
+                .iter()
+                .filter_map(|s| ApiScope::parse(s).ok())
+                .collect(),
+            last_used_at: r.last_used_at,
+        })
+    })
+    .transpose()
+}
+
+/// Records that the token is still in use.
+async fn touch_api_token(
+    connection_pool: &PgPool,
+    token_user: &TokenUser,
+) -> Result<(), sqlx::Error> {
+    if token_user.last_used_at.map_or(false, |last_used_at| {
+        Utc::now() - last_used_at < Duration::seconds(LAST_USED_RESOLUTION_SECONDS)
+    }) {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"UPDATE api_tokens SET last_used_at = now() WHERE token_id = $1"#,
+        token_user.token_id
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bearer_token, ApiScope};
+    use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use actix_web::http::Method;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        headers
+    }
+
+    #[test]
+    fn scopes_round_trip_through_their_name() {
+        for scope in ApiScope::ALL {
+            assert_eq!(ApiScope::parse(scope.as_str()), Ok(scope));
+        }
+        assert!(ApiScope::parse("users:write").is_err());
+    }
+
+    #[test]
+    fn reads_and_writes_need_different_scopes() {
+        assert_eq!(
+            ApiScope::required_for(&Method::GET, "/api/v1/subscribers"),
+            Some(ApiScope::SubscribersRead)
+        );
+        assert_eq!(
+            ApiScope::required_for(&Method::DELETE, "/api/v1/subscribers/1"),
+            Some(ApiScope::SubscribersWrite)
+        );
+        assert_eq!(
+            ApiScope::required_for(&Method::GET, "/api/v1/issues/1/stats"),
+            Some(ApiScope::IssuesRead)
+        );
+        assert_eq!(
+            ApiScope::required_for(&Method::POST, "/api/v1/issues/1/publish"),
+            Some(ApiScope::IssuesWrite)
+        );
+        assert_eq!(ApiScope::required_for(&Method::GET, "/admin/users"), None);
+    }
+
+    #[test]
+    fn only_bearer_tokens_are_read() {
+        assert_eq!(
+            bearer_token(&headers("Bearer z2p_abc")),
+            Some("z2p_abc".to_string())
+        );
+        assert_eq!(
+            bearer_token(&headers("bearer z2p_abc")),
+            Some("z2p_abc".to_string())
+        );
+        assert_eq!(bearer_token(&headers("Basic dXNlcjpwYXNz")), None);
+        assert_eq!(bearer_token(&headers("Bearer ")), None);
+        assert_eq!(bearer_token(&HeaderMap::new()), None);
+    }
+}
@@ -0,0 +1,129 @@
+use super::middleware::is_api_request;
+use super::password_reset::generate_secret_token;
+use super::ApiTokenScopes;
+use crate::rate_limiting::bytes_to_payload;
+use crate::routes::ApiError;
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, ContentType};
+use actix_web::http::Method;
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use actix_web_lab::middleware::Next;
+
+/// The CSRF token of the session. Every admin form posts it back in a `csrf_token` field.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl std::fmt::Display for CsrfToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Rejects admin form posts that do not carry the CSRF token of the session, so that other
+/// sites cannot submit forms with the session cookie of an admin.
+/// Forms that upload files pass the token in the query string instead, as only url-encoded
+/// bodies are read here.
+pub async fn reject_invalid_csrf_tokens(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    // Browsers do not send API tokens on their own, so requests made with them cannot be forged
+    if req.extensions().contains::<ApiTokenScopes>() {
+        return next.call(req).await;
+    }
+
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let csrf_token = match session.get_csrf_token().map_err(e500)? {
+        Some(csrf_token) => csrf_token,
+        None => {
+            let csrf_token = generate_secret_token();
+            session.insert_csrf_token(&csrf_token).map_err(e500)?;
+            csrf_token
+        }
+    };
+
+    if !matches!(*req.method(), Method::GET | Method::HEAD) {
+        let submitted_token = get_submitted_token(&mut req).await?;
+        if !submitted_token.map_or(false, |t| tokens_match(&t, &csrf_token)) {
+            if is_api_request(&req) {
+                return Err(ApiError::Forbidden(
+                    "The request does not carry the CSRF token of the session.".into(),
+                )
+                .into());
+            }
+            let response = HttpResponse::Forbidden()
+                .content_type(ContentType::plaintext())
+                .body(
+                    "The form has expired or was not sent from this site. \
+                    Please reload the page and try again.",
+                );
+            let e = anyhow::anyhow!("{} was posted without a valid CSRF token", req.path());
+            return Err(InternalError::from_response(e, response).into());
+        }
+    }
+
+    req.extensions_mut().insert(CsrfToken(csrf_token));
+    next.call(req).await
+}
+
+#[derive(serde::Deserialize)]
+struct CsrfTokenQuery {
+    csrf_token: Option<String>,
+}
+
+async fn get_submitted_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    let is_form = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .map_or(false, |content_type| {
+            content_type.starts_with("application/x-www-form-urlencoded")
+        });
+    if !is_form {
+        let query: Option<CsrfTokenQuery> = serde_urlencoded::from_str(req.query_string()).ok();
+        return Ok(query.and_then(|q| q.csrf_token));
+    }
+
+    // The body is read here and handed back to the request for the handler to read it again
+    let body = {
+        let (http_request, payload) = req.parts_mut();
+        web::Bytes::from_request(http_request, payload).await
+    }?;
+    let fields: Vec<(String, String)> = serde_urlencoded::from_bytes(&body).unwrap_or_default();
+    req.set_payload(bytes_to_payload(body));
+    Ok(fields
+        .into_iter()
+        .find(|(name, _)| name == "csrf_token")
+        .map(|(_, value)| value))
+}
+
+/// Compares secrets byte by byte in full, so that the time taken does not tell how much of the
+/// submitted one was right.
+pub fn tokens_match(submitted: &str, expected: &str) -> bool {
+    submitted.len() == expected.len()
+        && submitted
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokens_match;
+
+    #[test]
+    fn only_identical_tokens_match() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc124", "abc123"));
+        assert!(!tokens_match("abc12", "abc123"));
+        assert!(!tokens_match("", "abc123"));
+    }
+}
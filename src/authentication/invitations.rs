@@ -0,0 +1,166 @@
+use super::password_reset::{generate_secret_token, hash_secret_token};
+use crate::authentication::Role;
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// Invitations that are not accepted within a week have to be sent again
+const INVITATION_TTL_DAYS: i64 = 7;
+
+pub struct Invitation {
+    pub invitation_id: Uuid,
+    pub email: String,
+    pub role: Role,
+    pub invited_by: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Stores an invitation and returns its token. Earlier invitations to the same address are
+/// revoked, so that only the latest link works.
+#[tracing::instrument(name = "Store an invitation", skip(transaction))]
+pub async fn store_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    role: Role,
+    invited_by: Uuid,
+) -> Result<String, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE user_invitations
+        SET revoked_at = now()
+        WHERE lower(email) = lower($1) AND accepted_at IS NULL AND revoked_at IS NULL
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let token = generate_secret_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations
+            (invitation_id, token_hash, email, role, invited_by, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        hash_secret_token(&token),
+        email,
+        role.as_str(),
+        invited_by
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(token)
+}
+
+/// Invitations that can still be accepted, newest first.
+#[tracing::instrument(name = "Get pending invitations", skip(connection_pool))]
+pub async fn get_pending_invitations(
+    connection_pool: &PgPool,
+) -> Result<Vec<Invitation>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT i.invitation_id, i.email, i.role, u.username AS "invited_by?", i.created_at
+        FROM user_invitations i
+        LEFT JOIN users u ON u.user_id = i.invited_by
+        WHERE i.accepted_at IS NULL AND i.revoked_at IS NULL AND i.created_at > $1
+        ORDER BY i.created_at DESC
+        "#,
+        oldest_valid_invitation()
+    )
+    .fetch_all(connection_pool)
+    .await
+    .context("Failed to get the pending invitations.")?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(Invitation {
+                invitation_id: r.invitation_id,
+                email: r.email,
+                role: Role::parse(&r.role).map_err(anyhow::Error::msg)?,
+                invited_by: r.invited_by,
+                expires_at: r.created_at + Duration::days(INVITATION_TTL_DAYS),
+            })
+        })
+        .collect()
+}
+
+/// The invitation a token stands for, if it can still be accepted.
+#[tracing::instrument(name = "Get an invitation", skip(connection_pool, token))]
+pub async fn get_invitation(
+    connection_pool: &PgPool,
+    token: &str,
+) -> Result<Option<Invitation>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT i.invitation_id, i.email, i.role, u.username AS "invited_by?", i.created_at
+        FROM user_invitations i
+        LEFT JOIN users u ON u.user_id = i.invited_by
+        WHERE i.token_hash = $1
+            AND i.accepted_at IS NULL AND i.revoked_at IS NULL AND i.created_at > $2
+        "#,
+        hash_secret_token(token),
+        oldest_valid_invitation()
+    )
+    .fetch_optional(connection_pool)
+    .await
+    .context("Failed to get the invitation.")?;
+    row.map(|r| {
+        Ok(Invitation {
+            invitation_id: r.invitation_id,
+            email: r.email,
+            role: Role::parse(&r.role).map_err(anyhow::Error::msg)?,
+            invited_by: r.invited_by,
+            expires_at: r.created_at + Duration::days(INVITATION_TTL_DAYS),
+        })
+    })
+    .transpose()
+}
+
+/// Marks the invitation as accepted and returns its email and role, if it could still be
+/// accepted.
+#[tracing::instrument(name = "Accept an invitation", skip(transaction, token))]
+pub async fn accept_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<(String, Role)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE user_invitations
+        SET accepted_at = now()
+        WHERE token_hash = $1 AND accepted_at IS NULL AND revoked_at IS NULL AND created_at > $2
+        RETURNING email, role
+        "#,
+        hash_secret_token(token),
+        oldest_valid_invitation()
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to accept the invitation.")?;
+    row.map(|r| Ok((r.email, Role::parse(&r.role).map_err(anyhow::Error::msg)?)))
+        .transpose()
+}
+
+/// Returns the invited email, or `None` if there was no pending invitation to revoke.
+#[tracing::instrument(name = "Revoke an invitation", skip(connection_pool))]
+pub async fn revoke_invitation(
+    connection_pool: &PgPool,
+    invitation_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE user_invitations
+        SET revoked_at = now()
+        WHERE invitation_id = $1 AND accepted_at IS NULL AND revoked_at IS NULL
+        RETURNING email
+        "#,
+        invitation_id
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.map(|r| r.email))
+}
+
+fn oldest_valid_invitation() -> DateTime<Utc> {
+    Utc::now() - Duration::days(INVITATION_TTL_DAYS)
+}
@@ -0,0 +1,198 @@
+use crate::security_events::{
+    record_security_event, ACCOUNT_LOCKED, LOGIN_FAILED, LOGIN_SUCCEEDED,
+};
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Consecutive failed logins allowed before the account is locked.
+pub const MAX_FAILED_LOGIN_ATTEMPTS: i32 = 5;
+// The first lockout lasts 15 minutes and doubles with every further failure, up to a day
+const BASE_LOCKOUT_SECONDS: i64 = 15 * 60;
+const MAX_LOCKOUT_SECONDS: i64 = 24 * 60 * 60;
+
+pub struct LoginLockout {
+    pub user_id: Uuid,
+    pub username: String,
+    pub failed_login_attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+/// How long to lock an account after its `failed_login_attempts`-th consecutive failure.
+pub fn lockout_duration(failed_login_attempts: i32) -> Option<Duration> {
+    if failed_login_attempts < MAX_FAILED_LOGIN_ATTEMPTS {
+        return None;
+    }
+    let doublings = (failed_login_attempts - MAX_FAILED_LOGIN_ATTEMPTS).min(16) as u32;
+    let seconds = (BASE_LOCKOUT_SECONDS << doublings).min(MAX_LOCKOUT_SECONDS);
+    Some(Duration::seconds(seconds))
+}
+
+/// Counts a failed login for the user, locking the account once there were too many of them,
+/// and records it for security review.
+#[tracing::instrument(name = "Record a failed login", skip(connection_pool))]
+pub async fn record_failed_login(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+    username: &str,
+    details: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let locked_until = increment_failed_logins(connection_pool, user_id)
+        .await
+        .context("Failed to record a failed login.")?;
+    record_security_event(
+        connection_pool,
+        LOGIN_FAILED,
+        username,
+        Some(user_id),
+        details,
+    )
+    .await
+    .context("Failed to record a security event.")?;
+    if let Some(locked_until) = locked_until {
+        let details = format!("Locked until {}", locked_until.to_rfc3339());
+        record_security_event(
+            connection_pool,
+            ACCOUNT_LOCKED,
+            username,
+            Some(user_id),
+            Some(&details),
+        )
+        .await
+        .context("Failed to record a security event.")?;
+    }
+    Ok(())
+}
+
+/// Forgets the past failures of a user who completed a login and records it for security review.
+#[tracing::instrument(name = "Record a successful login", skip(connection_pool))]
+pub async fn record_successful_login(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+    username: &str,
+    details: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    clear_failed_logins(connection_pool, user_id)
+        .await
+        .context("Failed to clear failed logins.")?;
+    record_security_event(
+        connection_pool,
+        LOGIN_SUCCEEDED,
+        username,
+        Some(user_id),
+        details,
+    )
+    .await
+    .context("Failed to record a security event.")?;
+    Ok(())
+}
+
+pub async fn is_locked_out(connection_pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT locked_until > now() AS "locked!"
+        FROM users
+        WHERE user_id = $1 AND locked_until IS NOT NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.map(|r| r.locked).unwrap_or(false))
+}
+
+// Returns when the lockout ends if the account got locked
+async fn increment_failed_logins(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let failed_login_attempts = sqlx::query!(
+        r#"
+        UPDATE users
+        SET failed_login_attempts = failed_login_attempts + 1
+        WHERE user_id = $1
+        RETURNING failed_login_attempts
+        "#,
+        user_id
+    )
+    .fetch_one(connection_pool)
+    .await?
+    .failed_login_attempts;
+
+    let lockout_duration = match lockout_duration(failed_login_attempts) {
+        Some(lockout_duration) => lockout_duration,
+        None => return Ok(None),
+    };
+    let locked_until = Utc::now() + lockout_duration;
+    sqlx::query!(
+        r#"UPDATE users SET locked_until = $1 WHERE user_id = $2"#,
+        locked_until,
+        user_id
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(Some(locked_until))
+}
+
+/// Forgets past failures and lifts any lockout of the user.
+#[tracing::instrument(name = "Clear failed logins", skip(connection_pool))]
+pub async fn clear_failed_logins(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE users
+        SET failed_login_attempts = 0, locked_until = NULL
+        WHERE user_id = $1
+        RETURNING username
+        "#,
+        user_id
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.map(|r| r.username))
+}
+
+/// Users with recent failed logins, locked accounts first.
+#[tracing::instrument(name = "Get login lockouts", skip(connection_pool))]
+pub async fn get_login_lockouts(
+    connection_pool: &PgPool,
+) -> Result<Vec<LoginLockout>, sqlx::Error> {
+    sqlx::query_as!(
+        LoginLockout,
+        r#"
+        SELECT user_id, username, failed_login_attempts, locked_until
+        FROM users
+        WHERE failed_login_attempts > 0 OR locked_until > now()
+        ORDER BY locked_until DESC NULLS LAST, failed_login_attempts DESC, username
+        "#
+    )
+    .fetch_all(connection_pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::authentication::{lockout_duration, MAX_FAILED_LOGIN_ATTEMPTS};
+    use chrono::Duration;
+
+    #[test]
+    fn accounts_are_not_locked_before_the_limit() {
+        assert_eq!(lockout_duration(MAX_FAILED_LOGIN_ATTEMPTS - 1), None);
+    }
+
+    #[test]
+    fn the_lockout_doubles_with_every_further_failure() {
+        let first = lockout_duration(MAX_FAILED_LOGIN_ATTEMPTS).unwrap();
+        let second = lockout_duration(MAX_FAILED_LOGIN_ATTEMPTS + 1).unwrap();
+        assert_eq!(first, Duration::minutes(15));
+        assert_eq!(second, first * 2);
+    }
+
+    #[test]
+    fn the_lockout_is_capped_at_a_day() {
+        assert_eq!(lockout_duration(i32::MAX), Some(Duration::days(1)));
+    }
+}
@@ -3,17 +3,20 @@ use std::ops::Deref;
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::{web, FromRequest, HttpMessage};
 use actix_web_lab::middleware::Next;
 
+use crate::authentication::{get_session_user, touch_session, ApiTokenScopes};
+use crate::routes::ApiError;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
+use sqlx::PgPool;
 use uuid::Uuid;
 
 // Wrapper to prevent conflicts in the type map used by middleware to pass information downstream
 // Note: Used to insert information to pass downstream to request handlers into the type map
 #[derive(Copy, Clone, Debug)]
-pub struct UserId(Uuid);
+pub struct UserId(pub(super) Uuid);
 
 impl std::fmt::Display for UserId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -29,10 +32,41 @@ impl Deref for UserId {
     }
 }
 
+// The row of the current session in `user_sessions`
+#[derive(Copy, Clone, Debug)]
+pub struct SessionId(Uuid);
+
+impl Deref for SessionId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Requests to the API are turned away with JSON errors rather than redirects or plain text.
+pub(super) fn is_api_request(req: &ServiceRequest) -> bool {
+    req.path().starts_with("/api/")
+}
+
+// API clients are told to authenticate, browsers are sent to the login page
+fn reject_logged_out_user(req: &ServiceRequest, e: anyhow::Error) -> actix_web::Error {
+    if is_api_request(req) {
+        ApiError::Unauthorized("You must log in or send an API token.".into()).into()
+    } else {
+        InternalError::from_response(e, see_other("/login")).into()
+    }
+}
+
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
 ) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    // Requests made with an API token carry no session, and were authenticated already
+    if req.extensions().contains::<ApiTokenScopes>() {
+        return next.call(req).await;
+    }
+
     // ServiceRequest is a wrapper on HttpRequest and Payload.
     // Can leverage implementation of FromRequest
     let session = {
@@ -40,15 +74,39 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
-            req.extensions_mut().insert(UserId(user_id)); // Insert UserId into typemap for use downstream
-            next.call(req).await
-        }
-        None => {
-            let response = see_other("/login");
+    let (user_id, session_id) = match (
+        session.get_user_id().map_err(e500)?,
+        session.get_session_id().map_err(e500)?,
+    ) {
+        (Some(user_id), Some(session_id)) => (user_id, session_id),
+        _ => {
             let e = anyhow::anyhow!("The user is not logged in");
-            Err(InternalError::from_response(e, response).into())
+            return Err(reject_logged_out_user(&req, e));
         }
-    }
+    };
+
+    // Revoked sessions and disabled users are logged out
+    let connection_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .expect("The connection pool is missing from the application data")
+        .clone();
+    let session_user = match get_session_user(&connection_pool, user_id, session_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(session_user) if !session_user.disabled => session_user,
+        _ => {
+            session.log_out();
+            let e = anyhow::anyhow!("The session of the user has ended");
+            return Err(reject_logged_out_user(&req, e));
+        }
+    };
+    touch_session(&connection_pool, session_id, &session_user)
+        .await
+        .map_err(e500)?;
+
+    req.extensions_mut().insert(UserId(user_id)); // Insert UserId into typemap for use downstream
+    req.extensions_mut().insert(SessionId(session_id));
+    req.extensions_mut().insert(session_user.role);
+    next.call(req).await
 }
@@ -1,4 +1,27 @@
+mod api_tokens;
+mod csrf;
+mod invitations;
+mod lockout;
 mod middleware;
 mod password;
+mod password_policy;
+mod password_reset;
+mod roles;
+mod sessions;
+mod two_factor;
+mod users;
+pub use api_tokens::*;
+pub use csrf::*;
+pub use invitations::*;
+pub use lockout::*;
 pub use middleware::*;
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use password::{
+    change_password, validate_credentials, verify_current_password, AuthError, Credentials,
+    PasswordHashing,
+};
+pub use password_policy::*;
+pub use password_reset::*;
+pub use roles::*;
+pub use sessions::*;
+pub use two_factor::*;
+pub use users::*;
@@ -1,9 +1,13 @@
+use crate::authentication::record_failed_login;
+use crate::configuration::PasswordHashingSettings;
+use crate::security_events::{record_security_event, LOGIN_FAILED, LOGIN_WHILE_LOCKED};
 use crate::telemetry::spawn_blocking_with_tracing;
 use anyhow::Context;
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use chrono::{DateTime, Utc};
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
@@ -18,48 +22,188 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, connection_pool))]
+/// The Argon2id parameters new password hashes are computed with.
+#[derive(Clone, Debug)]
+pub struct PasswordHashing(Params);
+
+impl PasswordHashing {
+    pub fn new(settings: &PasswordHashingSettings) -> Result<Self, anyhow::Error> {
+        let params = Params::new(
+            settings.memory_size_kib,
+            settings.iterations,
+            settings.parallelism,
+            None,
+        )
+        .map_err(|e| anyhow::anyhow!("Invalid password hashing parameters: {}", e))?;
+        Ok(Self(params))
+    }
+
+    fn hasher(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.0.clone())
+    }
+
+    /// Whether a stored hash was computed with an older algorithm or weaker parameters.
+    fn is_outdated(&self, password_hash: &PasswordHash) -> bool {
+        let params = match Params::try_from(password_hash) {
+            Ok(params) => params,
+            Err(_) => return true,
+        };
+        password_hash.algorithm != Algorithm::Argon2id.ident()
+            || password_hash.version != Some(Version::V0x13.into())
+            || params.m_cost() < self.0.m_cost()
+            || params.t_cost() < self.0.t_cost()
+            || params.p_cost() < self.0.p_cost()
+    }
+}
+
+#[tracing::instrument(
+    name = "Validate credentials",
+    skip(credentials, connection_pool, password_hashing)
+)]
 pub async fn validate_credentials(
     credentials: Credentials,
     connection_pool: &PgPool,
+    password_hashing: &PasswordHashing,
 ) -> Result<uuid::Uuid, AuthError> {
-    let mut user_id = None;
-    // Use a default hash so that password verification is run even if user does not exist
-    // Tradeoff the performance speed to mask difference in response times based on if user exists
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
-
-    if let Some((stored_user_id, stored_password_hash)) =
+    let mut stored_user = None;
+    let mut expected_password_hash = None;
+
+    if let Some(stored_credentials) =
         get_stored_credentials(&credentials.username, connection_pool).await?
     {
-        user_id = Some(stored_user_id);
-        expected_password_hash = stored_password_hash;
+        stored_user = Some((
+            stored_credentials.user_id,
+            stored_credentials.locked_until,
+            stored_credentials.disabled,
+        ));
+        expected_password_hash = Some(stored_credentials.password_hash);
     }
 
     // Spawn a separate threadpool to offload the CPU-intensive task of hashing the password
     // In order to remove the block on async tasks
+    // The hash is verified for locked accounts too, so the lockout does not change response times
+    let password_hashing = password_hashing.clone();
+    let verification = spawn_blocking_with_tracing(move || {
+        verify_password_hash(
+            expected_password_hash,
+            credentials.password,
+            &password_hashing,
+        )
+    })
+    .await
+    .context("Failed to spawn blocking task.")?;
+    let (password_is_valid, upgraded_password_hash) = match verification {
+        Ok(upgraded_password_hash) => (true, upgraded_password_hash),
+        Err(AuthError::InvalidCredentials(_)) => (false, None),
+        Err(e) => return Err(e),
+    };
+
+    let username = credentials.username;
+    let (user_id, locked_until, disabled) = match stored_user {
+        Some(stored_user) => stored_user,
+        None => {
+            record_security_event(connection_pool, LOGIN_FAILED, &username, None, None)
+                .await
+                .context("Failed to record a security event.")?;
+            return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                "Unknown username."
+            )));
+        }
+    };
+
+    // A locked account rejects even the right password, without extending the lockout
+    if let Some(locked_until) = locked_until.filter(|locked_until| *locked_until > Utc::now()) {
+        record_security_event(
+            connection_pool,
+            LOGIN_WHILE_LOCKED,
+            &username,
+            Some(user_id),
+            None,
+        )
+        .await
+        .context("Failed to record a security event.")?;
+        return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+            "The account is locked until {}.",
+            locked_until.to_rfc3339()
+        )));
+    }
+
+    if disabled {
+        record_security_event(
+            connection_pool,
+            LOGIN_FAILED,
+            &username,
+            Some(user_id),
+            Some("The account is disabled"),
+        )
+        .await
+        .context("Failed to record a security event.")?;
+        return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+            "The account is disabled."
+        )));
+    }
+
+    if !password_is_valid {
+        record_failed_login(connection_pool, user_id, &username, None).await?;
+        return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+            "Invalid password."
+        )));
+    }
+
+    if let Some(upgraded_password_hash) = upgraded_password_hash {
+        upgrade_password_hash(connection_pool, user_id, upgraded_password_hash).await?;
+    }
+    Ok(user_id)
+}
+
+/// Checks the password of a user who is logged in already, before a change that needs it.
+/// Unlike `validate_credentials`, a mistyped password does not count towards the login lockout.
+#[tracing::instrument(
+    name = "Verify the current password",
+    skip(password, connection_pool, password_hashing)
+)]
+pub async fn verify_current_password(
+    user_id: uuid::Uuid,
+    password: Secret<String>,
+    connection_pool: &PgPool,
+    password_hashing: &PasswordHashing,
+) -> Result<(), AuthError> {
+    let password_hash = sqlx::query!(
+        r#"SELECT password_hash FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(connection_pool)
+    .await
+    .context("Failed to retrieve the password hash of the user.")?
+    .password_hash;
+
+    let password_hashing = password_hashing.clone();
     spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
+        verify_password_hash(
+            Some(Secret::new(password_hash)),
+            password,
+            &password_hashing,
+        )
     })
     .await
     .context("Failed to spawn blocking task.")??;
+    Ok(())
+}
 
-    user_id
-        .ok_or_else(|| anyhow::anyhow!("Unknown username."))
-        .map_err(AuthError::InvalidCredentials)
+struct StoredCredentials {
+    user_id: uuid::Uuid,
+    password_hash: Secret<String>,
+    locked_until: Option<DateTime<Utc>>,
+    disabled: bool,
 }
 
 async fn get_stored_credentials(
     username: &str,
     connection_pool: &PgPool,
-) -> Result<Option<(uuid::Uuid, Secret<String>)>, anyhow::Error> {
+) -> Result<Option<StoredCredentials>, anyhow::Error> {
     let row: Option<_> = sqlx::query!(
         r#"
-        SELECT user_id, password_hash
+        SELECT user_id, password_hash, locked_until, disabled
         FROM users
         WHERE username = $1
         "#,
@@ -68,64 +212,197 @@ async fn get_stored_credentials(
     .fetch_optional(connection_pool)
     .await
     .context("Failed to perform a query to retrieve stored credentials.")?
-    .map(|row| (row.user_id, Secret::new(row.password_hash)));
+    .map(|row| StoredCredentials {
+        user_id: row.user_id,
+        password_hash: Secret::new(row.password_hash),
+        locked_until: row.locked_until,
+        disabled: row.disabled,
+    });
 
     Ok(row)
 }
 
+// A stored hash with outdated parameters and its replacement, for the same password
+struct UpgradedPasswordHash {
+    old: Secret<String>,
+    new: Secret<String>,
+}
+
+/// Returns the replacement of the stored hash if it is outdated.
+/// Without a stored hash, the candidate is hashed anyway so that unknown usernames take as long
+/// to reject as wrong passwords.
 #[tracing::instrument(
     name = "Verify password hash",
-    skip(expected_password_hash, password_candidate)
+    skip(expected_password_hash, password_candidate, password_hashing)
 )]
 fn verify_password_hash(
-    expected_password_hash: Secret<String>,
+    expected_password_hash: Option<Secret<String>>,
     password_candidate: Secret<String>,
-) -> Result<(), AuthError> {
-    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+    password_hashing: &PasswordHashing,
+) -> Result<Option<UpgradedPasswordHash>, AuthError> {
+    let expected_password_hash = match expected_password_hash {
+        Some(expected_password_hash) => expected_password_hash,
+        None => {
+            compute_password_hash(password_candidate, password_hashing)?;
+            return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                "Unknown username."
+            )));
+        }
+    };
+    let parsed_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")?;
 
+    // Argon2 takes the parameters to verify with from the stored hash
     Argon2::default()
         .verify_password(
             password_candidate.expose_secret().as_bytes(),
-            &expected_password_hash,
+            &parsed_password_hash,
         )
         .context("Invalid password.")
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+
+    if !password_hashing.is_outdated(&parsed_password_hash) {
+        return Ok(None);
+    }
+    Ok(Some(UpgradedPasswordHash {
+        old: expected_password_hash,
+        new: compute_password_hash(password_candidate, password_hashing)?,
+    }))
 }
 
-#[tracing::instrument(name = "Change password", skip(password, connection_pool))]
+/// Replaces a hash with one computed with the current parameters, unless the password was
+/// changed in the meantime.
+#[tracing::instrument(name = "Upgrade password hash", skip(connection_pool, password_hash))]
+async fn upgrade_password_hash(
+    connection_pool: &PgPool,
+    user_id: uuid::Uuid,
+    password_hash: UpgradedPasswordHash,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2 AND password_hash = $3
+        "#,
+        password_hash.new.expose_secret(),
+        user_id,
+        password_hash.old.expose_secret()
+    )
+    .execute(connection_pool)
+    .await
+    .context("Failed to upgrade the password hash of the user.")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Change password",
+    skip(password, transaction, password_hashing)
+)]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
-    connection_pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
+    password_hashing: &PasswordHashing,
+    history_size: i64,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
+    let password_hashing = password_hashing.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &password_hashing))
+            .await?
+            .context("Failed to hash password")?;
 
+    // The old hash is kept so that the password cannot be reused. The history holds
+    // `history_size - 1` hashes besides the current one; the pruning cannot see the row inserted
+    // alongside it, so it keeps one fewer of the older ones.
     sqlx::query!(
         r#"
+        WITH previous AS (
+            INSERT INTO password_history (user_id, password_hash, replaced_at)
+            SELECT user_id, password_hash, now() FROM users WHERE user_id = $2
+        ), pruned AS (
+            DELETE FROM password_history
+            WHERE user_id = $2
+            AND replaced_at NOT IN (
+                SELECT replaced_at FROM password_history
+                WHERE user_id = $2
+                ORDER BY replaced_at DESC
+                LIMIT GREATEST($3::bigint - 2, 0)
+            )
+        )
         UPDATE users
         SET password_hash = $1
         WHERE user_id = $2
         "#,
         password_hash.expose_secret(),
-        user_id
+        user_id,
+        history_size
     )
-    .execute(connection_pool)
+    .execute(transaction)
     .await
     .context("Failed to change user's password in the database")?;
     Ok(())
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+pub(super) fn compute_password_hash(
+    password: Secret<String>,
+    password_hashing: &PasswordHashing,
+) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    let password_hash = password_hashing
+        .hasher()
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
     Ok(Secret::new(password_hash))
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::authentication::PasswordHashing;
+    use crate::configuration::PasswordHashingSettings;
+    use argon2::PasswordHash;
+
+    fn password_hashing() -> PasswordHashing {
+        PasswordHashing::new(&PasswordHashingSettings {
+            memory_size_kib: 19456,
+            iterations: 2,
+            parallelism: 1,
+        })
+        .unwrap()
+    }
+
+    const SALT_AND_HASH: &str =
+        "gZiV/M1gPc22ElAH/Jh1Hw$CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno";
+
+    fn is_outdated(params: &str) -> bool {
+        let password_hash = format!("{}${}", params, SALT_AND_HASH);
+        password_hashing().is_outdated(&PasswordHash::new(&password_hash).unwrap())
+    }
+
+    #[test]
+    fn hashes_with_the_current_or_stronger_parameters_are_kept() {
+        assert!(!is_outdated("$argon2id$v=19$m=19456,t=2,p=1"));
+        assert!(!is_outdated("$argon2id$v=19$m=65536,t=3,p=1"));
+    }
+
+    #[test]
+    fn hashes_with_weaker_parameters_are_outdated() {
+        assert!(is_outdated("$argon2id$v=19$m=15000,t=2,p=1"));
+        assert!(is_outdated("$argon2id$v=19$m=19456,t=1,p=1"));
+    }
+
+    #[test]
+    fn hashes_with_another_algorithm_or_version_are_outdated() {
+        assert!(is_outdated("$argon2i$v=19$m=19456,t=2,p=1"));
+        assert!(is_outdated("$argon2id$v=16$m=19456,t=2,p=1"));
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        assert!(PasswordHashing::new(&PasswordHashingSettings {
+            memory_size_kib: 1,
+            iterations: 2,
+            parallelism: 1,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use crate::configuration::PasswordPolicySettings;
+use crate::telemetry::spawn_blocking_with_tracing;
+use anyhow::Context;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+#[derive(thiserror::Error, Debug)]
+pub enum PasswordPolicyError {
+    // The message is shown to the user as is
+    #[error("{0}")]
+    Violation(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// The rules every new password has to follow.
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    min_entropy_bits: f64,
+    history_size: i64,
+    common_passwords: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    pub fn new(settings: &PasswordPolicySettings) -> Result<Self, anyhow::Error> {
+        // The current password always counts, so the history cannot be shorter than that
+        if settings.history_size < 1 {
+            anyhow::bail!(
+                "The password history size must be at least 1, got {}",
+                settings.history_size
+            );
+        }
+        let common_passwords = std::fs::read_to_string(&settings.common_passwords_path)
+            .with_context(|| {
+                format!(
+                    "Failed to read the common passwords from {}",
+                    settings.common_passwords_path
+                )
+            })?;
+        Ok(Self {
+            min_length: settings.min_length,
+            max_length: settings.max_length,
+            min_entropy_bits: settings.min_entropy_bits,
+            history_size: settings.history_size,
+            common_passwords: parse_common_passwords(&common_passwords),
+        })
+    }
+
+    pub fn history_size(&self) -> i64 {
+        self.history_size
+    }
+
+    /// Checks a new password of `username`. Without a `user_id`, for accounts that do not exist
+    /// yet, earlier passwords are not checked.
+    #[tracing::instrument(name = "Check a new password", skip(self, connection_pool, password))]
+    pub async fn check(
+        &self,
+        connection_pool: &PgPool,
+        user_id: Option<Uuid>,
+        username: &str,
+        password: &Secret<String>,
+    ) -> Result<(), PasswordPolicyError> {
+        self.check_rules(username, password.expose_secret())
+            .map_err(PasswordPolicyError::Violation)?;
+        if let Some(user_id) = user_id {
+            if is_recent_password(connection_pool, user_id, password, self.history_size).await? {
+                return Err(PasswordPolicyError::Violation(format!(
+                    "The new password must be different from your last {} passwords.",
+                    self.history_size
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_rules(&self, username: &str, password: &str) -> Result<(), String> {
+        let length = password.chars().count();
+        if length < self.min_length || length > self.max_length {
+            return Err(format!(
+                "The new password must be between {} and {} characters long.",
+                self.min_length, self.max_length
+            ));
+        }
+        let lowercase_password = password.to_lowercase();
+        let lowercase_username = username.trim().to_lowercase();
+        if !lowercase_username.is_empty() && lowercase_password.contains(&lowercase_username) {
+            return Err("The new password cannot contain your username.".into());
+        }
+        if self.common_passwords.contains(&lowercase_password) {
+            return Err("The new password is too common. Please choose another one.".into());
+        }
+        if estimate_entropy_bits(password) < self.min_entropy_bits {
+            return Err(
+                "The new password is too easy to guess. Use more varied characters.".into(),
+            );
+        }
+        Ok(())
+    }
+}
+
+// One password per line, ignoring blank lines and comments
+fn parse_common_passwords(contents: &str) -> HashSet<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// A rough estimate of how hard a password is to brute force: every distinct character adds
+/// the bits of the character classes in use, repeated characters only add one bit.
+fn estimate_entropy_bits(password: &str) -> f64 {
+    let mut pool_size = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool_size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool_size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool_size += 10;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        pool_size += 33;
+    }
+    if !password.is_ascii() {
+        pool_size += 100;
+    }
+    if pool_size == 0 {
+        return 0.0;
+    }
+
+    let length = password.chars().count();
+    let distinct = password.chars().collect::<HashSet<_>>().len();
+    distinct as f64 * f64::from(pool_size).log2() + (length - distinct) as f64
+}
+
+/// Whether the password is the current one of the user or one of the ones before it.
+async fn is_recent_password(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+    password: &Secret<String>,
+    history_size: i64,
+) -> Result<bool, anyhow::Error> {
+    let recent_password_hashes: Vec<String> = sqlx::query!(
+        r#"
+        SELECT password_hash AS "password_hash!" FROM users WHERE user_id = $1
+        UNION ALL
+        (
+            SELECT password_hash FROM password_history
+            WHERE user_id = $1
+            ORDER BY replaced_at DESC
+            LIMIT $2
+        )
+        "#,
+        user_id,
+        history_size - 1
+    )
+    .fetch_all(connection_pool)
+    .await
+    .context("Failed to get the recent passwords of the user.")?
+    .into_iter()
+    .map(|r| r.password_hash)
+    .collect();
+
+    let password = password.clone();
+    spawn_blocking_with_tracing(move || {
+        recent_password_hashes.iter().any(|password_hash| {
+            PasswordHash::new(password_hash)
+                .map(|password_hash| {
+                    Argon2::default()
+                        .verify_password(password.expose_secret().as_bytes(), &password_hash)
+                        .is_ok()
+                })
+                .unwrap_or(false)
+        })
+    })
+    .await
+    .context("Failed to spawn blocking task.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_entropy_bits, parse_common_passwords, PasswordPolicy};
+    use crate::configuration::PasswordPolicySettings;
+    use claim::{assert_err, assert_ok};
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 13,
+            max_length: 128,
+            min_entropy_bits: 50.0,
+            history_size: 5,
+            common_passwords: parse_common_passwords("# Comment\nPassword12345\n\nqwertyuiop123\n"),
+        }
+    }
+
+    #[test]
+    fn the_length_must_be_within_bounds() {
+        assert_err!(policy().check_rules("alice", "Xy7#kd9!Lm2q"));
+        assert_ok!(policy().check_rules("alice", "Xy7#kd9!Lm2qP"));
+        assert_err!(policy().check_rules("alice", &"Xy7#kd9!Lm2qP".repeat(10)));
+    }
+
+    #[test]
+    fn the_length_is_counted_in_characters() {
+        assert_ok!(policy().check_rules("alice", "ééééééXy7#kd9!"));
+    }
+
+    #[test]
+    fn passwords_cannot_contain_the_username() {
+        assert_err!(policy().check_rules("Alice", "my-alice-Xy7#kd9!"));
+    }
+
+    #[test]
+    fn common_passwords_are_rejected_whatever_the_case() {
+        assert_err!(policy().check_rules("alice", "PASSWORD12345"));
+        assert_err!(policy().check_rules("alice", "qwertyuiop123"));
+    }
+
+    #[test]
+    fn comments_are_not_common_passwords() {
+        assert!(!parse_common_passwords("# Comment\n").contains("# comment"));
+    }
+
+    #[test]
+    fn repetitive_passwords_are_too_easy_to_guess() {
+        assert_err!(policy().check_rules("alice", "aaaaaaaaaaaaab"));
+        assert_err!(policy().check_rules("alice", "12121212121212"));
+    }
+
+    #[test]
+    fn more_character_classes_add_entropy() {
+        assert!(estimate_entropy_bits("abcdefgh") < estimate_entropy_bits("abcdEFGH"));
+        assert!(estimate_entropy_bits("abcdEFGH") < estimate_entropy_bits("abcdEF1!"));
+        assert_eq!(estimate_entropy_bits(""), 0.0);
+    }
+
+    #[test]
+    fn the_history_must_hold_the_current_password() {
+        let settings = PasswordPolicySettings {
+            min_length: 13,
+            max_length: 128,
+            min_entropy_bits: 50.0,
+            history_size: 0,
+            common_passwords_path: "configuration/common_passwords.txt".into(),
+        };
+        assert!(PasswordPolicy::new(&settings).is_err());
+    }
+}
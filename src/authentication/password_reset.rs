@@ -0,0 +1,211 @@
+use chrono::{Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// Reset and verification links are only good for a short while after they were requested
+const PASSWORD_RESET_TOKEN_TTL_MINUTES: i64 = 60;
+
+/// An admin who can be sent a password reset link.
+pub struct PasswordResetRecipient {
+    pub user_id: Uuid,
+    pub username: String,
+    pub email: String,
+}
+
+#[tracing::instrument(name = "Get password reset recipient", skip(connection_pool))]
+pub async fn get_password_reset_recipient(
+    connection_pool: &PgPool,
+    username: &str,
+) -> Result<Option<PasswordResetRecipient>, sqlx::Error> {
+    sqlx::query_as!(
+        PasswordResetRecipient,
+        r#"
+        SELECT user_id, username, email AS "email!"
+        FROM users
+        WHERE username = $1 AND email IS NOT NULL AND NOT disabled
+        "#,
+        username
+    )
+    .fetch_optional(connection_pool)
+    .await
+}
+
+pub async fn get_recovery_email(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT email FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(connection_pool)
+        .await?;
+    Ok(row.email)
+}
+
+/// Stops password reset links from being sent for the user.
+#[tracing::instrument(name = "Remove recovery email", skip(connection_pool))]
+pub async fn remove_recovery_email(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET email = NULL WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(())
+}
+
+/// Stores the address the user wants as recovery email until they verify it, and returns the
+/// token of the verification link. Only the latest request of the user can be verified.
+#[tracing::instrument(name = "Store a recovery email verification", skip(connection_pool))]
+pub async fn store_recovery_email_verification(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+    email: &str,
+) -> Result<String, sqlx::Error> {
+    let token = generate_secret_token();
+    let mut transaction = connection_pool.begin().await?;
+    sqlx::query!(
+        r#"DELETE FROM recovery_email_verifications WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO recovery_email_verifications (token_hash, user_id, email, created_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        hash_secret_token(&token),
+        user_id,
+        email
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(token)
+}
+
+/// Makes the address the token was sent to the recovery email of the user, if the token has
+/// not been used and has not expired. Returns the user.
+#[tracing::instrument(name = "Verify a recovery email", skip(connection_pool, token))]
+pub async fn verify_recovery_email(
+    connection_pool: &PgPool,
+    token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH verified AS (
+            DELETE FROM recovery_email_verifications
+            WHERE token_hash = $1 AND created_at > $2
+            RETURNING user_id, email
+        )
+        UPDATE users u
+        SET email = v.email
+        FROM verified v
+        WHERE u.user_id = v.user_id
+        RETURNING u.user_id
+        "#,
+        hash_secret_token(token),
+        oldest_valid_token()
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.map(|r| r.user_id))
+}
+
+/// Stores a new reset token for the user and returns it. Only its hash is kept.
+#[tracing::instrument(name = "Store a password reset token", skip(connection_pool))]
+pub async fn store_password_reset_token(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    let token = generate_secret_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at)
+        VALUES ($1, $2, now())
+        "#,
+        hash_secret_token(&token),
+        user_id
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(token)
+}
+
+/// The user a token resets the password of, if it is unused and has not expired.
+#[tracing::instrument(name = "Check a password reset token", skip(connection_pool, token))]
+pub async fn get_password_reset_user(
+    connection_pool: &PgPool,
+    token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM password_reset_tokens
+        WHERE token_hash = $1 AND used_at IS NULL AND created_at > $2
+        "#,
+        hash_secret_token(token),
+        oldest_valid_token()
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.map(|r| r.user_id))
+}
+
+/// Marks the token as used, along with every other token of the same user, and returns the
+/// user if the token was still valid.
+#[tracing::instrument(name = "Use a password reset token", skip(transaction, token))]
+pub async fn use_password_reset_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE token_hash = $1 AND used_at IS NULL AND created_at > $2
+        RETURNING user_id
+        "#,
+        hash_secret_token(token),
+        oldest_valid_token()
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let user_id = match row {
+        Some(row) => row.user_id,
+        None => return Ok(None),
+    };
+    sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(Some(user_id))
+}
+
+fn oldest_valid_token() -> chrono::DateTime<Utc> {
+    Utc::now() - Duration::minutes(PASSWORD_RESET_TOKEN_TTL_MINUTES)
+}
+
+/// A random token for links sent by email. Only its hash is stored.
+pub fn generate_secret_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+pub fn hash_secret_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
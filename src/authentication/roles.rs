@@ -0,0 +1,138 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpMessage, HttpResponse};
+use actix_web_lab::middleware::Next;
+
+use super::middleware::is_api_request;
+use crate::routes::ApiError;
+
+/// What an admin may do, from the least to the most privileged.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Reads the delivery stats.
+    Viewer,
+    /// Drafts and publishes newsletter issues.
+    Editor,
+    /// Manages subscribers, settings and the other admins.
+    Owner,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::Editor, Role::Viewer];
+
+    pub fn parse(s: &str) -> Result<Role, String> {
+        match s {
+            "owner" => Ok(Role::Owner),
+            "editor" => Ok(Role::Editor),
+            "viewer" => Ok(Role::Viewer),
+            other => Err(format!("{} is not a valid role.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    /// The least privileged role allowed to use an admin page or API endpoint. They are for
+    /// owners only unless listed here.
+    pub fn required_for(path: &str) -> Role {
+        match path {
+            "/admin/dashboard" | "/admin/password" | "/admin/logout" | "/admin/recovery_email" => {
+                Role::Viewer
+            }
+            path if path.starts_with("/admin/two_factor") => Role::Viewer,
+            path if path.starts_with("/admin/sessions") => Role::Viewer,
+            path if path.starts_with("/admin/api_tokens") => Role::Viewer,
+            "/admin/deliveries" => Role::Viewer,
+            "/admin/newsletters" => Role::Editor,
+            path if path.starts_with("/api/v1/issues/") && path.ends_with("/stats") => Role::Viewer,
+            path if path.starts_with("/api/v1/issues") => Role::Editor,
+            _ => Role::Owner,
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Rejects requests to admin pages and API endpoints the role of the user does not allow.
+/// Must run after `reject_anonymous_users`, which looks up the role.
+pub async fn reject_unauthorized_users(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let role = req
+        .extensions()
+        .get::<Role>()
+        .copied()
+        .expect("The role of the user is missing from the request extensions");
+    let required_role = Role::required_for(req.path());
+
+    if role >= required_role {
+        next.call(req).await
+    } else if is_api_request(&req) {
+        let message = format!("This request requires the {} role.", required_role);
+        Err(ApiError::Forbidden(message).into())
+    } else {
+        let response = HttpResponse::Forbidden()
+            .content_type(ContentType::plaintext())
+            .body("You do not have permission to access this page.");
+        let e = anyhow::anyhow!(
+            "{} requires the {} role, the user is a {}",
+            req.path(),
+            required_role,
+            role
+        );
+        Err(InternalError::from_response(e, response).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::authentication::Role;
+
+    #[test]
+    fn roles_are_ordered_by_privilege() {
+        assert!(Role::Viewer < Role::Editor);
+        assert!(Role::Editor < Role::Owner);
+    }
+
+    #[test]
+    fn roles_round_trip_through_their_name() {
+        for role in Role::ALL {
+            assert_eq!(Role::parse(role.as_str()), Ok(role));
+        }
+        assert!(Role::parse("admin").is_err());
+    }
+
+    #[test]
+    fn admin_pages_are_for_owners_unless_listed() {
+        assert_eq!(Role::required_for("/admin/users"), Role::Owner);
+        assert_eq!(Role::required_for("/admin/subscribers/export"), Role::Owner);
+        assert_eq!(Role::required_for("/admin/newsletters"), Role::Editor);
+        assert_eq!(Role::required_for("/admin/two_factor/enroll"), Role::Viewer);
+        assert_eq!(Role::required_for("/admin/sessions/revoke"), Role::Viewer);
+        assert_eq!(Role::required_for("/admin/api_tokens/revoke"), Role::Viewer);
+        assert_eq!(Role::required_for("/admin/dashboard"), Role::Viewer);
+        assert_eq!(Role::required_for("/api/v1/subscribers"), Role::Owner);
+        assert_eq!(Role::required_for("/api/v1/issues"), Role::Editor);
+        assert_eq!(
+            Role::required_for("/api/v1/issues/8c1a2f0e-5bd2-4b7c-9d4e-3f6a1b2c3d4e/publish"),
+            Role::Editor
+        );
+        assert_eq!(
+            Role::required_for("/api/v1/issues/8c1a2f0e-5bd2-4b7c-9d4e-3f6a1b2c3d4e/stats"),
+            Role::Viewer
+        );
+    }
+}
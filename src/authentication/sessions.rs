@@ -0,0 +1,205 @@
+use crate::authentication::Role;
+use crate::client_ip::TrustedProxies;
+use crate::session_state::TypedSession;
+use actix_web::http::header::USER_AGENT;
+use actix_web::{web, HttpRequest};
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// Sessions left alone for longer than this expire
+const SESSION_IDLE_DAYS: i64 = 1;
+// How often the last-seen time of a session is written, to avoid a write on every request
+const LAST_SEEN_RESOLUTION_SECONDS: i64 = 60;
+
+/// A session of the user, as listed on the sessions page.
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// What a session is checked against on every request.
+pub struct SessionUser {
+    pub role: Role,
+    pub disabled: bool,
+    last_seen_at: DateTime<Utc>,
+}
+
+/// Logs the user in and records the session, so that it can be listed and revoked.
+pub async fn start_session(
+    session: &TypedSession,
+    connection_pool: &PgPool,
+    user_id: Uuid,
+    request: &HttpRequest,
+) -> Result<(), anyhow::Error> {
+    let session_id = Uuid::new_v4();
+    let ip_address = request
+        .app_data::<web::Data<TrustedProxies>>()
+        .expect("The trusted proxies are missing from the application data")
+        .client_ip(request)
+        .map(|ip| ip.to_string());
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|h| h.to_str().ok())
+        .map(String::from);
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions
+            (session_id, user_id, created_at, last_seen_at, ip_address, user_agent)
+        VALUES ($1, $2, now(), now(), $3, $4)
+        "#,
+        session_id,
+        user_id,
+        ip_address,
+        user_agent
+    )
+    .execute(connection_pool)
+    .await
+    .context("Failed to record the session.")?;
+    session.insert_user_id(user_id)?;
+    session.insert_session_id(session_id)?;
+    Ok(())
+}
+
+/// The user of a session that was neither revoked nor left idle, or `None`.
+pub async fn get_session_user(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<Option<SessionUser>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT u.role, u.disabled, s.last_seen_at
+        FROM user_sessions s
+        JOIN users u ON u.user_id = s.user_id
+        WHERE
+            s.session_id = $1 AND
+            s.user_id = $2 AND
+            s.revoked_at IS NULL AND
+            s.last_seen_at > $3
+        "#,
+        session_id,
+        user_id,
+        oldest_active_session()
+    )
+    .fetch_optional(connection_pool)
+    .await
+    .context("Failed to get the user of the session.")?;
+    row.map(|r| {
+        Ok(SessionUser {
+            role: Role::parse(&r.role).map_err(anyhow::Error::msg)?,
+            disabled: r.disabled,
+            last_seen_at: r.last_seen_at,
+        })
+    })
+    .transpose()
+}
+
+/// Records that the session is still in use.
+pub async fn touch_session(
+    connection_pool: &PgPool,
+    session_id: Uuid,
+    session_user: &SessionUser,
+) -> Result<(), sqlx::Error> {
+    if Utc::now() - session_user.last_seen_at < Duration::seconds(LAST_SEEN_RESOLUTION_SECONDS) {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"UPDATE user_sessions SET last_seen_at = now() WHERE session_id = $1"#,
+        session_id
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(())
+}
+
+/// Sessions of the user that were neither revoked nor left idle, most recent first.
+#[tracing::instrument(name = "Get the sessions of a user", skip(connection_pool))]
+pub async fn get_user_sessions(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<UserSession>, sqlx::Error> {
+    sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT session_id, created_at, last_seen_at, ip_address, user_agent
+        FROM user_sessions
+        WHERE user_id = $1 AND revoked_at IS NULL AND last_seen_at > $2
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+        oldest_active_session()
+    )
+    .fetch_all(connection_pool)
+    .await
+}
+
+fn oldest_active_session() -> DateTime<Utc> {
+    Utc::now() - Duration::days(SESSION_IDLE_DAYS)
+}
+
+/// Revokes one session of the user. Returns whether there was such a session.
+#[tracing::instrument(name = "Revoke a session", skip(connection_pool))]
+pub async fn revoke_session(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Revokes every session of the user but `current_session_id`.
+#[tracing::instrument(name = "Revoke the other sessions of a user", skip(transaction))]
+pub async fn revoke_other_sessions(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    current_session_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE user_id = $1 AND session_id != $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        current_session_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Logs the user out of every session.
+#[tracing::instrument(name = "End all sessions of a user", skip(transaction))]
+pub async fn end_all_sessions(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE user_id = $1 AND revoked_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
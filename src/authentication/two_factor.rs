@@ -0,0 +1,404 @@
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// RFC 6238 defaults, the only parameters authenticator apps reliably support
+const TOTP_STEP_SECONDS: i64 = 30;
+const TOTP_DIGITS: usize = 6;
+const TOTP_SECRET_BYTES: usize = 20;
+const TOTP_ISSUER: &str = "zero2prod";
+const NONCE_BYTES: usize = 12;
+pub const N_RECOVERY_CODES: usize = 10;
+
+/// Encrypts TOTP secrets at rest with the configured key. The user id is authenticated along
+/// with the secret, so a secret copied onto another user does not decrypt.
+pub struct TotpCipher(Aes256Gcm);
+
+impl TotpCipher {
+    /// `key` is 32 bytes, hex encoded.
+    pub fn new(key: &Secret<String>) -> Result<Self, anyhow::Error> {
+        let key = hex::decode(key.expose_secret()).context("The TOTP key is not hex encoded")?;
+        if key.len() != 32 {
+            anyhow::bail!("The TOTP key must be 32 bytes long");
+        }
+        Ok(Self(Aes256Gcm::new(Key::from_slice(&key))))
+    }
+
+    pub fn encrypt(&self, user_id: Uuid, secret: &Secret<Vec<u8>>) -> Vec<u8> {
+        let nonce: [u8; NONCE_BYTES] = rand::thread_rng().gen();
+        let payload = Payload {
+            msg: secret.expose_secret(),
+            aad: user_id.as_bytes(),
+        };
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend(
+            self.0
+                .encrypt(Nonce::from_slice(&nonce), payload)
+                .expect("Failed to encrypt a TOTP secret"),
+        );
+        encrypted
+    }
+
+    pub fn decrypt(
+        &self,
+        user_id: Uuid,
+        encrypted: &[u8],
+    ) -> Result<Secret<Vec<u8>>, anyhow::Error> {
+        if encrypted.len() < NONCE_BYTES {
+            anyhow::bail!("The encrypted TOTP secret is too short");
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_BYTES);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: user_id.as_bytes(),
+        };
+        let secret = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt the TOTP secret"))?;
+        Ok(Secret::new(secret))
+    }
+}
+
+pub fn generate_totp_secret() -> Secret<Vec<u8>> {
+    let secret: [u8; TOTP_SECRET_BYTES] = rand::thread_rng().gen();
+    Secret::new(secret.to_vec())
+}
+
+/// The secret as authenticator apps expect it to be typed in.
+pub fn totp_secret_base32(secret: &Secret<Vec<u8>>) -> String {
+    BASE32_NOPAD.encode(secret.expose_secret())
+}
+
+/// `otpauth://` URI to show as a QR code when enrolling an authenticator app.
+pub fn totp_provisioning_uri(username: &str, secret: &Secret<Vec<u8>>) -> String {
+    format!(
+        concat!(
+            "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}",
+            "&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_STEP_SECONDS}"
+        ),
+        issuer = urlencoding::encode(TOTP_ISSUER),
+        username = urlencoding::encode(username),
+        secret = totp_secret_base32(secret),
+        TOTP_DIGITS = TOTP_DIGITS,
+        TOTP_STEP_SECONDS = TOTP_STEP_SECONDS,
+    )
+}
+
+pub fn totp_step(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(TOTP_STEP_SECONDS)
+}
+
+pub fn totp_code(secret: &Secret<Vec<u8>>, step: i64) -> String {
+    let mut mac = Hmac::<sha1::Sha1>::new_from_slice(secret.expose_secret())
+        .expect("HMAC can take a key of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    // Dynamic truncation, RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let code = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        code % 10u32.pow(TOTP_DIGITS as u32),
+        width = TOTP_DIGITS
+    )
+}
+
+/// Returns the time step `code` is valid for, allowing one step of clock drift either way.
+pub fn verify_totp_code(secret: &Secret<Vec<u8>>, code: &str, time: DateTime<Utc>) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let step = totp_step(time);
+    (step - 1..=step + 1).find(|step| totp_code(secret, *step) == code)
+}
+
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..N_RECOVERY_CODES)
+        .map(|_| {
+            let bytes: [u8; 5] = rand::thread_rng().gen();
+            let code = BASE32_NOPAD.encode(&bytes).to_lowercase();
+            format!("{}-{}", &code[..4], &code[4..])
+        })
+        .collect()
+}
+
+// Recovery codes are random enough that a fast hash is safe
+fn hash_recovery_code(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    hex::encode(Sha256::digest(code.as_bytes()))
+}
+
+pub struct TwoFactorSettings {
+    pub encrypted_secret: Vec<u8>,
+    pub enabled: bool,
+}
+
+pub enum SecondFactor {
+    Totp,
+    RecoveryCode,
+}
+
+#[tracing::instrument(name = "Get two-factor settings", skip(connection_pool))]
+pub async fn get_two_factor_settings(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<TwoFactorSettings>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret, totp_enabled FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.and_then(|r| {
+        r.totp_secret.map(|encrypted_secret| TwoFactorSettings {
+            encrypted_secret,
+            enabled: r.totp_enabled,
+        })
+    }))
+}
+
+pub async fn is_two_factor_enabled(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    Ok(get_two_factor_settings(connection_pool, user_id)
+        .await?
+        .map(|s| s.enabled)
+        .unwrap_or(false))
+}
+
+/// Stores a secret waiting for the user to prove their authenticator app produces its codes.
+#[tracing::instrument(
+    name = "Store a pending TOTP secret",
+    skip(connection_pool, encrypted_secret)
+)]
+pub async fn store_pending_totp_secret(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+    encrypted_secret: &[u8],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $1, totp_last_used_step = NULL
+        WHERE user_id = $2 AND NOT totp_enabled
+        "#,
+        encrypted_secret,
+        user_id
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(())
+}
+
+/// Turns two-factor authentication on, replacing any previous recovery codes. `step` is the time
+/// step of the code that confirmed the enrolment.
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip(transaction, recovery_codes)
+)]
+pub async fn enable_two_factor(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    step: i64,
+    recovery_codes: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_enabled = true, totp_last_used_step = $1
+        WHERE user_id = $2
+        "#,
+        step,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM totp_recovery_codes WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    for code in recovery_codes {
+        sqlx::query!(
+            r#"INSERT INTO totp_recovery_codes (user_id, code_hash) VALUES ($1, $2)"#,
+            user_id,
+            hash_recovery_code(code)
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(transaction))]
+pub async fn disable_two_factor(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_enabled = false, totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM totp_recovery_codes WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+pub async fn count_unused_recovery_codes(
+    connection_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "n_codes!" FROM totp_recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_one(connection_pool)
+    .await
+}
+
+/// Checks `code` as a TOTP code, then as a recovery code. Either can only be used once.
+#[tracing::instrument(name = "Verify a second factor", skip(connection_pool, cipher, code))]
+pub async fn verify_second_factor(
+    connection_pool: &PgPool,
+    cipher: &TotpCipher,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Option<SecondFactor>, anyhow::Error> {
+    let settings = match get_two_factor_settings(connection_pool, user_id)
+        .await
+        .context("Failed to get the two-factor settings")?
+    {
+        Some(settings) if settings.enabled => settings,
+        _ => return Ok(None),
+    };
+    let secret = cipher.decrypt(user_id, &settings.encrypted_secret)?;
+
+    if let Some(step) = verify_totp_code(&secret, code, Utc::now()) {
+        // Only accept steps after the last one used, so an observed code cannot be replayed
+        let accepted = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $1
+            WHERE user_id = $2 AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)
+            "#,
+            step,
+            user_id
+        )
+        .execute(connection_pool)
+        .await
+        .context("Failed to record the TOTP step")?
+        .rows_affected()
+            == 1;
+        return Ok(if accepted {
+            Some(SecondFactor::Totp)
+        } else {
+            None
+        });
+    }
+
+    let n_used = sqlx::query!(
+        r#"
+        UPDATE totp_recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code)
+    )
+    .execute(connection_pool)
+    .await
+    .context("Failed to use a recovery code")?
+    .rows_affected();
+    Ok(if n_used == 1 {
+        Some(SecondFactor::RecoveryCode)
+    } else {
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::authentication::{generate_totp_secret, totp_code, verify_totp_code, TotpCipher};
+    use chrono::{TimeZone, Utc};
+    use secrecy::{ExposeSecret, Secret};
+    use uuid::Uuid;
+
+    fn rfc_6238_secret() -> Secret<Vec<u8>> {
+        Secret::new(b"12345678901234567890".to_vec())
+    }
+
+    #[test]
+    fn codes_match_the_rfc_6238_test_vectors() {
+        // Last six digits of the SHA1 test vectors
+        assert_eq!(totp_code(&rfc_6238_secret(), 59 / 30), "287082");
+        assert_eq!(totp_code(&rfc_6238_secret(), 1111111109 / 30), "081804");
+        assert_eq!(totp_code(&rfc_6238_secret(), 2000000000 / 30), "279037");
+    }
+
+    #[test]
+    fn codes_from_the_adjacent_steps_are_accepted() {
+        let time = Utc.timestamp(1111111109, 0);
+        let secret = rfc_6238_secret();
+        let step = time.timestamp() / 30;
+
+        assert_eq!(
+            verify_totp_code(&secret, &totp_code(&secret, step - 1), time),
+            Some(step - 1)
+        );
+        assert_eq!(
+            verify_totp_code(&secret, &totp_code(&secret, step + 1), time),
+            Some(step + 1)
+        );
+        assert_eq!(
+            verify_totp_code(&secret, &totp_code(&secret, step + 2), time),
+            None
+        );
+    }
+
+    #[test]
+    fn a_secret_only_decrypts_for_the_user_it_was_encrypted_for() {
+        let cipher = TotpCipher::new(&Secret::new("00".repeat(32))).unwrap();
+        let user_id = Uuid::new_v4();
+        let secret = generate_totp_secret();
+
+        let encrypted = cipher.encrypt(user_id, &secret);
+
+        assert_ne!(&encrypted, secret.expose_secret());
+        assert_eq!(
+            cipher.decrypt(user_id, &encrypted).unwrap().expose_secret(),
+            secret.expose_secret()
+        );
+        assert!(cipher.decrypt(Uuid::new_v4(), &encrypted).is_err());
+    }
+}
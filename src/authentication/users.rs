@@ -0,0 +1,166 @@
+use super::password::{compute_password_hash, PasswordHashing};
+use crate::authentication::Role;
+use crate::telemetry::spawn_blocking_with_tracing;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub struct AdminUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: Role,
+    pub disabled: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CreateUserError {
+    #[error("The username {0} is already taken.")]
+    UsernameTaken(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[tracing::instrument(name = "Get admin users", skip(connection_pool))]
+pub async fn get_admin_users(connection_pool: &PgPool) -> Result<Vec<AdminUser>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT user_id, username, role, disabled
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(connection_pool)
+    .await
+    .context("Failed to get the admin users.")?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(AdminUser {
+                user_id: r.user_id,
+                username: r.username,
+                role: Role::parse(&r.role).map_err(anyhow::Error::msg)?,
+                disabled: r.disabled,
+            })
+        })
+        .collect()
+}
+
+/// Whether an admin already has `email` as recovery email.
+#[tracing::instrument(name = "Check for a user with an email", skip(connection_pool))]
+pub async fn email_belongs_to_user(
+    connection_pool: &PgPool,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+/// Adds an admin who can log in with `password` right away. `email` becomes their recovery email.
+#[tracing::instrument(
+    name = "Create an admin user",
+    skip(password, transaction, password_hashing)
+)]
+pub async fn create_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    password: Secret<String>,
+    role: Role,
+    email: Option<&str>,
+    password_hashing: &PasswordHashing,
+) -> Result<Uuid, CreateUserError> {
+    let password_hashing = password_hashing.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &password_hashing))
+            .await
+            .context("Failed to spawn blocking task.")?
+            .context("Failed to hash password")?;
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, role, email)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (username) DO NOTHING
+        RETURNING user_id
+        "#,
+        Uuid::new_v4(),
+        username,
+        password_hash.expose_secret(),
+        role.as_str(),
+        email
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to store the new admin user.")?;
+    match row {
+        Some(row) => Ok(row.user_id),
+        None => Err(CreateUserError::UsernameTaken(username.to_string())),
+    }
+}
+
+/// Locks the owners who are not disabled until the end of the transaction and returns them,
+/// so that concurrent changes cannot leave the newsletter without an owner.
+pub async fn lock_active_owners(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM users
+        WHERE role = 'owner' AND NOT disabled
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(transaction)
+    .await?;
+    Ok(rows.into_iter().map(|r| r.user_id).collect())
+}
+
+/// Returns the username, or `None` if the user does not exist.
+pub async fn set_user_role(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    role: Role,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"UPDATE users SET role = $1 WHERE user_id = $2 RETURNING username"#,
+        role.as_str(),
+        user_id
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.map(|r| r.username))
+}
+
+/// Returns the username, or `None` if the user does not exist.
+pub async fn set_user_disabled(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    disabled: bool,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"UPDATE users SET disabled = $1 WHERE user_id = $2 RETURNING username"#,
+        disabled,
+        user_id
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.map(|r| r.username))
+}
+
+/// Returns the username, or `None` if the user does not exist.
+pub async fn delete_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"DELETE FROM users WHERE user_id = $1 RETURNING username"#,
+        user_id
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.map(|r| r.username))
+}
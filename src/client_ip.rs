@@ -0,0 +1,93 @@
+use actix_web::HttpRequest;
+use std::net::IpAddr;
+
+/// The reverse proxies allowed to report the client address in `X-Forwarded-For`.
+/// Anyone can send the header, so it is ignored unless the request comes from one of them.
+#[derive(Clone)]
+pub struct TrustedProxies(Vec<IpAddr>);
+
+impl TrustedProxies {
+    pub fn new(proxies: Vec<IpAddr>) -> Self {
+        Self(proxies)
+    }
+
+    /// The address the request came from, read back through the trusted proxies.
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let mut client_ip = request.peer_addr()?.ip();
+        // Each proxy appends the address it got the request from, so the chain is walked from
+        // the end for as long as the hop it came through is trusted
+        let forwarded_for: Vec<&str> = request
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        for hop in forwarded_for.into_iter().rev() {
+            if !self.0.contains(&client_ip) {
+                break;
+            }
+            match hop.trim().parse() {
+                Ok(hop) => client_ip = hop,
+                Err(_) => break,
+            }
+        }
+        Some(client_ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_ip::TrustedProxies;
+    use actix_web::test::TestRequest;
+    use std::net::{IpAddr, SocketAddr};
+
+    const PROXY: &str = "10.0.0.1";
+
+    fn request(peer: &str, forwarded_for: Option<&str>) -> actix_web::HttpRequest {
+        let peer: IpAddr = peer.parse().unwrap();
+        let request = TestRequest::default().peer_addr(SocketAddr::new(peer, 443));
+        match forwarded_for {
+            Some(forwarded_for) => request.insert_header(("X-Forwarded-For", forwarded_for)),
+            None => request,
+        }
+        .to_http_request()
+    }
+
+    fn client_ip(peer: &str, forwarded_for: Option<&str>) -> String {
+        TrustedProxies::new(vec![PROXY.parse().unwrap()])
+            .client_ip(&request(peer, forwarded_for))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn the_header_is_ignored_when_the_peer_is_not_a_trusted_proxy() {
+        assert_eq!(
+            client_ip("203.0.113.7", Some("198.51.100.1")),
+            "203.0.113.7"
+        );
+    }
+
+    #[test]
+    fn a_trusted_proxy_reports_the_client_address() {
+        assert_eq!(client_ip(PROXY, Some("203.0.113.7")), "203.0.113.7");
+    }
+
+    #[test]
+    fn addresses_prepended_by_the_client_are_ignored() {
+        assert_eq!(
+            client_ip(PROXY, Some("198.51.100.1, 203.0.113.7")),
+            "203.0.113.7"
+        );
+    }
+
+    #[test]
+    fn the_peer_address_is_used_without_the_header() {
+        assert_eq!(client_ip(PROXY, None), PROXY);
+    }
+
+    #[test]
+    fn a_malformed_hop_stops_the_walk() {
+        assert_eq!(client_ip(PROXY, Some("not an ip")), PROXY);
+    }
+}
@@ -4,6 +4,7 @@ use sqlx::{
     postgres::{PgConnectOptions, PgSslMode},
     ConnectOptions,
 };
+use std::net::IpAddr;
 
 use crate::{domain::SubscriberEmail, email_client::EmailClient};
 
@@ -13,6 +14,11 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    pub postmark_webhook: WebhookSettings,
+    pub rate_limits: RateLimitSettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub security_headers: SecurityHeadersSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -33,6 +39,10 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    // 32 bytes, hex encoded, to encrypt the TOTP secrets of admins
+    pub totp_encryption_key: Secret<String>,
+    // Reverse proxies whose `X-Forwarded-For` header is believed
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -43,6 +53,71 @@ pub struct EmailClientSettings {
     pub timeout_milliseconds: u64,
 }
 
+// Basic auth credentials the email provider must present when calling our webhook
+#[derive(serde::Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+// Redis keys are namespaced with `key_prefix` so several deployments can share one instance
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub key_prefix: String,
+    pub subscribe: RateLimit,
+    pub login: RateLimit,
+}
+
+/// How many requests a client IP, and a submitted email or username, can make per window.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct RateLimit {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests_per_ip: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests_per_key: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+}
+
+// Argon2id cost parameters of new password hashes. Raising them upgrades stored hashes on login
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct PasswordHashingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_size_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+/// Rules new passwords have to follow. `history_size` counts the current password too.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct PasswordPolicySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_length: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_length: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_entropy_bits: f64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub history_size: i64,
+    // Read at startup, relative to the working directory like the configuration files
+    pub common_passwords_path: String,
+}
+
+/// Headers added to every response that does not set them itself. Empty values leave a header
+/// out, and a `hsts_max_age_seconds` of 0 turns Strict-Transport-Security off.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SecurityHeadersSettings {
+    pub content_security_policy: String,
+    pub frame_options: String,
+    pub referrer_policy: String,
+    pub content_type_nosniff: bool,
+    // Only worth setting where the application is served over HTTPS, browsers then refuse HTTP
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub hsts_max_age_seconds: u64,
+}
+
 impl EmailClientSettings {
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address");
@@ -0,0 +1,167 @@
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::{retry_delay_seconds, ExecutionOutcome, MAX_DELIVERY_ATTEMPTS};
+use crate::manage_links::ManageLinks;
+use crate::routes::send_confirmation_email;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::field::display;
+use tracing::Span;
+use uuid::Uuid;
+
+// Bulk imports queue their confirmation emails instead of sending thousands of them
+// while the upload request is in flight
+#[tracing::instrument(skip(transaction, subscription_token))]
+pub async fn enqueue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscription_token, enqueued_at)
+        VALUES ($1, now())
+        "#,
+        subscription_token
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(subscriber_id = tracing::field::Empty),
+    err
+)]
+pub async fn try_send_confirmation_email(
+    connection_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    manage_links: &ManageLinks,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = connection_pool.begin().await?;
+    // Lock the row with FOR UPDATE and skip locked rows with SKIP LOCKED
+    let task = sqlx::query!(
+        r#"
+        SELECT q.subscription_token, q.n_attempts, s.id, s.email, s.name
+        FROM confirmation_email_queue q
+        JOIN subscription_tokens t ON t.subscription_token = q.subscription_token
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE q.execute_after <= now()
+        ORDER BY q.execute_after
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let task = match task {
+        Some(task) => task,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("subscriber_id", &display(task.id));
+
+    let new_subscriber = SubscriberEmail::parse(task.email).and_then(|email| {
+        Ok(NewSubscriber {
+            email,
+            name: SubscriberName::parse(task.name)?,
+        })
+    });
+    match new_subscriber {
+        Ok(new_subscriber) => {
+            if let Err(e) = send_confirmation_email(
+                connection_pool,
+                email_client,
+                new_subscriber,
+                base_url,
+                &task.subscription_token,
+                &manage_links.link(task.id),
+            )
+            .await
+            {
+                if task.n_attempts + 1 >= MAX_DELIVERY_ATTEMPTS {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send a queued confirmation email. Giving up"
+                    );
+                } else {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send a queued confirmation email. Retrying later"
+                    );
+                    register_failed_attempt(transaction, &task.subscription_token, task.n_attempts)
+                        .await?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping a queued confirmation email. The stored subscriber is invalid"
+            );
+        }
+    }
+    delete_task(transaction, &task.subscription_token).await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    mut transaction: Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM confirmation_email_queue WHERE subscription_token = $1"#,
+        subscription_token
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn register_failed_attempt(
+    mut transaction: Transaction<'_, Postgres>,
+    subscription_token: &str,
+    n_attempts: i16,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE confirmation_email_queue
+        SET
+            n_attempts = n_attempts + 1,
+            execute_after = now() + make_interval(secs => $2)
+        WHERE subscription_token = $1
+        "#,
+        subscription_token,
+        retry_delay_seconds(n_attempts)
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Drops the queued confirmation emails of a subscriber, so that their tokens can be deleted.
+#[tracing::instrument(skip(transaction))]
+pub async fn dequeue_confirmation_emails(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM confirmation_email_queue
+        WHERE subscription_token IN (
+            SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1
+        )
+        "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
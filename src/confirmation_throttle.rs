@@ -0,0 +1,109 @@
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+/// How many confirmation emails an address can receive per window.
+pub const MAX_CONFIRMATION_EMAILS_PER_WINDOW: i64 = 3;
+pub const CONFIRMATION_EMAIL_WINDOW: &str = "1 hour";
+/// How long a confirmation link stays valid. Email change links are single use and do not expire.
+pub const SUBSCRIPTION_TOKEN_TTL: &str = "24 hours";
+
+/// Records a confirmation email to `email` unless the address already received the maximum
+/// number of them in the current window. Returns whether the email may be sent.
+#[tracing::instrument(name = "Reserve a confirmation email", skip(transaction, email))]
+pub async fn try_reserve_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    // Serialise concurrent requests for the same address until the transaction ends
+    sqlx::query!(
+        r#"SELECT pg_advisory_xact_lock(hashtext(lower($1)))"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM confirmation_email_sends
+        WHERE lower(email) = lower($1) AND sent_at <= now() - $2::text::interval
+        "#,
+        email,
+        CONFIRMATION_EMAIL_WINDOW
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let n_sent = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "n_sent!" FROM confirmation_email_sends
+        WHERE lower(email) = lower($1)
+        "#,
+        email
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    if n_sent >= MAX_CONFIRMATION_EMAILS_PER_WINDOW {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"INSERT INTO confirmation_email_sends (email, sent_at) VALUES ($1, now())"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(true)
+}
+
+/// Returns the most recent unexpired confirmation token of the subscriber for the list.
+#[tracing::instrument(name = "Get an unexpired subscription token", skip(transaction))]
+pub async fn get_unexpired_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT subscription_token FROM subscription_tokens
+        WHERE
+            subscriber_id = $1 AND
+            list_id = $2 AND
+            new_email IS NULL AND
+            created_at > now() - $3::text::interval
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+        subscriber_id,
+        list_id,
+        SUBSCRIPTION_TOKEN_TTL
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.map(|r| r.subscription_token))
+}
+
+/// Returns the most recent unexpired token of the subscriber for a change to `new_email`.
+#[tracing::instrument(
+    name = "Get an unexpired email change token",
+    skip(transaction, new_email)
+)]
+pub async fn get_unexpired_email_change_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_email: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT subscription_token FROM subscription_tokens
+        WHERE
+            subscriber_id = $1 AND
+            lower(new_email) = lower($2) AND
+            created_at > now() - $3::text::interval
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+        subscriber_id,
+        new_email,
+        SUBSCRIPTION_TOKEN_TTL
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.map(|r| r.subscription_token))
+}
@@ -0,0 +1,251 @@
+use crate::domain::SubscriberTag;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+/// A boolean expression over subscriber tags, e.g. `beta AND NOT "paid customers"`.
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    Tag(SubscriberTag),
+    Not(Box<Segment>),
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    Tag(SubscriberTag),
+}
+
+impl Segment {
+    /// Parses a segment expression. `NOT` binds tighter than `AND`, which binds tighter than `OR`.
+    /// Tags containing spaces must be wrapped in double quotes.
+    pub fn parse(s: String) -> Result<Segment, String> {
+        // Bound the input so that the recursive descent cannot blow the stack
+        if s.chars().count() > 512 {
+            return Err("The segment expression must be shorter than 512 characters.".into());
+        }
+        let mut tokens = tokenize(&s)?.into_iter().peekable();
+        let segment = parse_or(&mut tokens)?;
+        match tokens.next() {
+            None => Ok(segment),
+            Some(token) => Err(format!("Unexpected {:?} in segment expression.", token)),
+        }
+    }
+
+    /// Renders the segment as a SQL condition on a `subscriptions` row aliased as `s`.
+    /// Tags are returned separately, to be bound from `$first_param` onwards.
+    pub fn to_sql(&self, first_param: usize) -> (String, Vec<String>) {
+        let mut sql = String::new();
+        let mut tags = Vec::new();
+        self.write_sql(&mut sql, &mut tags, first_param);
+        (sql, tags)
+    }
+
+    fn write_sql(&self, sql: &mut String, tags: &mut Vec<String>, first_param: usize) {
+        match self {
+            Segment::Tag(tag) => {
+                sql.push_str(&format!(
+                    "EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ${})",
+                    first_param + tags.len()
+                ));
+                tags.push(tag.as_ref().to_string());
+            }
+            Segment::Not(inner) => {
+                sql.push_str("NOT (");
+                inner.write_sql(sql, tags, first_param);
+                sql.push(')');
+            }
+            Segment::And(left, right) | Segment::Or(left, right) => {
+                let operator = if matches!(self, Segment::And(..)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                sql.push('(');
+                left.write_sql(sql, tags, first_param);
+                sql.push_str(operator);
+                right.write_sql(sql, tags, first_param);
+                sql.push(')');
+            }
+        }
+    }
+}
+
+// Renders a normalised expression that parses back to the same segment
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Segment::Tag(tag) => write!(f, "\"{}\"", tag.as_ref()),
+            Segment::Not(inner) => write!(f, "NOT {}", inner),
+            Segment::And(left, right) => write!(f, "({} AND {})", left, right),
+            Segment::Or(left, right) => write!(f, "({} OR {})", left, right),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::LeftParen);
+        } else if c == ')' {
+            chars.next();
+            tokens.push(Token::RightParen);
+        } else if c == '"' {
+            chars.next();
+            let mut quoted = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => quoted.push(c),
+                    None => return Err("Unterminated quote in segment expression.".into()),
+                }
+            }
+            tokens.push(Token::Tag(SubscriberTag::parse(quoted)?));
+        } else if c.is_alphanumeric() || c == '-' || c == '_' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '-' || c == '_') {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            let token = match word.to_uppercase().as_str() {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                _ => Token::Tag(SubscriberTag::parse(word)?),
+            };
+            tokens.push(token);
+        } else {
+            return Err(format!("Unexpected character {} in segment expression.", c));
+        }
+    }
+    Ok(tokens)
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+fn parse_or(tokens: &mut Tokens) -> Result<Segment, String> {
+    let mut segment = parse_and(tokens)?;
+    while tokens.peek() == Some(&Token::Or) {
+        tokens.next();
+        segment = Segment::Or(Box::new(segment), Box::new(parse_and(tokens)?));
+    }
+    Ok(segment)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Segment, String> {
+    let mut segment = parse_not(tokens)?;
+    while tokens.peek() == Some(&Token::And) {
+        tokens.next();
+        segment = Segment::And(Box::new(segment), Box::new(parse_not(tokens)?));
+    }
+    Ok(segment)
+}
+
+fn parse_not(tokens: &mut Tokens) -> Result<Segment, String> {
+    match tokens.next() {
+        Some(Token::Not) => Ok(Segment::Not(Box::new(parse_not(tokens)?))),
+        Some(Token::LeftParen) => {
+            let segment = parse_or(tokens)?;
+            match tokens.next() {
+                Some(Token::RightParen) => Ok(segment),
+                _ => Err("Missing closing parenthesis in segment expression.".into()),
+            }
+        }
+        Some(Token::Tag(tag)) => Ok(Segment::Tag(tag)),
+        Some(token) => Err(format!("Unexpected {:?} in segment expression.", token)),
+        None => Err("The segment expression ended unexpectedly.".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{Segment, SubscriberTag};
+    use claim::assert_err;
+
+    fn tag(s: &str) -> Box<Segment> {
+        Box::new(Segment::Tag(SubscriberTag::parse(s.to_string()).unwrap()))
+    }
+
+    #[test]
+    fn a_single_tag_is_parsed() {
+        assert_eq!(Segment::parse("beta".into()), Ok(*tag("beta")));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        let segment = Segment::parse("a OR b AND NOT c".into()).unwrap();
+        assert_eq!(
+            segment,
+            Segment::Or(
+                tag("a"),
+                Box::new(Segment::And(tag("b"), Box::new(Segment::Not(tag("c")))))
+            )
+        );
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        let segment = Segment::parse("(a or b) and c".into()).unwrap();
+        assert_eq!(
+            segment,
+            Segment::And(Box::new(Segment::Or(tag("a"), tag("b"))), tag("c"))
+        );
+    }
+
+    #[test]
+    fn quoted_tags_may_contain_spaces() {
+        let segment = Segment::parse(r#""beta testers" AND NOT "paid customers""#.into()).unwrap();
+        assert_eq!(
+            segment,
+            Segment::And(
+                tag("beta testers"),
+                Box::new(Segment::Not(tag("paid customers")))
+            )
+        );
+    }
+
+    #[test]
+    fn the_displayed_expression_parses_back_to_the_same_segment() {
+        let segment = Segment::parse(r#"NOT (a OR "b c") AND d"#.into()).unwrap();
+        assert_eq!(Segment::parse(segment.to_string()), Ok(segment));
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        for expression in &[
+            "",
+            "a AND",
+            "(a OR b",
+            "a b",
+            "\"unterminated",
+            "a; DROP TABLE",
+        ] {
+            assert_err!(Segment::parse(expression.to_string()));
+        }
+    }
+
+    #[test]
+    fn tags_are_bound_as_parameters() {
+        let segment = Segment::parse("a AND NOT b".into()).unwrap();
+        let (sql, tags) = segment.to_sql(3);
+        assert_eq!(
+            sql,
+            "(EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $3) AND \
+            NOT (EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $4)))"
+        );
+        assert_eq!(tags, vec!["a".to_string(), "b".to_string()]);
+    }
+}
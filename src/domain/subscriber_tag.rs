@@ -0,0 +1,60 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    /// Returns an instance of `SubscriberTag` if the input is a non-empty tag of at most
+    /// 64 letters, digits, spaces, dashes or underscores. Surrounding whitespace is trimmed.
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let trimmed = s.trim();
+        let is_too_long = trimmed.chars().count() > 64;
+        let has_forbidden_characters = trimmed
+            .chars()
+            .any(|c| !(c.is_alphanumeric() || c == ' ' || c == '-' || c == '_'));
+
+        if trimmed.is_empty() || is_too_long || has_forbidden_characters {
+            Err(format!("{} is not a valid tag.", s))
+        } else {
+            Ok(Self(trimmed.to_string()))
+        }
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberTag;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn a_tag_with_spaces_is_valid() {
+        assert_ok!(SubscriberTag::parse("beta testers".to_string()));
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let tag = SubscriberTag::parse("  paid  ".to_string()).unwrap();
+        assert_eq!(tag.as_ref(), "paid");
+    }
+
+    #[test]
+    fn whitespace_only_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse("   ".to_string()));
+    }
+
+    #[test]
+    fn tags_containing_quotes_or_parentheses_are_rejected() {
+        for tag in &["\"paid\"", "(paid)", "paid;"] {
+            assert_err!(SubscriberTag::parse(tag.to_string()));
+        }
+    }
+
+    #[test]
+    fn a_65_character_tag_is_rejected() {
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+}
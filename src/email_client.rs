@@ -32,7 +32,7 @@ impl EmailClient {
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<Option<String>, reqwest::Error> {
         let url: reqwest::Url =
             reqwest::Url::parse(&self.base_url).expect("Failed to parse base_url");
         let full_url = url
@@ -47,7 +47,8 @@ impl EmailClient {
             text_body: text_content,
         };
 
-        self.http_client
+        let response_body = self
+            .http_client
             .post(full_url)
             .header(
                 "X-Postmark-Server-Token",
@@ -56,13 +57,26 @@ impl EmailClient {
             .json(&request_body)
             .send()
             .await?
-            .error_for_status()?;
+            .error_for_status()?
+            .bytes()
+            .await?;
         //Note: json method will also set the Content-Type header to application/json
 
-        Ok(())
+        // The message id is only used for bookkeeping - a body we cannot parse does not fail the send
+        let message_id = serde_json::from_slice::<SendEmailResponse>(&response_body)
+            .ok()
+            .and_then(|r| r.message_id);
+
+        Ok(message_id)
     }
 }
 
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
@@ -168,6 +182,31 @@ mod tests {
         assert_ok!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_returns_the_provider_message_id() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ErrorCode": 0,
+                "Message": "OK",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_eq!(
+            assert_ok!(outcome).as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
         let mock_server = MockServer::start().await;
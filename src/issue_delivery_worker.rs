@@ -6,10 +6,22 @@ use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
-    startup::get_connection_pool,
+    configuration::Settings, confirmation_email_queue::try_send_confirmation_email,
+    domain::SubscriberEmail, email_client::EmailClient, manage_links::ManageLinks,
+    startup::get_connection_pool, suppression_list::is_suppressed_or_erased,
+    suppression_list::SuppressionKey,
 };
 
+// A delivery that still fails after this many attempts is recorded as failed and dropped
+pub const MAX_DELIVERY_ATTEMPTS: i16 = 5;
+// The wait before the first retry, doubled after every failed attempt
+const RETRY_BASE_DELAY_SECONDS: f64 = 60.0;
+
+/// How long to wait before retrying a task that failed `n_attempts` times before.
+pub fn retry_delay_seconds(n_attempts: i16) -> f64 {
+    RETRY_BASE_DELAY_SECONDS * 2f64.powi(n_attempts.into())
+}
+
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
@@ -26,13 +38,15 @@ pub enum ExecutionOutcome {
 pub async fn try_execute_task(
     connection_pool: &PgPool,
     email_client: &EmailClient,
+    manage_links: &ManageLinks,
+    suppression_key: &SuppressionKey,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
     let task = dequeue_task(connection_pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
 
-    let (transaction, issue_id, email) = task.unwrap();
+    let (mut transaction, issue_id, email, n_attempts) = task.unwrap();
 
     Span::current()
         .record("newsletter_issue_id", &display(issue_id))
@@ -40,7 +54,21 @@ pub async fn try_execute_task(
 
     match SubscriberEmail::parse(email.clone()) {
         Ok(email) => {
-            let issue = get_issue(connection_pool, issue_id).await?;
+            // The task was queued when the issue was published: the subscriber may have left the
+            // list or bounced since
+            let subscriber_id = get_recipient_id(connection_pool, issue_id, email.as_ref()).await?;
+            let is_suppressed =
+                is_suppressed_or_erased(connection_pool, suppression_key, email.as_ref()).await?;
+            let subscriber_id = match subscriber_id {
+                Some(subscriber_id) if !is_suppressed => subscriber_id,
+                _ => {
+                    tracing::info!("Skipping a subscriber who no longer receives the issue");
+                    delete_task(transaction, issue_id, email.as_ref()).await?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+            };
+            let mut issue = get_issue(connection_pool, issue_id).await?;
+            issue.append_manage_link(&manage_links.link(subscriber_id));
             match email_client
                 .send_email(
                     &email,
@@ -50,25 +78,62 @@ pub async fn try_execute_task(
                 )
                 .await
             {
-                Ok(_) => {
+                Ok(message_id) => {
                     // Only delete the task if send_email was successful
-                    delete_task(transaction, issue_id, &email.to_string()).await?
+                    // The delivery is recorded in the same transaction that removes the task
+                    let delivery = Delivery {
+                        issue_id,
+                        email: email.as_ref(),
+                        provider_message_id: message_id.as_deref(),
+                        outcome: "sent",
+                        n_attempts: n_attempts + 1,
+                    };
+                    record_delivery(&mut transaction, &delivery).await?;
+                    delete_task(transaction, issue_id, email.as_ref()).await?
+                }
+                Err(e) if n_attempts + 1 >= MAX_DELIVERY_ATTEMPTS => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber. Giving up"
+                    );
+                    let delivery = Delivery {
+                        issue_id,
+                        email: email.as_ref(),
+                        provider_message_id: None,
+                        outcome: "failed",
+                        n_attempts: n_attempts + 1,
+                    };
+                    record_delivery(&mut transaction, &delivery).await?;
+                    delete_task(transaction, issue_id, email.as_ref()).await?
                 }
                 Err(e) => {
                     tracing::error!(
                         error.cause_chain = ?e,
                         error.message = %e,
-                        "Failed to deliver issue to a confirmed subscriber. Skipping"
+                        "Failed to deliver issue to a confirmed subscriber. Retrying later"
                     );
+                    register_failed_attempt(transaction, issue_id, email.as_ref(), n_attempts)
+                        .await?;
                 }
             }
         }
         Err(e) => {
             tracing::error!(
             error.cause_chain = ?e,
-            error.message = %3,
+            error.message = %e,
             "Skipping a confirmed subscriber. The stored email address is invalid"
             );
+            // Retrying will never succeed - record the outcome and drop the task
+            let delivery = Delivery {
+                issue_id,
+                email: &email,
+                provider_message_id: None,
+                outcome: "invalid_address",
+                n_attempts: n_attempts + 1,
+            };
+            record_delivery(&mut transaction, &delivery).await?;
+            delete_task(transaction, issue_id, &email).await?
         }
     }
     Ok(ExecutionOutcome::TaskCompleted)
@@ -79,14 +144,16 @@ type PgTransaction = Transaction<'static, Postgres>;
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     connection_pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+) -> Result<Option<(PgTransaction, Uuid, String, i16)>, anyhow::Error> {
     let mut transaction = connection_pool.begin().await?;
 
     // Lock the row with FOR UPDATE and skip locked rows with SKIP LOCKED
     let r = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
+        SELECT newsletter_issue_id, subscriber_email, n_attempts
         FROM issue_delivery_queue
+        WHERE execute_after <= now()
+        ORDER BY execute_after
         FOR UPDATE
         SKIP LOCKED
         LIMIT 1
@@ -99,6 +166,7 @@ async fn dequeue_task(
             transaction,
             r.newsletter_issue_id,
             r.subscriber_email,
+            r.n_attempts,
         )))
     } else {
         Ok(None)
@@ -127,12 +195,115 @@ async fn delete_task(
     Ok(())
 }
 
+#[tracing::instrument(skip_all)]
+async fn register_failed_attempt(
+    mut transaction: PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    n_attempts: i16,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_attempts = n_attempts + 1,
+            execute_after = now() + make_interval(secs => $3)
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        issue_id,
+        email,
+        retry_delay_seconds(n_attempts)
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+struct Delivery<'a> {
+    issue_id: Uuid,
+    email: &'a str,
+    provider_message_id: Option<&'a str>,
+    outcome: &'a str,
+    n_attempts: i16,
+}
+
+#[tracing::instrument(skip_all)]
+async fn record_delivery(
+    transaction: &mut PgTransaction,
+    delivery: &Delivery<'_>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            provider_message_id,
+            outcome,
+            n_attempts,
+            sent_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        delivery.issue_id,
+        delivery.email,
+        delivery.provider_message_id,
+        delivery.outcome,
+        delivery.n_attempts
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
 }
 
+impl NewsletterIssue {
+    fn append_manage_link(&mut self, manage_link: &str) {
+        self.text_content
+            .push_str(&format!("\n\nManage your subscription: {}", manage_link));
+        self.html_content.push_str(&format!(
+            r#"<p><a href="{}">Manage your subscription</a></p>"#,
+            manage_link
+        ));
+    }
+}
+
+/// The subscriber with the address, if they are still confirmed and unpaused on the list of
+/// the issue.
+#[tracing::instrument(skip_all)]
+async fn get_recipient_id(
+    connection_pool: &PgPool,
+    issue_id: Uuid,
+    email: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT s.id
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        JOIN newsletter_issues i ON i.list_id = ls.list_id
+        WHERE
+            i.newsletter_issue_id = $1 AND
+            s.email = $2 AND
+            ls.status = 'confirmed' AND
+            s.status = 'confirmed' AND
+            (s.paused_until IS NULL OR s.paused_until <= now())
+        "#,
+        issue_id,
+        email
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(r.map(|r| r.id))
+}
+
 #[tracing::instrument(skip_all)]
 async fn get_issue(
     connection_pool: &PgPool,
@@ -160,9 +331,32 @@ async fn get_issue(
 async fn worker_loop(
     connection_pool: PgPool,
     email_client: EmailClient,
+    base_url: String,
+    manage_links: ManageLinks,
+    suppression_key: SuppressionKey,
 ) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&connection_pool, &email_client).await {
+        // Queued confirmation emails go out before newsletter issues
+        let outcome = match try_send_confirmation_email(
+            &connection_pool,
+            &email_client,
+            &base_url,
+            &manage_links,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                try_execute_task(
+                    &connection_pool,
+                    &email_client,
+                    &manage_links,
+                    &suppression_key,
+                )
+                .await
+            }
+            outcome => outcome,
+        };
+        match outcome {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -177,5 +371,17 @@ async fn worker_loop(
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
     let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    let suppression_key = SuppressionKey::new(configuration.application.hmac_secret.clone());
+    let manage_links = ManageLinks::new(
+        configuration.application.base_url.clone(),
+        configuration.application.hmac_secret,
+    );
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        manage_links,
+        suppression_key,
+    )
+    .await
 }
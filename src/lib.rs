@@ -1,11 +1,21 @@
 pub mod authentication;
+pub mod client_ip;
 pub mod configuration;
+pub mod confirmation_email_queue;
+pub mod confirmation_throttle;
 pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod mailing_lists;
+pub mod manage_links;
+pub mod personal_data;
+pub mod rate_limiting;
 pub mod routes;
+pub mod security_events;
+pub mod security_headers;
 pub mod session_state;
 pub mod startup;
+pub mod suppression_list;
 pub mod telemetry;
 pub mod utils;
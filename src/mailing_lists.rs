@@ -0,0 +1,81 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// The list every subscriber belonged to before multiple lists were introduced.
+// Requests that do not name a list keep targeting it.
+pub const DEFAULT_LIST_ID: Uuid = Uuid::from_u128(0x6149bdd6_d90e_4241_b007_62745603d135);
+
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub name: String,
+}
+
+#[tracing::instrument(name = "Get mailing lists", skip(connection_pool))]
+pub async fn get_lists(connection_pool: &PgPool) -> Result<Vec<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT list_id, name
+        FROM lists
+        ORDER BY created_at, name
+        "#
+    )
+    .fetch_all(connection_pool)
+    .await
+}
+
+#[tracing::instrument(name = "Check that a mailing list exists", skip(connection_pool))]
+pub async fn list_exists(connection_pool: &PgPool, list_id: Uuid) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT list_id FROM lists WHERE list_id = $1"#, list_id)
+        .fetch_optional(connection_pool)
+        .await?;
+    Ok(row.is_some())
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(connection_pool))]
+pub async fn insert_list(connection_pool: &PgPool, name: &str) -> Result<Uuid, sqlx::Error> {
+    let list_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, name, created_at)
+        VALUES ($1, $2, now())
+        "#,
+        list_id,
+        name
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(list_id)
+}
+
+/// Returns the status of the subscriber on the list, creating a pending membership if there is none.
+#[tracing::instrument(name = "Add a subscriber to a mailing list", skip(transaction))]
+pub async fn add_list_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (subscriber_id, list_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', now())
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let row = sqlx::query!(
+        r#"
+        SELECT status FROM list_subscriptions
+        WHERE subscriber_id = $1 AND list_id = $2
+        "#,
+        subscriber_id,
+        list_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    Ok(row.status)
+}
@@ -0,0 +1,92 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+/// Builds and verifies the signed "manage my subscription" links embedded in every email.
+/// The signature is an HMAC of the subscriber id, so a link cannot be forged for another subscriber.
+#[derive(Clone)]
+pub struct ManageLinks {
+    base_url: String,
+    hmac_secret: Secret<String>,
+}
+
+impl ManageLinks {
+    pub fn new(base_url: String, hmac_secret: Secret<String>) -> Self {
+        Self {
+            base_url,
+            hmac_secret,
+        }
+    }
+
+    pub fn link(&self, subscriber_id: Uuid) -> String {
+        format!("{}{}", self.base_url, self.path(subscriber_id))
+    }
+
+    /// The link without the base url, for redirects within the application.
+    pub fn path(&self, subscriber_id: Uuid) -> String {
+        format!(
+            "/subscriptions/manage?subscriber_id={}&signature={}",
+            subscriber_id,
+            self.signature(subscriber_id)
+        )
+    }
+
+    pub fn signature(&self, subscriber_id: Uuid) -> String {
+        let mut mac = self.mac();
+        mac.update(subscriber_id.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    pub fn verify(&self, subscriber_id: Uuid, signature: &str) -> bool {
+        let signature = match hex::decode(signature) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+        let mut mac = self.mac();
+        mac.update(subscriber_id.as_bytes());
+        // verify_slice compares in constant time
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    fn mac(&self) -> Hmac<sha2::Sha256> {
+        Hmac::<sha2::Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::manage_links::ManageLinks;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn manage_links(secret: &str) -> ManageLinks {
+        ManageLinks::new("http://localhost".into(), Secret::new(secret.into()))
+    }
+
+    #[test]
+    fn a_signature_is_valid_for_the_subscriber_it_was_issued_for() {
+        let links = manage_links("secret");
+        let subscriber_id = Uuid::new_v4();
+        assert!(links.verify(subscriber_id, &links.signature(subscriber_id)));
+    }
+
+    #[test]
+    fn a_signature_is_rejected_for_another_subscriber() {
+        let links = manage_links("secret");
+        let signature = links.signature(Uuid::new_v4());
+        assert!(!links.verify(Uuid::new_v4(), &signature));
+    }
+
+    #[test]
+    fn a_signature_made_with_another_secret_is_rejected() {
+        let subscriber_id = Uuid::new_v4();
+        let signature = manage_links("another secret").signature(subscriber_id);
+        assert!(!manage_links("secret").verify(subscriber_id, &signature));
+    }
+
+    #[test]
+    fn a_malformed_signature_is_rejected() {
+        assert!(!manage_links("secret").verify(Uuid::new_v4(), "not hex"));
+    }
+}
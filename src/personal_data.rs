@@ -0,0 +1,320 @@
+use crate::authentication::{generate_secret_token, hash_secret_token};
+use crate::suppression_list::{anonymise_suppression, SuppressionKey};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// Erasure links are only good for a short while after they were requested
+const ERASURE_REQUEST_TTL_MINUTES: i64 = 60;
+
+fn timestamp(t: DateTime<Utc>) -> String {
+    t.to_rfc3339()
+}
+
+/// Collects everything stored about an email address, for a data subject access request.
+#[tracing::instrument(
+    name = "Export personal data",
+    skip(connection_pool, suppression_key, email)
+)]
+pub async fn export_personal_data(
+    connection_pool: &PgPool,
+    suppression_key: &SuppressionKey,
+    email: &str,
+) -> Result<serde_json::Value, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, subscribed_at, paused_until
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+        "#,
+        email
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    let subscriber_id = subscriber.as_ref().map(|s| s.id);
+
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.name, ls.status, ls.subscribed_at
+        FROM list_subscriptions ls
+        JOIN lists l ON l.list_id = ls.list_id
+        WHERE ls.subscriber_id = $1
+        ORDER BY ls.subscribed_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(connection_pool)
+    .await?;
+
+    let tags = sqlx::query!(
+        r#"
+        SELECT tag, tagged_at FROM subscriber_tags
+        WHERE subscriber_id = $1
+        ORDER BY tagged_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(connection_pool)
+    .await?;
+
+    // Pending email changes to this address are stored against another subscriber
+    let tokens = sqlx::query!(
+        r#"
+        SELECT subscription_token, list_id, new_email
+        FROM subscription_tokens
+        WHERE subscriber_id = $1 OR lower(new_email) = lower($2)
+        "#,
+        subscriber_id,
+        email
+    )
+    .fetch_all(connection_pool)
+    .await?;
+
+    let confirmation_emails = sqlx::query!(
+        r#"
+        SELECT sent_at FROM confirmation_email_sends
+        WHERE lower(email) = lower($1)
+        ORDER BY sent_at
+        "#,
+        email
+    )
+    .fetch_all(connection_pool)
+    .await?;
+
+    let deliveries = sqlx::query!(
+        r#"
+        SELECT d.newsletter_issue_id, i.title, d.provider_message_id, d.outcome, d.n_attempts, d.sent_at
+        FROM newsletter_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE lower(d.subscriber_email) = lower($1)
+        ORDER BY d.sent_at
+        "#,
+        email
+    )
+    .fetch_all(connection_pool)
+    .await?;
+
+    let queued_deliveries = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, n_attempts
+        FROM issue_delivery_queue
+        WHERE lower(subscriber_email) = lower($1)
+        "#,
+        email
+    )
+    .fetch_all(connection_pool)
+    .await?;
+
+    // An erased address is only suppressed by its hash
+    let suppression = sqlx::query!(
+        r#"
+        SELECT reason AS "reason!", suppressed_at AS "suppressed_at!" FROM suppressed_emails
+        WHERE lower(email) = lower($1)
+        UNION ALL
+        SELECT reason, suppressed_at FROM erased_suppressed_emails
+        WHERE email_hash = $2
+        "#,
+        email,
+        suppression_key.hash(email)
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+
+    Ok(json!({
+        "email": email,
+        "subscriber": subscriber.map(|s| json!({
+            "id": s.id,
+            "email": s.email,
+            "name": s.name,
+            "status": s.status,
+            "subscribed_at": timestamp(s.subscribed_at),
+            "paused_until": s.paused_until.map(timestamp),
+        })),
+        "lists": lists.into_iter().map(|l| json!({
+            "name": l.name,
+            "status": l.status,
+            "subscribed_at": timestamp(l.subscribed_at),
+        })).collect::<Vec<_>>(),
+        "tags": tags.into_iter().map(|t| json!({
+            "tag": t.tag,
+            "tagged_at": timestamp(t.tagged_at),
+        })).collect::<Vec<_>>(),
+        "subscription_tokens": tokens.into_iter().map(|t| json!({
+            "subscription_token": t.subscription_token,
+            "list_id": t.list_id,
+            "new_email": t.new_email,
+        })).collect::<Vec<_>>(),
+        "confirmation_emails": confirmation_emails.into_iter().map(|c| json!({
+            "sent_at": timestamp(c.sent_at),
+        })).collect::<Vec<_>>(),
+        "deliveries": deliveries.into_iter().map(|d| json!({
+            "newsletter_issue_id": d.newsletter_issue_id,
+            "title": d.title,
+            "provider_message_id": d.provider_message_id,
+            "outcome": d.outcome,
+            "n_attempts": d.n_attempts,
+            "sent_at": timestamp(d.sent_at),
+        })).collect::<Vec<_>>(),
+        "queued_deliveries": queued_deliveries.into_iter().map(|d| json!({
+            "newsletter_issue_id": d.newsletter_issue_id,
+            "n_attempts": d.n_attempts,
+        })).collect::<Vec<_>>(),
+        "suppression": suppression.map(|s| json!({
+            "reason": s.reason,
+            "suppressed_at": timestamp(s.suppressed_at),
+        })),
+    }))
+}
+
+/// Deletes everything tied to an email address in a single transaction.
+/// Delivery records are kept for the newsletter statistics, with the address anonymised.
+/// The suppression list entry is kept as a keyed hash: it is what stops the address from being
+/// mailed again.
+/// Returns whether anything was stored about the address.
+#[tracing::instrument(
+    name = "Erase personal data",
+    skip(connection_pool, suppression_key, email)
+)]
+pub async fn erase_personal_data(
+    connection_pool: &PgPool,
+    suppression_key: &SuppressionKey,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = connection_pool.begin().await?;
+    let subscriber_ids: Vec<Uuid> = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .fetch_all(&mut transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+
+    let mut n_rows = 0;
+    n_rows += sqlx::query!(
+        r#"
+        DELETE FROM confirmation_email_queue
+        WHERE subscription_token IN (
+            SELECT subscription_token FROM subscription_tokens
+            WHERE subscriber_id = ANY($1) OR lower(new_email) = lower($2)
+        )
+        "#,
+        &subscriber_ids,
+        email
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    n_rows += sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE subscriber_id = ANY($1) OR lower(new_email) = lower($2)
+        "#,
+        &subscriber_ids,
+        email
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    n_rows += sqlx::query!(
+        r#"DELETE FROM confirmation_email_sends WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    n_rows += sqlx::query!(
+        r#"DELETE FROM list_subscriptions WHERE subscriber_id = ANY($1)"#,
+        &subscriber_ids
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    n_rows += sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = ANY($1)"#,
+        &subscriber_ids
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    n_rows += sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = ANY($1)"#,
+        &subscriber_ids
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    n_rows += sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE lower(subscriber_email) = lower($1)"#,
+        email
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    // The message id would let provider logs be tied back to the address
+    n_rows += sqlx::query!(
+        r#"
+        UPDATE newsletter_deliveries
+        SET subscriber_email = $1, provider_message_id = NULL
+        WHERE lower(subscriber_email) = lower($2)
+        "#,
+        format!("erased-{}", Uuid::new_v4()),
+        email
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    n_rows += anonymise_suppression(&mut transaction, suppression_key, email).await?;
+
+    transaction.commit().await?;
+    Ok(n_rows > 0)
+}
+
+/// Stores a request to erase the data of a subscriber and returns the token that confirms it.
+/// Only the hash of the token is stored.
+#[tracing::instrument(name = "Store an erasure request", skip(connection_pool))]
+pub async fn store_erasure_request(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    let token = generate_secret_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO erasure_requests (token_hash, subscriber_id, created_at)
+        VALUES ($1, $2, now())
+        "#,
+        hash_secret_token(&token),
+        subscriber_id
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(token)
+}
+
+/// Returns the address whose erasure the token confirms, or `None` if the token is invalid or
+/// has expired. The request is deleted along with the subscriber when the data is erased.
+#[tracing::instrument(name = "Get an erasure request", skip(connection_pool, token))]
+pub async fn get_erasure_request(
+    connection_pool: &PgPool,
+    token: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT s.email
+        FROM erasure_requests r
+        JOIN subscriptions s ON s.id = r.subscriber_id
+        WHERE r.token_hash = $1 AND r.created_at > $2
+        "#,
+        hash_secret_token(token),
+        oldest_valid_erasure_request()
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.map(|r| r.email))
+}
+
+fn oldest_valid_erasure_request() -> DateTime<Utc> {
+    Utc::now() - Duration::minutes(ERASURE_REQUEST_TTL_MINUTES)
+}
@@ -0,0 +1,151 @@
+use crate::client_ip::TrustedProxies;
+use crate::configuration::RateLimit;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::{InternalError, PayloadError};
+use actix_web::http::header::{ContentType, RETRY_AFTER};
+use actix_web::{web, FromRequest, HttpResponse};
+use actix_web_lab::middleware::Next;
+use futures_util::stream::{self, Stream};
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::pin::Pin;
+
+/// Fixed-window request counters kept in Redis, shared by every worker and instance.
+#[derive(Clone)]
+pub struct RateLimiter {
+    connection: ConnectionManager,
+    key_prefix: String,
+}
+
+impl RateLimiter {
+    pub async fn new(redis_uri: &str, key_prefix: String) -> Result<Self, anyhow::Error> {
+        let connection = ConnectionManager::new(redis::Client::open(redis_uri)?).await?;
+        Ok(Self {
+            connection,
+            key_prefix,
+        })
+    }
+
+    /// Counts a request against `key`. Returns how many seconds the client must wait if the
+    /// request is over the limit.
+    pub async fn hit(
+        &self,
+        key: &str,
+        max_requests: u64,
+        window_seconds: u64,
+    ) -> Result<Option<u64>, redis::RedisError> {
+        let key = format!("{}:{}", self.key_prefix, key);
+        // The window starts with the first request and the counter expires with it
+        let (n_requests, ttl): (u64, i64) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(0)
+            .arg("EX")
+            .arg(window_seconds)
+            .arg("NX")
+            .ignore()
+            .cmd("INCR")
+            .arg(&key)
+            .cmd("TTL")
+            .arg(&key)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        if n_requests > max_requests {
+            Ok(Some(ttl.max(1) as u64))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Who shares the counter of a `key_field` value.
+#[derive(Clone, Copy)]
+pub enum KeyScope {
+    /// Every client, e.g. to cap the emails an address receives however many clients ask.
+    Shared,
+    /// Each client IP, so that a client using up the limit of a username does not lock
+    /// its owner out.
+    PerClient,
+}
+
+/// Limits the requests to `route` per client IP and per value of the `key_field` form field.
+/// Meant to wrap a `POST` resource taking a url-encoded form.
+pub async fn rate_limit(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+    route: &'static str,
+    key_field: &'static str,
+    key_scope: KeyScope,
+    limit: RateLimit,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let rate_limiter = req
+        .app_data::<web::Data<RateLimiter>>()
+        .expect("The rate limiter is missing from the application data")
+        .clone();
+    let trusted_proxies = req
+        .app_data::<web::Data<TrustedProxies>>()
+        .expect("The trusted proxies are missing from the application data")
+        .clone();
+    let client_ip = trusted_proxies
+        .client_ip(req.parts_mut().0)
+        .map_or_else(|| "unknown".to_owned(), |ip| ip.to_string());
+
+    // Read the form to find the key, then put the body back for the handler
+    let body = {
+        let (http_request, payload) = req.parts_mut();
+        web::Bytes::from_request(http_request, payload).await
+    }?;
+    let key = serde_urlencoded::from_bytes::<HashMap<String, String>>(&body)
+        .ok()
+        .and_then(|mut form| form.remove(key_field))
+        .map(|value| value.trim().to_lowercase());
+    req.set_payload(bytes_to_payload(body));
+
+    let mut counters = vec![(
+        format!("{}:ip:{}", route, client_ip),
+        limit.max_requests_per_ip,
+    )];
+    if let Some(key) = key {
+        let counter = match key_scope {
+            KeyScope::Shared => format!("{}:{}:{}", route, key_field, key),
+            KeyScope::PerClient => format!("{}:{}:{}:ip:{}", route, key_field, key, client_ip),
+        };
+        counters.push((counter, limit.max_requests_per_key));
+    }
+
+    let mut retry_after = None;
+    for (counter, max_requests) in counters {
+        match rate_limiter
+            .hit(&counter, max_requests, limit.window_seconds)
+            .await
+        {
+            Ok(wait) => retry_after = retry_after.max(wait),
+            // Let requests through rather than locking everyone out while Redis is unavailable
+            Err(e) => tracing::error!(error.cause_chain = ?e, "Failed to check the rate limit"),
+        }
+    }
+
+    match retry_after {
+        None => next.call(req).await,
+        Some(retry_after) => {
+            tracing::warn!(route, client_ip = %client_ip, "Request rate limited");
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((RETRY_AFTER, retry_after.to_string()))
+                .content_type(ContentType::plaintext())
+                .body(format!(
+                    "Too many requests. Please try again in {} seconds.",
+                    retry_after
+                ));
+            let e = anyhow::anyhow!("Too many requests to {}", route);
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+pub(crate) fn bytes_to_payload(body: web::Bytes) -> Payload {
+    let stream: Pin<Box<dyn Stream<Item = Result<web::Bytes, PayloadError>>>> =
+        Box::pin(stream::once(async move { Ok(body) }));
+    Payload::from(stream)
+}
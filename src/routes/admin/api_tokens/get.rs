@@ -0,0 +1,34 @@
+use crate::authentication::{get_api_tokens, ApiScope, ApiToken, CsrfToken, UserId};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "admin/api_tokens.html")]
+struct ApiTokensTemplate {
+    flash_messages: IncomingFlashMessages,
+    tokens: Vec<ApiToken>,
+    scopes: [ApiScope; 4],
+    csrf_token: CsrfToken,
+}
+
+pub async fn api_tokens(
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let tokens = get_api_tokens(&connection_pool, **user_id)
+        .await
+        .map_err(e500)?;
+
+    render_page(&ApiTokensTemplate {
+        flash_messages,
+        tokens,
+        scopes: ApiScope::ALL,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::api_tokens;
+mod post;
+pub use post::{create_admin_api_token, revoke_admin_api_token};
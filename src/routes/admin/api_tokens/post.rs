@@ -0,0 +1,163 @@
+use crate::authentication::{
+    create_api_token, revoke_api_token, verify_current_password, ApiScope, AuthError,
+    PasswordHashing, UserId,
+};
+use crate::utils::{e500, render_page, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use askama::Template;
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(Template)]
+#[template(path = "admin/api_token_created.html")]
+struct ApiTokenCreatedTemplate {
+    name: String,
+    token: String,
+}
+
+// The form sends one `scopes` field per ticked box, which only a list of pairs can hold
+#[tracing::instrument(
+    name = "Create an admin API token",
+    skip(form, connection_pool, password_hashing)
+)]
+pub async fn create_admin_api_token(
+    form: web::Form<Vec<(String, String)>>,
+    connection_pool: web::Data<PgPool>,
+    password_hashing: web::Data<PasswordHashing>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let fields = form.into_inner();
+
+    // A token outlives the session, so a hijacked session is not enough to create one
+    let current_password = fields
+        .iter()
+        .find(|(key, _)| key == "current_password")
+        .map(|(_, value)| value.clone())
+        .unwrap_or_default();
+    if let Err(e) = verify_current_password(
+        **user_id,
+        Secret::new(current_password),
+        &connection_pool,
+        &password_hashing,
+    )
+    .await
+    {
+        return match e {
+            AuthError::InvalidCredentials(_) => {
+                FlashMessage::error("The current password is incorrect.").send();
+                Ok(see_other("/admin/api_tokens"))
+            }
+            AuthError::UnexpectedError(_) => Err(e500(e)),
+        };
+    }
+
+    let (name, scopes) = match parse_form(fields) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/api_tokens"));
+        }
+    };
+
+    let token = create_api_token(&connection_pool, **user_id, &name, &scopes)
+        .await
+        .map_err(e500)?;
+
+    // The token is shown once, as only its hash is stored
+    render_page(&ApiTokenCreatedTemplate { name, token }).map_err(e500)
+}
+
+fn parse_form(fields: Vec<(String, String)>) -> Result<(String, Vec<ApiScope>), String> {
+    let mut name = String::new();
+    let mut scopes = Vec::new();
+    for (key, value) in fields {
+        match key.as_str() {
+            "name" => name = value.trim().to_string(),
+            "scopes" => {
+                let scope = ApiScope::parse(&value)?;
+                if !scopes.contains(&scope) {
+                    scopes.push(scope);
+                }
+            }
+            _ => (),
+        }
+    }
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "The name of a token must be between 1 and {} characters long.",
+            MAX_NAME_LENGTH
+        ));
+    }
+    if scopes.is_empty() {
+        return Err("Choose at least one scope for the token.".into());
+    }
+    Ok((name, scopes))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeFormData {
+    token_id: Uuid,
+}
+
+#[tracing::instrument(name = "Revoke an admin API token", skip(form, connection_pool))]
+pub async fn revoke_admin_api_token(
+    form: web::Form<RevokeFormData>,
+    connection_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Only tokens of the user themselves can be revoked
+    if revoke_api_token(&connection_pool, **user_id, form.token_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The API token has been revoked.").send();
+    } else {
+        FlashMessage::error("The API token does not exist or has already been revoked.").send();
+    }
+    Ok(see_other("/admin/api_tokens"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_form;
+    use crate::authentication::ApiScope;
+    use claim::assert_err;
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn every_ticked_scope_is_kept_once() {
+        let (name, scopes) = parse_form(fields(&[
+            ("name", " CRM sync "),
+            ("scopes", "subscribers:read"),
+            ("scopes", "issues:write"),
+            ("scopes", "subscribers:read"),
+            ("csrf_token", "abc"),
+        ]))
+        .unwrap();
+        assert_eq!(name, "CRM sync");
+        assert_eq!(
+            scopes,
+            vec![ApiScope::SubscribersRead, ApiScope::IssuesWrite]
+        );
+    }
+
+    #[test]
+    fn a_name_and_a_scope_are_required() {
+        assert_err!(parse_form(fields(&[("scopes", "issues:read")])));
+        assert_err!(parse_form(fields(&[("name", "CRM sync")])));
+        assert_err!(parse_form(fields(&[
+            ("name", "CRM sync"),
+            ("scopes", "users:write")
+        ])));
+    }
+}
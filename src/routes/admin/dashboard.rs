@@ -1,43 +1,56 @@
-use crate::authentication::UserId;
-use crate::utils::e500;
-use actix_web::{http::header::ContentType, web, HttpResponse};
+use crate::authentication::{CsrfToken, Role, UserId};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
 use anyhow::Context;
+use askama::Template;
 use sqlx::PgPool;
 use uuid::Uuid;
 
+#[derive(Template)]
+#[template(path = "admin/dashboard.html")]
+struct DashboardTemplate {
+    username: String,
+    role: Role,
+    csrf_token: CsrfToken,
+    actions: Vec<(&'static str, &'static str)>,
+}
+
 pub async fn admin_dashboard(
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
     connection_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_token = csrf_token.into_inner();
     let user_id = user_id.into_inner();
+    let role = role.into_inner();
     let username = get_username(*user_id, &connection_pool)
         .await
         .map_err(e500)?;
 
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-<meta http-equiv="content-type" content="text/html; charset=utf-8">
-<title>Admin dashboard</title>
-</head>
-<body>
-<p>Welcome {username}!</p>
-<p>Available actions:</p>
-<ol>
-    <li><a href="/admin/password">Change password</a></li>
-    <li>
-        <form name="logoutForm" action="/admin/logout" method="post">
-            <input type="submit" value="Logout">
-        </form>
-    </li>
-    <li><a href="/admin/newsletters">Send a Newsletter</a></li>
-</ol>
-</body>
-</html>"#
-        )))
+    // Only offer the pages the role of the user allows
+    let actions = [
+        ("/admin/newsletters", "Send a Newsletter"),
+        ("/admin/subscribers", "Subscribers"),
+        ("/admin/lists", "Mailing lists"),
+        ("/admin/deliveries", "Delivery log"),
+        ("/admin/suppressions", "Suppression list"),
+        ("/admin/tags", "Subscriber tags"),
+        ("/admin/privacy", "Personal data requests"),
+        ("/admin/lockouts", "Login lockouts"),
+        ("/admin/users", "Users"),
+    ]
+    .into_iter()
+    .filter(|(href, _)| role >= Role::required_for(href))
+    .collect();
+
+    render_page(&DashboardTemplate {
+        username,
+        role,
+        csrf_token,
+        actions,
+    })
+    .map_err(e500)
 }
 
 #[tracing::instrument(name = "Get username", skip(connection_pool))]
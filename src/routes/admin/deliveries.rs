@@ -0,0 +1,96 @@
+use crate::utils::{e400, e500, render_page};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    newsletter_issue_id: Option<String>,
+    subscriber_email: Option<String>,
+}
+
+pub struct DeliveryRecord {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub subscriber_email: String,
+    pub provider_message_id: Option<String>,
+    pub outcome: String,
+    pub n_attempts: i16,
+    pub sent_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/deliveries.html")]
+struct DeliveryLogTemplate {
+    newsletter_issue_id: Option<String>,
+    subscriber_email: Option<String>,
+    deliveries: Vec<DeliveryRecord>,
+}
+
+pub async fn delivery_log(
+    query: web::Query<QueryParams>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // The search form submits empty strings for fields that were left blank
+    let QueryParams {
+        newsletter_issue_id,
+        subscriber_email,
+    } = query.into_inner();
+    let newsletter_issue_id = newsletter_issue_id.filter(|s| !s.trim().is_empty());
+    let subscriber_email = subscriber_email.filter(|s| !s.trim().is_empty());
+    let issue_id = newsletter_issue_id
+        .as_deref()
+        .map(|s| Uuid::parse_str(s.trim()))
+        .transpose()
+        .map_err(e400)?;
+
+    let deliveries = get_deliveries(&connection_pool, issue_id, subscriber_email.as_deref())
+        .await
+        .map_err(e500)?;
+
+    render_page(&DeliveryLogTemplate {
+        newsletter_issue_id,
+        subscriber_email,
+        deliveries,
+    })
+    .map_err(e500)
+}
+
+#[tracing::instrument(name = "Get newsletter deliveries", skip(connection_pool))]
+pub async fn get_deliveries(
+    connection_pool: &PgPool,
+    newsletter_issue_id: Option<Uuid>,
+    subscriber_email: Option<&str>,
+) -> Result<Vec<DeliveryRecord>, anyhow::Error> {
+    // A NULL filter matches every row, so both filters are optional
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT
+            d.newsletter_issue_id,
+            i.title,
+            d.subscriber_email,
+            d.provider_message_id,
+            d.outcome,
+            d.n_attempts,
+            d.sent_at
+        FROM newsletter_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE
+            ($1::uuid IS NULL OR d.newsletter_issue_id = $1) AND
+            ($2::text IS NULL OR d.subscriber_email = $2)
+        ORDER BY d.sent_at DESC
+        LIMIT 100
+        "#,
+        newsletter_issue_id,
+        subscriber_email,
+    )
+    .fetch_all(connection_pool)
+    .await
+    .context("Failed to retrieve newsletter deliveries.")?;
+
+    Ok(deliveries)
+}
@@ -0,0 +1,30 @@
+use crate::authentication::CsrfToken;
+use crate::mailing_lists::{get_lists, MailingList};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "admin/lists.html")]
+struct MailingListsTemplate {
+    flash_messages: IncomingFlashMessages,
+    lists: Vec<MailingList>,
+    csrf_token: CsrfToken,
+}
+
+pub async fn mailing_lists_form(
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = get_lists(&connection_pool).await.map_err(e500)?;
+
+    render_page(&MailingListsTemplate {
+        flash_messages,
+        lists,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::mailing_lists_form;
+mod post;
+pub use post::create_mailing_list;
@@ -0,0 +1,32 @@
+use crate::mailing_lists::insert_list;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(form, connection_pool))]
+pub async fn create_mailing_list(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The list name cannot be empty.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    match insert_list(&connection_pool, name).await {
+        Ok(_) => FlashMessage::info(format!("The list {} has been created.", name)).send(),
+        // The name column is unique
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("23505") => {
+            FlashMessage::error(format!("A list named {} already exists.", name)).send()
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    Ok(see_other("/admin/lists"))
+}
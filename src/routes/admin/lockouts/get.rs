@@ -0,0 +1,50 @@
+use crate::authentication::{get_login_lockouts, CsrfToken, LoginLockout};
+use crate::security_events::{get_recent_security_events, SecurityEvent};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use chrono::Utc;
+use sqlx::PgPool;
+
+const N_RECENT_SECURITY_EVENTS: i64 = 50;
+
+#[derive(Template)]
+#[template(path = "admin/lockouts.html")]
+struct LoginLockoutsTemplate {
+    flash_messages: IncomingFlashMessages,
+    // Each lockout with the end of its lock, if it is still running
+    lockouts: Vec<(LoginLockout, String)>,
+    events: Vec<SecurityEvent>,
+    csrf_token: CsrfToken,
+}
+
+pub async fn login_lockouts(
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lockouts = get_login_lockouts(&connection_pool)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|l| {
+            let locked_until = match l.locked_until {
+                Some(locked_until) if locked_until > Utc::now() => locked_until.to_rfc3339(),
+                _ => "-".into(),
+            };
+            (l, locked_until)
+        })
+        .collect();
+    let events = get_recent_security_events(&connection_pool, N_RECENT_SECURITY_EVENTS)
+        .await
+        .map_err(e500)?;
+
+    render_page(&LoginLockoutsTemplate {
+        flash_messages,
+        lockouts,
+        events,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::login_lockouts;
+mod post;
+pub use post::clear_lockout;
@@ -0,0 +1,49 @@
+use crate::authentication::{clear_failed_logins, UserId};
+use crate::routes::admin::dashboard::get_username;
+use crate::security_events::{record_security_event, LOCKOUT_CLEARED};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    user_id: Uuid,
+}
+
+#[tracing::instrument(name = "Clear a login lockout", skip(form, connection_pool))]
+pub async fn clear_lockout(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+    admin_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let admin_id = admin_id.into_inner();
+    let username = match clear_failed_logins(&connection_pool, form.user_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(username) => username,
+        None => {
+            FlashMessage::error("The admin does not exist.").send();
+            return Ok(see_other("/admin/lockouts"));
+        }
+    };
+
+    let admin_username = get_username(*admin_id, &connection_pool)
+        .await
+        .map_err(e500)?;
+    let details = format!("Cleared by {}", admin_username);
+    record_security_event(
+        &connection_pool,
+        LOCKOUT_CLEARED,
+        &username,
+        Some(form.user_id),
+        Some(&details),
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info(format!("{} can log in again.", username)).send();
+    Ok(see_other("/admin/lockouts"))
+}
@@ -1,9 +1,33 @@
+mod api_tokens;
 mod dashboard;
+mod deliveries;
+mod lists;
+mod lockouts;
 mod logout;
 mod newsletters;
 mod password;
+mod privacy;
+mod recovery_email;
+mod sessions;
+mod subscribers;
+mod suppressions;
+mod tags;
+mod two_factor;
+mod users;
 
-pub use dashboard::admin_dashboard;
+pub use api_tokens::*;
+pub use dashboard::{admin_dashboard, get_username};
+pub use deliveries::delivery_log;
+pub use lists::*;
+pub use lockouts::*;
 pub use logout::log_out;
 pub use newsletters::*;
 pub use password::*;
+pub use privacy::*;
+pub use recovery_email::*;
+pub use sessions::*;
+pub use subscribers::*;
+pub use suppressions::*;
+pub use tags::*;
+pub use two_factor::*;
+pub use users::*;
@@ -0,0 +1,72 @@
+use crate::domain::Segment;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Builds the `FROM ... WHERE ...` clause selecting the subscribers (aliased as `s`) an issue is
+/// delivered to: confirmed, unsuppressed and unpaused members of the list bound at `$first_param`
+/// who match the optional segment. The segment tags must be bound right after the list id.
+fn audience_clause(first_param: usize, segment: Option<&Segment>) -> (String, Vec<String>) {
+    let mut clause = format!(
+        r#"
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        WHERE
+            ls.list_id = ${first_param} AND
+            ls.status = 'confirmed' AND
+            s.status = 'confirmed' AND
+            (s.paused_until IS NULL OR s.paused_until <= now()) AND
+            NOT EXISTS (
+                SELECT 1 FROM suppressed_emails se
+                WHERE lower(se.email) = lower(s.email)
+            )"#
+    );
+    let mut tags = Vec::new();
+    if let Some(segment) = segment {
+        let (condition, segment_tags) = segment.to_sql(first_param + 1);
+        clause.push_str(" AND ");
+        clause.push_str(&condition);
+        tags = segment_tags;
+    }
+    (clause, tags)
+}
+
+#[tracing::instrument(skip(connection_pool, segment))]
+pub async fn count_recipients(
+    connection_pool: &PgPool,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+) -> Result<i64, sqlx::Error> {
+    let (clause, tags) = audience_clause(1, segment);
+    let sql = format!("SELECT COUNT(*) {}", clause);
+    let mut query = sqlx::query_scalar(&sql).bind(list_id);
+    for tag in tags {
+        query = query.bind(tag);
+    }
+    query.fetch_one(connection_pool).await
+}
+
+// The segment makes the query dynamic, so it cannot be checked at compile time with `query!`
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+) -> Result<(), sqlx::Error> {
+    let (clause, tags) = audience_clause(2, segment);
+    let sql = format!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT $1, s.email {}"#,
+        clause
+    );
+    let mut query = sqlx::query(&sql).bind(newsletter_issue_id).bind(list_id);
+    for tag in tags {
+        query = query.bind(tag);
+    }
+    query.execute(transaction).await?;
+    Ok(())
+}
@@ -1,60 +1,75 @@
-use crate::utils::e500;
-use actix_web::{http::header::ContentType, HttpResponse};
+use super::audience::count_recipients;
+use crate::authentication::CsrfToken;
+use crate::domain::Segment;
+use crate::mailing_lists::{get_lists, MailingList, DEFAULT_LIST_ID};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// Set by the audience form to preview how many subscribers an issue would reach
+#[derive(serde::Deserialize)]
+pub struct AudienceParams {
+    list_id: Option<Uuid>,
+    segment: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/newsletters.html")]
+struct NewsletterFormTemplate {
+    flash_messages: IncomingFlashMessages,
+    // The recipient count, or why the segment could not be parsed
+    audience_message: Option<String>,
+    list_options: Vec<(MailingList, bool)>,
+    segment: String,
+    idempotency_key: Uuid,
+    csrf_token: CsrfToken,
+}
 
 pub async fn submit_newsletter_to_send_form(
     flash_messages: IncomingFlashMessages,
+    audience: web::Query<AudienceParams>,
+    connection_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let idempotency_key = uuid::Uuid::new_v4();
-    let mut msg_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).map_err(e500)?;
+    let AudienceParams { list_id, segment } = audience.into_inner();
+    let segment = segment.filter(|s| !s.trim().is_empty());
+    let mut audience_message = None;
+    if list_id.is_some() || segment.is_some() {
+        let list_id = list_id.unwrap_or(DEFAULT_LIST_ID);
+        audience_message = Some(match segment.clone().map(Segment::parse).transpose() {
+            Ok(segment) => {
+                let n_recipients = count_recipients(&connection_pool, list_id, segment.as_ref())
+                    .await
+                    .map_err(e500)?;
+                format!(
+                    "This issue will be delivered to {} subscribers.",
+                    n_recipients
+                )
+            }
+            Err(e) => e,
+        });
     }
 
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-    <head>
-        <meta http-equiv="content-type" content="text/html; charset=utf-8">
-        <title>Send a Newsletter</title>
-    </head>
-    <body>
-        {msg_html}
-        <form action="/admin/newsletters" method="post">
-            <label>Title:<br>
-                <input
-                    type="text"
-                    placeholder="Enter newsletter title"
-                    name="title"
-                >
-            </label>
-            <br>
-            <label>HTML Content: <br>
-                <textarea
-                    placeholder="Enter content in HTML format"
-                    name="html_content"
-                    rows="20"
-                    cols="50"
-                ></textarea>
-            </label>
-            <br>
-            <label>Text Content: <br>
-                <textarea
-                    placeholder="Enter content in plain text"
-                    name="text_content"
-                    rows="20"
-                    cols="50"
-                ></textarea>
-            </label>
-            <br>
-            <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
-            <button type="submit">Send Newsletter</button>
-        </form>
-        <p><a href="/admin/dashboard">&lt;- Back</a></p>
-    </body>
-</html>"#,
-        )))
+    let list_options = get_lists(&connection_pool)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|list| {
+            let selected = Some(list.list_id) == list_id;
+            (list, selected)
+        })
+        .collect();
+
+    render_page(&NewsletterFormTemplate {
+        flash_messages,
+        audience_message,
+        list_options,
+        segment: segment.unwrap_or_default(),
+        idempotency_key: Uuid::new_v4(),
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
 }
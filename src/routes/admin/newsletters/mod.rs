@@ -1,5 +1,7 @@
+mod audience;
 mod get;
 mod post;
 
+pub use audience::enqueue_delivery_tasks;
 pub use get::*;
-pub use post::*;
+pub use post::{send_newsletter, PublishError};
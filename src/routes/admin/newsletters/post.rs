@@ -1,5 +1,8 @@
+use super::audience::enqueue_delivery_tasks;
 use crate::authentication::UserId;
+use crate::domain::Segment;
 use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::mailing_lists::{list_exists, DEFAULT_LIST_ID};
 use crate::routes::error_chain_fmt;
 use crate::utils::see_other;
 use crate::utils::{e400, e500};
@@ -17,6 +20,8 @@ pub struct FormData {
     html_content: String,
     text_content: String,
     idempotency_key: String,
+    list_id: Option<Uuid>,
+    segment: Option<String>,
 }
 
 #[tracing::instrument(
@@ -34,9 +39,30 @@ pub async fn send_newsletter(
         html_content,
         text_content,
         idempotency_key,
+        list_id,
+        segment,
     } = form.0;
     let user_id = user_id.into_inner();
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let list_id = list_id.unwrap_or(DEFAULT_LIST_ID);
+    if !list_exists(&connection_pool, list_id)
+        .await
+        .context("Failed to check the target mailing list")
+        .map_err(e500)?
+    {
+        return Err(e400(format!("{} is not a valid mailing list.", list_id)).into());
+    }
+    // A blank segment targets the whole list
+    let segment = match segment.filter(|s| !s.trim().is_empty()) {
+        None => None,
+        Some(s) => match Segment::parse(s) {
+            Ok(segment) => Some(segment),
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other("/admin/newsletters"));
+            }
+        },
+    };
 
     let mut transaction = match try_processing(&connection_pool, &idempotency_key, *user_id)
         .await
@@ -49,12 +75,19 @@ pub async fn send_newsletter(
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        list_id,
+        segment.as_ref(),
+        &title,
+        &text_content,
+        &html_content,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
 
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    enqueue_delivery_tasks(&mut transaction, issue_id, list_id, segment.as_ref())
         .await
         .context("Failed to enqueue delivery tasks")
         .map_err(e500)?;
@@ -70,6 +103,10 @@ pub async fn send_newsletter(
 
 #[derive(thiserror::Error)]
 pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The newsletter issue has already been published.")]
+    AlreadyPublished,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
     #[error(transparent)]
@@ -83,15 +120,20 @@ impl std::fmt::Debug for PublishError {
 }
 
 impl ResponseError for PublishError {
-    // Implement error_response instead of status_code to insert a custom header
-    fn error_response(&self) -> HttpResponse {
+    fn status_code(&self) -> StatusCode {
         match self {
-            PublishError::UnexpectedError(_) => {
-                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            PublishError::ValidationError(_) | PublishError::IdempotencyKeyError(_) => {
+                StatusCode::BAD_REQUEST
             }
-            PublishError::IdempotencyKeyError(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            PublishError::AlreadyPublished => StatusCode::CONFLICT,
+            PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    // Implement error_response as well, so that the error is not sent back in the body
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::new(self.status_code())
+    }
 }
 
 fn success_message() -> FlashMessage {
@@ -101,23 +143,31 @@ fn success_message() -> FlashMessage {
 #[tracing::instrument(skip_all)]
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    segment: Option<&Segment>,
     title: &str,
     text_content: &str,
     html_content: &str,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
+    // Keep the normalised expression around to know who an issue was aimed at
+    let segment = segment.map(|s| s.to_string());
     sqlx::query!(
         r#"
         INSERT INTO newsletter_issues (
             newsletter_issue_id,
+            list_id,
+            segment,
             title,
             text_content,
             html_content,
             published_at
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, $5, $6, now())
         "#,
         newsletter_issue_id,
+        list_id,
+        segment,
         title,
         text_content,
         html_content
@@ -126,25 +176,3 @@ async fn insert_newsletter_issue(
     .await?;
     Ok(newsletter_issue_id)
 }
-
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id,
-    )
-    .execute(transaction)
-    .await?;
-    Ok(())
-}
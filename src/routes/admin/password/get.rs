@@ -1,56 +1,23 @@
-use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use crate::authentication::CsrfToken;
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "admin/password.html")]
+struct ChangePasswordTemplate {
+    flash_messages: IncomingFlashMessages,
+    csrf_token: CsrfToken,
+}
 
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let mut msg_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
-
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Change Password</title>
-</head>
-<body>
-    {msg_html}
-    <form action="/admin/password" method="post">
-        <label>Current password
-            <input
-            type="password"
-            placeholder="Enter current password"
-            name="current_password"
-            >
-        </label>
-        <br>
-        <label>New password
-            <input
-            type="password"
-            placeholder="Enter new password"
-            name="new_password"
-            >
-        </label>
-        <br>
-        <label>Confirm new password
-            <input
-            type="password"
-            placeholder="Enter new password again"
-            name="new_password_check"
-            >
-        </label>
-        <br>
-        <button type="submit">Change password</button>
-    </form>
-    <p><a href="/admin/dashboard">&lt;- Back</a></p>
-</body>
-</html>"#,
-        )))
+    render_page(&ChangePasswordTemplate {
+        flash_messages,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
 }
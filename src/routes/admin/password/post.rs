@@ -1,10 +1,14 @@
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
 use secrecy::{ExposeSecret, Secret};
 
 use crate::routes::admin::dashboard::get_username;
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials, UserId},
+    authentication::{
+        revoke_all_api_tokens, revoke_other_sessions, verify_current_password, AuthError,
+        PasswordHashing, PasswordPolicy, PasswordPolicyError, SessionId, UserId,
+    },
     utils::{e500, see_other},
 };
 use sqlx::PgPool;
@@ -19,7 +23,10 @@ pub struct FormData {
 pub async fn change_password(
     form: web::Form<FormData>,
     connection_pool: web::Data<PgPool>,
+    password_hashing: web::Data<PasswordHashing>,
+    password_policy: web::Data<PasswordPolicy>,
     user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner(); // Consumes ReqData and returns the inner value
 
@@ -36,12 +43,15 @@ pub async fn change_password(
         .await
         .map_err(e500)?;
 
-    let credentials = Credentials {
-        username,
-        password: form.0.current_password.clone(),
-    };
-
-    if let Err(e) = validate_credentials(credentials, &connection_pool).await {
+    // A mistyped password should not lock the admin out of their own account
+    if let Err(e) = verify_current_password(
+        *user_id,
+        form.0.current_password.clone(),
+        &connection_pool,
+        &password_hashing,
+    )
+    .await
+    {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -51,19 +61,49 @@ pub async fn change_password(
         };
     }
 
-    let new_password_length = form.new_password.expose_secret().len();
-
-    if new_password_length <= 12 || new_password_length >= 256 {
-        FlashMessage::error(
-            "The new password must be longer than 12 characters and shorter than 128 characters.",
+    match password_policy
+        .check(
+            &connection_pool,
+            Some(*user_id),
+            &username,
+            &form.new_password,
         )
-        .send();
+        .await
+    {
+        Ok(()) => {}
+        Err(PasswordPolicyError::Violation(message)) => {
+            FlashMessage::error(message).send();
 
-        return Ok(see_other("/admin/password"));
+            return Ok(see_other("/admin/password"));
+        }
+        Err(e) => return Err(e500(e)),
     }
 
-    crate::authentication::change_password(*user_id, form.0.new_password, &connection_pool)
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    crate::authentication::change_password(
+        *user_id,
+        form.0.new_password,
+        &mut transaction,
+        &password_hashing,
+        password_policy.history_size(),
+    )
+    .await
+    .map_err(e500)?;
+    // Whoever knew the old password is logged out everywhere else, and loses the tokens they made
+    revoke_other_sessions(&mut transaction, *user_id, **session_id)
+        .await
+        .map_err(e500)?;
+    revoke_all_api_tokens(&mut transaction, *user_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
         .await
+        .context("Failed to commit SQL transaction to change a password")
         .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
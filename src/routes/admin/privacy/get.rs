@@ -0,0 +1,50 @@
+use crate::authentication::CsrfToken;
+use crate::personal_data::export_personal_data;
+use crate::suppression_list::SuppressionKey;
+use crate::utils::{e500, render_page};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "admin/privacy.html")]
+struct PrivacyTemplate {
+    flash_messages: IncomingFlashMessages,
+    csrf_token: CsrfToken,
+}
+
+pub async fn privacy_form(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    render_page(&PrivacyTemplate {
+        flash_messages,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    email: String,
+}
+
+#[tracing::instrument(name = "Export personal data for an admin", skip_all)]
+pub async fn export_personal_data_of(
+    query: web::Query<QueryParams>,
+    connection_pool: web::Data<PgPool>,
+    suppression_key: web::Data<SuppressionKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let personal_data =
+        export_personal_data(&connection_pool, &suppression_key, query.email.trim())
+            .await
+            .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("personal-data.json".into())],
+        })
+        .json(personal_data))
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::{export_personal_data_of, privacy_form};
+mod post;
+pub use post::erase_personal_data_of;
@@ -0,0 +1,30 @@
+use crate::personal_data::erase_personal_data;
+use crate::suppression_list::SuppressionKey;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[tracing::instrument(name = "Erase personal data for an admin", skip_all)]
+pub async fn erase_personal_data_of(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+    suppression_key: web::Data<SuppressionKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.email.trim();
+    let was_stored = erase_personal_data(&connection_pool, &suppression_key, email)
+        .await
+        .map_err(e500)?;
+
+    if was_stored {
+        FlashMessage::info(format!("The personal data of {} has been erased.", email)).send();
+    } else {
+        FlashMessage::error(format!("Nothing is stored about {}.", email)).send();
+    }
+    Ok(see_other("/admin/privacy"))
+}
@@ -0,0 +1,26 @@
+use crate::authentication::verify_recovery_email;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    token: String,
+}
+
+// Reached from the verification email, so it does not require a session
+#[tracing::instrument(name = "Confirm a recovery email", skip_all)]
+pub async fn confirm_recovery_email(
+    query: web::Query<QueryParams>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match verify_recovery_email(&connection_pool, &query.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(_) => FlashMessage::info("Your recovery email has been confirmed.").send(),
+        None => FlashMessage::error("The verification link is invalid or has expired.").send(),
+    }
+    Ok(see_other("/admin/recovery_email"))
+}
@@ -0,0 +1,32 @@
+use crate::authentication::{get_recovery_email, CsrfToken, UserId};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "admin/recovery_email.html")]
+struct RecoveryEmailTemplate {
+    flash_messages: IncomingFlashMessages,
+    recovery_email: Option<String>,
+    csrf_token: CsrfToken,
+}
+
+pub async fn recovery_email_form(
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let recovery_email = get_recovery_email(&connection_pool, *user_id.into_inner())
+        .await
+        .map_err(e500)?;
+
+    render_page(&RecoveryEmailTemplate {
+        flash_messages,
+        recovery_email,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
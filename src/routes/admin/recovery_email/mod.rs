@@ -0,0 +1,6 @@
+mod confirm;
+pub use confirm::confirm_recovery_email;
+mod get;
+pub use get::recovery_email_form;
+mod post;
+pub use post::change_recovery_email;
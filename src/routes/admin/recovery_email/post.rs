@@ -0,0 +1,133 @@
+use crate::authentication::{
+    remove_recovery_email, store_recovery_email_verification, verify_current_password, AuthError,
+    PasswordHashing, UserId,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::routes::get_username;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    current_password: Secret<String>,
+    email: String,
+}
+
+#[tracing::instrument(
+    name = "Change the recovery email",
+    skip(form, connection_pool, password_hashing, email_client, base_url)
+)]
+pub async fn change_recovery_email(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+    password_hashing: web::Data<PasswordHashing>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let FormData {
+        current_password,
+        email,
+    } = form.0;
+
+    // Whoever controls the recovery email can reset the password, so a hijacked session is not
+    // enough to change it
+    if let Err(e) = verify_current_password(
+        *user_id,
+        current_password,
+        &connection_pool,
+        &password_hashing,
+    )
+    .await
+    {
+        return match e {
+            AuthError::InvalidCredentials(_) => {
+                FlashMessage::error("The current password is incorrect.").send();
+                Ok(see_other("/admin/recovery_email"))
+            }
+            AuthError::UnexpectedError(_) => Err(e500(e)),
+        };
+    }
+
+    let email = email.trim().to_string();
+    if email.is_empty() {
+        remove_recovery_email(&connection_pool, *user_id)
+            .await
+            .map_err(e500)?;
+        FlashMessage::info("Your recovery email has been removed.").send();
+        return Ok(see_other("/admin/recovery_email"));
+    }
+
+    let username = get_username(*user_id, &connection_pool)
+        .await
+        .map_err(e500)?;
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/recovery_email"));
+        }
+    };
+    send_verification_email(
+        &connection_pool,
+        &email_client,
+        &base_url.0,
+        *user_id,
+        &username,
+        &email,
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info(format!(
+        "We sent a verification link to {}. It becomes your recovery email once you click it.",
+        email.as_ref()
+    ))
+    .send();
+    Ok(see_other("/admin/recovery_email"))
+}
+
+async fn send_verification_email(
+    connection_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    user_id: Uuid,
+    username: &str,
+    email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let token = store_recovery_email_verification(connection_pool, user_id, email.as_ref())
+        .await
+        .context("Failed to store a recovery email verification")?;
+    let verification_link = format!("{}/recovery_email/confirm?token={}", base_url, token);
+
+    let plain_body = format!(
+        "Someone asked to send the password reset links of {} to this address.\n\
+        Visit {} within the next hour to confirm.\n\n\
+        If it was not you, you can ignore this email.",
+        username, verification_link
+    );
+    let html_body = format!(
+        "Someone asked to send the password reset links of {} to this address. <br />\
+        Click <a href=\"{}\">here</a> within the next hour to confirm.\
+        <p>If it was not you, you can ignore this email.</p>",
+        htmlescape::encode_minimal(username),
+        verification_link
+    );
+    email_client
+        .send_email(
+            email,
+            "Confirm your recovery email",
+            &html_body,
+            &plain_body,
+        )
+        .await
+        .context("Failed to send the recovery email verification")?;
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use crate::authentication::{get_user_sessions, CsrfToken, SessionId, UserId, UserSession};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "admin/sessions.html")]
+struct SessionsTemplate {
+    flash_messages: IncomingFlashMessages,
+    sessions: Vec<UserSession>,
+    current_session_id: Uuid,
+    csrf_token: CsrfToken,
+}
+
+pub async fn admin_sessions(
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let sessions = get_user_sessions(&connection_pool, **user_id)
+        .await
+        .map_err(e500)?;
+
+    render_page(&SessionsTemplate {
+        flash_messages,
+        sessions,
+        current_session_id: **session_id,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
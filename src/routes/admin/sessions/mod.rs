@@ -0,0 +1,4 @@
+mod get;
+pub use get::admin_sessions;
+mod post;
+pub use post::revoke_admin_session;
@@ -0,0 +1,38 @@
+use crate::authentication::{revoke_session, SessionId, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    session_id: Uuid,
+}
+
+#[tracing::instrument(name = "Revoke an admin session", skip(form, connection_pool, session))]
+pub async fn revoke_admin_session(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Only sessions of the user themselves can be revoked
+    if !revoke_session(&connection_pool, **user_id, form.session_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("The session does not exist or has already ended.").send();
+        return Ok(see_other("/admin/sessions"));
+    }
+
+    if form.session_id == **session_id {
+        session.log_out();
+        FlashMessage::info("You have successfully logged out.").send();
+        return Ok(see_other("/login"));
+    }
+    FlashMessage::info("The session has been revoked.").send();
+    Ok(see_other("/admin/sessions"))
+}
@@ -0,0 +1,159 @@
+use super::filters::{get_subscribers, FilterParams, Filters, SubscriberRecord};
+use crate::utils::e400;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Subscribers are read and written out one batch at a time, so the export never
+// holds more than a batch in memory
+const BATCH_SIZE: i64 = 1000;
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportParams {
+    format: ExportFormat,
+    #[serde(flatten)]
+    filters: FilterParams,
+}
+
+#[derive(serde::Serialize)]
+struct ExportRecord<'a> {
+    id: Uuid,
+    email: &'a str,
+    name: &'a str,
+    status: &'a str,
+    subscribed_at: String,
+}
+
+impl<'a> From<&'a SubscriberRecord> for ExportRecord<'a> {
+    fn from(s: &'a SubscriberRecord) -> Self {
+        Self {
+            id: s.id,
+            email: &s.email,
+            name: &s.name,
+            status: &s.status,
+            subscribed_at: s.subscribed_at.to_rfc3339(),
+        }
+    }
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    fn file_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "subscribers.csv",
+            ExportFormat::Jsonl => "subscribers.jsonl",
+        }
+    }
+
+    fn write_batch(
+        &self,
+        batch: &[SubscriberRecord],
+        with_header: bool,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        match self {
+            ExportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(with_header)
+                    .from_writer(Vec::new());
+                if batch.is_empty() && with_header {
+                    // `serialize` only writes the header along with the first record
+                    writer.write_record(["id", "email", "name", "status", "subscribed_at"])?;
+                }
+                for s in batch {
+                    writer.serialize(ExportRecord::from(s))?;
+                }
+                Ok(writer.into_inner()?)
+            }
+            ExportFormat::Jsonl => {
+                let mut lines = Vec::new();
+                for s in batch {
+                    serde_json::to_writer(&mut lines, &ExportRecord::from(s))?;
+                    lines.push(b'\n');
+                }
+                Ok(lines)
+            }
+        }
+    }
+}
+
+struct ExportState {
+    connection_pool: Arc<PgPool>,
+    filters: Filters,
+    format: ExportFormat,
+    cursor: Option<(DateTime<Utc>, Uuid)>,
+    is_first_batch: bool,
+    is_done: bool,
+}
+
+#[tracing::instrument(name = "Export subscribers", skip(query, connection_pool))]
+pub async fn export_subscribers(
+    query: web::Query<ExportParams>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ExportParams { format, filters } = query.into_inner();
+    let filters = filters.normalized().parse().map_err(e400)?;
+
+    let state = ExportState {
+        connection_pool: connection_pool.into_inner(),
+        filters,
+        format,
+        cursor: None,
+        is_first_batch: true,
+        is_done: false,
+    };
+    let body = stream::unfold(state, |mut state| async move {
+        if state.is_done {
+            return None;
+        }
+        let batch = match get_subscribers(
+            &state.connection_pool,
+            &state.filters,
+            state.cursor,
+            BATCH_SIZE,
+        )
+        .await
+        {
+            Ok(batch) => batch,
+            Err(e) => {
+                // The response has already started: all we can do is cut it short
+                tracing::error!(error.cause_chain = ?e, "Failed to fetch subscribers to export");
+                state.is_done = true;
+                return Some((Err(anyhow::Error::new(e)), state));
+            }
+        };
+        state.is_done = (batch.len() as i64) < BATCH_SIZE;
+        state.cursor = batch.last().map(|s| (s.subscribed_at, s.id));
+        let chunk = state
+            .format
+            .write_batch(&batch, state.is_first_batch)
+            .map(Bytes::from);
+        state.is_first_batch = false;
+        Some((chunk, state))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format.file_name().into())],
+        })
+        .streaming(body))
+}
@@ -0,0 +1,150 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The subscriber filters shared by the browser and the export, as submitted by the search form.
+#[derive(serde::Deserialize)]
+pub struct FilterParams {
+    pub(super) search: Option<String>,
+    pub(super) status: Option<String>,
+    pub(super) subscribed_from: Option<String>,
+    pub(super) subscribed_to: Option<String>,
+    pub(super) list_id: Option<String>,
+    pub(super) tag: Option<String>,
+}
+
+pub struct Filters {
+    search: Option<String>,
+    status: Option<String>,
+    subscribed_from: Option<DateTime<Utc>>,
+    // Exclusive: the day after the last day of the range
+    subscribed_until: Option<DateTime<Utc>>,
+    list_id: Option<Uuid>,
+    tag: Option<String>,
+}
+
+impl FilterParams {
+    /// Drops the fields the search form submitted as empty strings.
+    pub fn normalized(self) -> Self {
+        let present = |s: Option<String>| s.filter(|s| !s.trim().is_empty());
+        Self {
+            search: present(self.search),
+            status: present(self.status),
+            subscribed_from: present(self.subscribed_from),
+            subscribed_to: present(self.subscribed_to),
+            list_id: present(self.list_id),
+            tag: present(self.tag),
+        }
+    }
+
+    pub fn parse(&self) -> Result<Filters, String> {
+        let parse_day = |day: &Option<String>| {
+            day.as_deref()
+                .map(|day| {
+                    NaiveDate::parse_from_str(day.trim(), "%Y-%m-%d")
+                        .map_err(|_| format!("{} is not a valid date.", day))
+                })
+                .transpose()
+        };
+        let list_id = self
+            .list_id
+            .as_deref()
+            .map(|list_id| {
+                Uuid::parse_str(list_id.trim())
+                    .map_err(|_| format!("{} is not a valid list.", list_id))
+            })
+            .transpose()?;
+        Ok(Filters {
+            search: self.search.clone(),
+            status: self.status.clone(),
+            subscribed_from: parse_day(&self.subscribed_from)?.map(start_of_day),
+            subscribed_until: parse_day(&self.subscribed_to)?
+                .map(|day| start_of_day(day + Duration::days(1))),
+            list_id,
+            tag: self.tag.as_ref().map(|tag| tag.trim().to_string()),
+        })
+    }
+
+    /// The filters that are set, to carry them over into links.
+    pub fn query_pairs(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("search", &self.search),
+            ("status", &self.status),
+            ("subscribed_from", &self.subscribed_from),
+            ("subscribed_to", &self.subscribed_to),
+            ("list_id", &self.list_id),
+            ("tag", &self.tag),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.as_deref().map(|value| (key, value)))
+        .collect()
+    }
+}
+
+fn start_of_day(day: NaiveDate) -> DateTime<Utc> {
+    DateTime::from_utc(day.and_hms(0, 0, 0), Utc)
+}
+
+// Escape LIKE wildcards so that the search term is matched literally
+fn like_pattern(search: &str) -> String {
+    let escaped = search
+        .trim()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+/// Returns up to `limit` subscribers matching the filters, newest first,
+/// starting after the `(subscribed_at, id)` keyset cursor.
+#[tracing::instrument(name = "Get a page of subscribers", skip(connection_pool, filters))]
+pub async fn get_subscribers(
+    connection_pool: &PgPool,
+    filters: &Filters,
+    cursor: Option<(DateTime<Utc>, Uuid)>,
+    limit: i64,
+) -> Result<Vec<SubscriberRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT s.id, s.email, s.name, s.status, s.subscribed_at
+        FROM subscriptions s
+        WHERE
+            ($1::text IS NULL OR s.email ILIKE $1 OR s.name ILIKE $1) AND
+            ($2::text IS NULL OR s.status = $2) AND
+            ($3::timestamptz IS NULL OR s.subscribed_at >= $3) AND
+            ($4::timestamptz IS NULL OR s.subscribed_at < $4) AND
+            ($5::uuid IS NULL OR EXISTS (
+                SELECT 1 FROM list_subscriptions ls
+                WHERE ls.subscriber_id = s.id AND ls.list_id = $5
+            )) AND
+            ($6::text IS NULL OR EXISTS (
+                SELECT 1 FROM subscriber_tags t
+                WHERE t.subscriber_id = s.id AND t.tag = $6
+            )) AND
+            ($7::timestamptz IS NULL OR (s.subscribed_at, s.id) < ($7, $8))
+        ORDER BY s.subscribed_at DESC, s.id DESC
+        LIMIT $9
+        "#,
+        filters.search.as_deref().map(like_pattern),
+        filters.status,
+        filters.subscribed_from,
+        filters.subscribed_until,
+        filters.list_id,
+        filters.tag,
+        cursor.map(|(subscribed_at, _)| subscribed_at),
+        cursor.map(|(_, id)| id),
+        limit
+    )
+    .fetch_all(connection_pool)
+    .await
+}
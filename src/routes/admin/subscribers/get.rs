@@ -0,0 +1,135 @@
+use super::filters::{get_subscribers, FilterParams, SubscriberRecord};
+use crate::authentication::CsrfToken;
+use crate::mailing_lists::{get_lists, MailingList};
+use crate::utils::{e400, e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    #[serde(flatten)]
+    filters: FilterParams,
+    // Keyset cursor: the last subscriber of the previous page
+    after_subscribed_at: Option<String>,
+    after_id: Option<Uuid>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscribers.html")]
+struct SubscribersTemplate {
+    flash_messages: IncomingFlashMessages,
+    subscribers: Vec<SubscriberRecord>,
+    actions: [(&'static str, &'static str); 4],
+    next_page_query: Option<String>,
+    // Each option with whether it is the one the filters use
+    status_options: Vec<(&'static str, bool)>,
+    list_options: Vec<(MailingList, bool)>,
+    export_query: String,
+    params: FilterParams,
+    csrf_token: CsrfToken,
+}
+
+pub async fn browse_subscribers(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_token = csrf_token.into_inner();
+    let QueryParams {
+        filters: params,
+        after_subscribed_at,
+        after_id,
+    } = query.into_inner();
+    let params = params.normalized();
+    let filters = params.parse().map_err(e400)?;
+    let cursor = match (after_subscribed_at, after_id) {
+        (Some(subscribed_at), Some(id)) => {
+            let subscribed_at = DateTime::parse_from_rfc3339(&subscribed_at).map_err(e400)?;
+            Some((subscribed_at.with_timezone(&Utc), id))
+        }
+        _ => None,
+    };
+
+    let mut subscribers = get_subscribers(&connection_pool, &filters, cursor, PAGE_SIZE + 1)
+        .await
+        .map_err(e500)?;
+    // One extra row is fetched to know whether there is a next page
+    let has_next_page = subscribers.len() as i64 > PAGE_SIZE;
+    subscribers.truncate(PAGE_SIZE as usize);
+
+    let next_page_query = match (has_next_page, subscribers.last()) {
+        (true, Some(last)) => {
+            let mut next_page_query = vec![
+                ("after_subscribed_at", last.subscribed_at.to_rfc3339()),
+                ("after_id", last.id.to_string()),
+            ];
+            for (key, value) in params.query_pairs() {
+                next_page_query.push((key, value.to_string()));
+            }
+            Some(
+                next_page_query
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+                    .collect::<Vec<_>>()
+                    .join("&"),
+            )
+        }
+        _ => None,
+    };
+
+    let status_options = [
+        "",
+        "pending_confirmation",
+        "confirmed",
+        "unsubscribed",
+        "bounced",
+        "complained",
+    ]
+    .into_iter()
+    .map(|status| {
+        (
+            status,
+            params.status.as_deref().unwrap_or_default() == status,
+        )
+    })
+    .collect();
+    let list_options = get_lists(&connection_pool)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|list| {
+            let selected = params.list_id.as_deref() == Some(list.list_id.to_string().as_str());
+            (list, selected)
+        })
+        .collect();
+    let export_query = params
+        .query_pairs()
+        .iter()
+        .map(|(key, value)| format!("&{}={}", key, urlencoding::encode(value)))
+        .collect();
+
+    render_page(&SubscribersTemplate {
+        flash_messages,
+        subscribers,
+        actions: [
+            ("confirm", "Confirm"),
+            ("unsubscribe", "Unsubscribe"),
+            ("delete", "Delete"),
+            ("resend_confirmation", "Resend confirmation"),
+        ],
+        next_page_query,
+        status_options,
+        list_options,
+        export_query,
+        params,
+        csrf_token,
+    })
+    .map_err(e500)
+}
@@ -0,0 +1,387 @@
+use crate::authentication::CsrfToken;
+use crate::confirmation_email_queue::enqueue_confirmation_email;
+use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::mailing_lists::{get_lists, list_exists, MailingList, DEFAULT_LIST_ID};
+use crate::routes::{generate_subscription_token, store_token};
+use crate::suppression_list::{is_suppressed_or_erased, SuppressionKey};
+use crate::utils::{e400, e500, render_page};
+use actix_multipart::{Field, Multipart};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use csv_core::ReadRecordResult;
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// A record longer than this is not a subscriber, and buffering it would let an upload exhaust
+// memory
+const MAX_RECORD_LENGTH: usize = 4096;
+const MAX_COLUMNS: usize = 64;
+
+#[derive(Template)]
+#[template(path = "admin/import.html")]
+struct ImportFormTemplate {
+    lists: Vec<MailingList>,
+    csrf_token: CsrfToken,
+}
+
+pub async fn import_subscribers_form(
+    connection_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = get_lists(&connection_pool).await.map_err(e500)?;
+    render_page(&ImportFormTemplate {
+        lists,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ImportStatus {
+    Confirmed,
+    PendingConfirmation,
+}
+
+impl ImportStatus {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "confirmed" => Ok(Self::Confirmed),
+            "pending_confirmation" => Ok(Self::PendingConfirmation),
+            other => Err(format!("{} is not a valid import status.", other)),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Confirmed => "confirmed",
+            Self::PendingConfirmation => "pending_confirmation",
+        }
+    }
+}
+
+enum RowOutcome {
+    Imported,
+    Duplicate,
+    Suppressed,
+    Invalid(String),
+}
+
+#[derive(Default, Template)]
+#[template(path = "admin/import_report.html")]
+struct ImportReport {
+    n_imported: usize,
+    n_duplicates: usize,
+    n_suppressed: usize,
+    n_invalid: usize,
+    // Line number, email and reason of every row that was not imported
+    skipped_rows: Vec<(usize, String, String)>,
+}
+
+impl ImportReport {
+    fn record(&mut self, line: usize, email: &str, outcome: RowOutcome) {
+        let reason = match outcome {
+            RowOutcome::Imported => {
+                self.n_imported += 1;
+                return;
+            }
+            RowOutcome::Duplicate => {
+                self.n_duplicates += 1;
+                "Already subscribed".to_string()
+            }
+            RowOutcome::Suppressed => {
+                self.n_suppressed += 1;
+                "Suppressed".to_string()
+            }
+            RowOutcome::Invalid(e) => {
+                self.n_invalid += 1;
+                e
+            }
+        };
+        self.skipped_rows.push((line, email.to_string(), reason));
+    }
+}
+
+/// Imports the uploaded CSV row by row as it streams in. The `status` and `list_id` fields
+/// must come before the `file` field, which is the order the import form sends them in.
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(payload, connection_pool, suppression_key)
+)]
+pub async fn import_subscribers(
+    mut payload: Multipart,
+    connection_pool: web::Data<PgPool>,
+    suppression_key: web::Data<SuppressionKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut status = None;
+    let mut list_id = None;
+    let mut report = None;
+    while let Some(mut field) = payload.try_next().await.map_err(e400)? {
+        let name = field.content_disposition().get_name().map(str::to_owned);
+        match name.as_deref() {
+            Some("status") => {
+                let value = read_text_field(&mut field).await?;
+                status = Some(ImportStatus::parse(value.trim()).map_err(e400)?);
+            }
+            Some("list_id") => {
+                let value = read_text_field(&mut field).await?;
+                list_id = Some(Uuid::parse_str(value.trim()).map_err(e400)?);
+            }
+            Some("file") => {
+                let status = status.ok_or_else(|| e400("The status must precede the file."))?;
+                let list_id = list_id.unwrap_or(DEFAULT_LIST_ID);
+                if !list_exists(&connection_pool, list_id).await.map_err(e500)? {
+                    return Err(e400(format!("{} is not a valid mailing list.", list_id)));
+                }
+                report = Some(
+                    import_csv(
+                        &mut field,
+                        &connection_pool,
+                        &suppression_key,
+                        status,
+                        list_id,
+                    )
+                    .await?,
+                );
+            }
+            // Skip fields we do not know about
+            _ => while field.try_next().await.map_err(e400)?.is_some() {},
+        }
+    }
+    let report = report.ok_or_else(|| e400("No CSV file was uploaded."))?;
+
+    render_page(&report).map_err(e500)
+}
+
+async fn read_text_field(field: &mut Field) -> Result<String, actix_web::Error> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.try_next().await.map_err(e400)? {
+        if value.len() + chunk.len() > MAX_RECORD_LENGTH {
+            return Err(e400("A form field is too long."));
+        }
+        value.extend_from_slice(&chunk);
+    }
+    String::from_utf8(value).map_err(e400)
+}
+
+async fn import_csv(
+    field: &mut Field,
+    connection_pool: &PgPool,
+    suppression_key: &SuppressionKey,
+    status: ImportStatus,
+    list_id: Uuid,
+) -> Result<ImportReport, actix_web::Error> {
+    let mut report = ImportReport::default();
+    let mut columns = None;
+    // The parser is fed the upload as it streams in, since a record can span several chunks
+    // and a quoted field several lines
+    let mut reader = csv_core::Reader::new();
+    let mut output = [0; MAX_RECORD_LENGTH];
+    let mut ends = [0; MAX_COLUMNS];
+    let (mut n_output, mut n_ends) = (0, 0);
+    let mut chunk = web::Bytes::new();
+    let mut end_of_file = false;
+    loop {
+        if chunk.is_empty() && !end_of_file {
+            match field.try_next().await.map_err(e400)? {
+                Some(next) => chunk = next,
+                // Reading an empty input tells the parser the upload is over
+                None => end_of_file = true,
+            }
+        }
+        let (result, n_in, n_out, n_end) =
+            reader.read_record(&chunk, &mut output[n_output..], &mut ends[n_ends..]);
+        let ends_with_newline = n_in > 0 && chunk[n_in - 1] == b'\n';
+        chunk = chunk.slice(n_in..);
+        n_output += n_out;
+        n_ends += n_end;
+        match result {
+            ReadRecordResult::InputEmpty => continue,
+            ReadRecordResult::End => break,
+            ReadRecordResult::OutputFull => {
+                return Err(e400(format!("Line {} is too long.", reader.line())));
+            }
+            ReadRecordResult::OutputEndsFull => {
+                return Err(e400(format!(
+                    "Line {} has too many columns.",
+                    reader.line()
+                )));
+            }
+            ReadRecordResult::Record => (),
+        }
+
+        let record = &output[..n_output];
+        // Report the line the record starts on
+        let n_lines = record.iter().filter(|b| **b == b'\n').count() as u64;
+        let line_number = (reader.line() - u64::from(ends_with_newline) - n_lines) as usize;
+        let record = parse_record(record, &ends[..n_ends]);
+        n_output = 0;
+        n_ends = 0;
+        let record = match record {
+            Ok(Some(record)) => record,
+            // Blank lines are skipped
+            Ok(None) => continue,
+            Err(e) => {
+                report.record(line_number, "", RowOutcome::Invalid(e));
+                continue;
+            }
+        };
+        let (email_column, name_column) = match columns {
+            Some(columns) => columns,
+            None => {
+                columns = Some(header_columns(&record).map_err(e400)?);
+                continue;
+            }
+        };
+        let email = record.get(email_column).unwrap_or_default();
+        let name = record.get(name_column).unwrap_or_default();
+        let outcome = import_row(
+            connection_pool,
+            suppression_key,
+            status,
+            list_id,
+            email,
+            name,
+        )
+        .await
+        .map_err(e500)?;
+        report.record(line_number, email, outcome);
+    }
+    Ok(report)
+}
+
+/// Builds a record out of the fields the parser wrote to `output`, which end at `ends`.
+fn parse_record(output: &[u8], ends: &[usize]) -> Result<Option<csv::StringRecord>, String> {
+    let mut record = csv::StringRecord::new();
+    let mut start = 0;
+    for end in ends {
+        let field = std::str::from_utf8(&output[start..*end])
+            .map_err(|e| format!("The row is not valid UTF-8: {}", e))?;
+        record.push_field(field);
+        start = *end;
+    }
+    if record.iter().all(|field| field.trim().is_empty()) {
+        return Ok(None);
+    }
+    Ok(Some(record))
+}
+
+/// Returns the positions of the `email` and `name` columns.
+fn header_columns(header: &csv::StringRecord) -> Result<(usize, usize), String> {
+    let position = |column: &str| {
+        header
+            .iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(column))
+    };
+    match (position("email"), position("name")) {
+        (Some(email), Some(name)) => Ok((email, name)),
+        _ => Err("The CSV header must have email and name columns.".into()),
+    }
+}
+
+#[tracing::instrument(
+    name = "Import a subscriber",
+    skip(connection_pool, suppression_key, status, name)
+)]
+async fn import_row(
+    connection_pool: &PgPool,
+    suppression_key: &SuppressionKey,
+    status: ImportStatus,
+    list_id: Uuid,
+    email: &str,
+    name: &str,
+) -> Result<RowOutcome, anyhow::Error> {
+    let email = match SubscriberEmail::parse(email.trim().to_string()) {
+        Ok(email) => email,
+        Err(e) => return Ok(RowOutcome::Invalid(e)),
+    };
+    let name = match SubscriberName::parse(name.trim().to_string()) {
+        Ok(name) => name,
+        Err(e) => return Ok(RowOutcome::Invalid(e)),
+    };
+    if is_suppressed_or_erased(connection_pool, suppression_key, email.as_ref())
+        .await
+        .context("Failed to check email against the suppression list")?
+    {
+        return Ok(RowOutcome::Suppressed);
+    }
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // The details of existing subscribers are left untouched, whatever the case of their
+    // address, but they are still added to the list
+    let existing_id = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to look up the imported subscriber")?
+    .map(|r| r.id);
+    let subscriber_id = match existing_id {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            let subscriber_id = sqlx::query!(
+                r#"
+                INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+                VALUES ($1, $2, $3, now(), $4)
+                ON CONFLICT DO NOTHING
+                RETURNING id
+                "#,
+                Uuid::new_v4(),
+                email.as_ref(),
+                name.as_ref(),
+                status.as_str()
+            )
+            .fetch_optional(&mut transaction)
+            .await
+            .context("Failed to insert the imported subscriber")?;
+            match subscriber_id {
+                Some(r) => r.id,
+                // Subscribed while the upload was being processed
+                None => return Ok(RowOutcome::Duplicate),
+            }
+        }
+    };
+
+    let n_added = sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (subscriber_id, list_id, status, subscribed_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        list_id,
+        status.as_str()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to add the imported subscriber to the mailing list")?
+    .rows_affected();
+    if n_added == 0 {
+        return Ok(RowOutcome::Duplicate);
+    }
+
+    if status == ImportStatus::PendingConfirmation {
+        let subscription_token = generate_subscription_token();
+        store_token(
+            &mut transaction,
+            subscriber_id,
+            list_id,
+            &subscription_token,
+        )
+        .await
+        .context("Failed to store subscription token in database")?;
+        enqueue_confirmation_email(&mut transaction, &subscription_token)
+            .await
+            .context("Failed to queue the confirmation email")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import a subscriber")?;
+    Ok(RowOutcome::Imported)
+}
@@ -0,0 +1,11 @@
+mod export;
+mod filters;
+mod get;
+mod import;
+mod post;
+
+pub use export::export_subscribers;
+pub use filters::{get_subscribers, FilterParams, SubscriberRecord};
+pub use get::browse_subscribers;
+pub use import::{import_subscribers, import_subscribers_form};
+pub use post::{delete_subscriber, update_subscriber};
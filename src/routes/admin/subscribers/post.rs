@@ -0,0 +1,245 @@
+use crate::confirmation_email_queue::dequeue_confirmation_emails;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::manage_links::ManageLinks;
+use crate::routes::{get_or_store_token, send_confirmation_email};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppression_list::suppress_email;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriberAction {
+    Confirm,
+    Unsubscribe,
+    Delete,
+    ResendConfirmation,
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    subscriber_id: Uuid,
+    action: SubscriberAction,
+}
+
+#[tracing::instrument(
+    name = "Apply an admin action to a subscriber",
+    skip(form, connection_pool, email_client, base_url, manage_links),
+    fields(subscriber_id = %form.subscriber_id, action = ?form.action)
+)]
+pub async fn update_subscriber(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    manage_links: web::Data<ManageLinks>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData {
+        subscriber_id,
+        action,
+    } = form.0;
+    let subscriber = match get_subscriber(&connection_pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => subscriber,
+        None => {
+            FlashMessage::error("The subscriber does not exist.").send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+
+    let message = match action {
+        SubscriberAction::Confirm => {
+            set_status(&connection_pool, subscriber_id, "confirmed")
+                .await
+                .map_err(e500)?;
+            format!("{} has been confirmed.", subscriber.email)
+        }
+        SubscriberAction::Unsubscribe => {
+            set_status(&connection_pool, subscriber_id, "unsubscribed")
+                .await
+                .map_err(e500)?;
+            format!("{} has been unsubscribed.", subscriber.email)
+        }
+        SubscriberAction::Delete => {
+            delete_subscriber(&connection_pool, subscriber_id)
+                .await
+                .map_err(e500)?;
+            format!("{} has been deleted.", subscriber.email)
+        }
+        SubscriberAction::ResendConfirmation => {
+            if subscriber.status != "pending_confirmation" {
+                FlashMessage::error(format!(
+                    "{} is not waiting for confirmation.",
+                    subscriber.email
+                ))
+                .send();
+                return Ok(see_other("/admin/subscribers"));
+            }
+            resend_confirmation(
+                &connection_pool,
+                &email_client,
+                &base_url.0,
+                &manage_links,
+                subscriber.clone(),
+            )
+            .await
+            .map_err(e500)?;
+            format!(
+                "A confirmation email has been sent to {}.",
+                subscriber.email
+            )
+        }
+    };
+    FlashMessage::info(message).send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+#[derive(Clone)]
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(connection_pool))]
+async fn get_subscriber(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"SELECT id, email, name, status FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(connection_pool)
+    .await
+}
+
+/// Sets the status of the subscriber and of all their list memberships. Unsubscribed addresses
+/// are suppressed, so that they are not subscribed or imported again.
+#[tracing::instrument(name = "Set subscriber status", skip(connection_pool))]
+async fn set_status(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = connection_pool.begin().await?;
+    let subscriber = sqlx::query!(
+        r#"UPDATE subscriptions SET status = $1 WHERE id = $2 RETURNING email"#,
+        status,
+        subscriber_id
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    if status == "unsubscribed" {
+        suppress_email(&mut transaction, &subscriber.email, "unsubscribed").await?;
+    }
+    sqlx::query!(
+        r#"UPDATE list_subscriptions SET status = $1 WHERE subscriber_id = $2"#,
+        status,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await
+}
+
+/// Deletes the subscriber and suppresses their address, so that it is not mailed again.
+#[tracing::instrument(name = "Delete subscriber", skip(connection_pool))]
+pub async fn delete_subscriber(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = connection_pool.begin().await?;
+    // Rows referencing the subscriber have to go first
+    dequeue_confirmation_emails(&mut transaction, subscriber_id).await?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM list_subscriptions WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    let subscriber = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1 RETURNING email"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    if let Some(subscriber) = subscriber {
+        suppress_email(&mut transaction, &subscriber.email, "deleted").await?;
+    }
+    transaction.commit().await
+}
+
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(connection_pool, email_client, base_url, manage_links, subscriber),
+    fields(subscriber_id = %subscriber.id)
+)]
+async fn resend_confirmation(
+    connection_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    manage_links: &ManageLinks,
+    subscriber: Subscriber,
+) -> Result<(), anyhow::Error> {
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(subscriber.email).map_err(anyhow::Error::msg)?,
+        name: SubscriberName::parse(subscriber.name).map_err(anyhow::Error::msg)?,
+    };
+    // Reuse the list of the most recent pending confirmation
+    let list_id = sqlx::query!(
+        r#"
+        SELECT list_id FROM list_subscriptions
+        WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+        ORDER BY subscribed_at DESC
+        LIMIT 1
+        "#,
+        subscriber.id
+    )
+    .fetch_optional(connection_pool)
+    .await
+    .context("Failed to fetch the pending list subscription")?
+    .map(|r| r.list_id)
+    .context("The subscriber has no pending list subscription")?;
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscription_token = get_or_store_token(&mut transaction, subscriber.id, list_id).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a subscription token")?;
+
+    send_confirmation_email(
+        connection_pool,
+        email_client,
+        new_subscriber,
+        base_url,
+        &subscription_token,
+        &manage_links.link(subscriber.id),
+    )
+    .await
+}
@@ -0,0 +1,32 @@
+use crate::authentication::CsrfToken;
+use crate::suppression_list::{get_suppressed_emails, SuppressedEmail};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "admin/suppressions.html")]
+struct SuppressionListTemplate {
+    flash_messages: IncomingFlashMessages,
+    suppressed_emails: Vec<SuppressedEmail>,
+    csrf_token: CsrfToken,
+}
+
+pub async fn suppression_list(
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let suppressed_emails = get_suppressed_emails(&connection_pool)
+        .await
+        .map_err(e500)?;
+
+    render_page(&SuppressionListTemplate {
+        flash_messages,
+        suppressed_emails,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::suppression_list;
+mod post;
+pub use post::remove_suppression;
@@ -0,0 +1,27 @@
+use crate::suppression_list::unsuppress_email;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[tracing::instrument(name = "Remove a suppressed email", skip(form, connection_pool))]
+pub async fn remove_suppression(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_deleted_rows = unsuppress_email(&connection_pool, &form.email)
+        .await
+        .map_err(e500)?;
+
+    if n_deleted_rows == 0 {
+        FlashMessage::error(format!("{} is not on the suppression list.", form.email)).send();
+    } else {
+        FlashMessage::info(format!("{} can be mailed again.", form.email)).send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
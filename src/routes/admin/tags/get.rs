@@ -0,0 +1,44 @@
+use crate::authentication::CsrfToken;
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "admin/tags.html")]
+struct SubscriberTagsTemplate {
+    flash_messages: IncomingFlashMessages,
+    tags: Vec<(String, i64)>,
+    csrf_token: CsrfToken,
+}
+
+pub async fn subscriber_tags_form(
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let tags = get_tag_counts(&connection_pool).await.map_err(e500)?;
+
+    render_page(&SubscriberTagsTemplate {
+        flash_messages,
+        tags,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
+
+#[tracing::instrument(name = "Count subscribers per tag", skip(connection_pool))]
+async fn get_tag_counts(connection_pool: &PgPool) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT tag, COUNT(*) AS "n_subscribers!"
+        FROM subscriber_tags
+        GROUP BY tag
+        ORDER BY tag
+        "#
+    )
+    .fetch_all(connection_pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.tag, r.n_subscribers)).collect())
+}
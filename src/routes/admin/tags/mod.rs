@@ -0,0 +1,4 @@
+mod get;
+pub use get::subscriber_tags_form;
+mod post;
+pub use post::update_subscriber_tag;
@@ -0,0 +1,116 @@
+use crate::domain::SubscriberTag;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TagAction {
+    Add,
+    Remove,
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    tag: String,
+    action: TagAction,
+}
+
+#[tracing::instrument(
+    name = "Update a subscriber tag",
+    skip(form, connection_pool),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn update_subscriber_tag(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { email, tag, action } = form.0;
+    let tag = match SubscriberTag::parse(tag) {
+        Ok(tag) => tag,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/tags"));
+        }
+    };
+
+    let subscriber_id = match get_subscriber_id(&connection_pool, &email)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            FlashMessage::error(format!("There is no subscriber with the email {}.", email)).send();
+            return Ok(see_other("/admin/tags"));
+        }
+    };
+
+    match action {
+        TagAction::Add => {
+            add_tag(&connection_pool, subscriber_id, &tag)
+                .await
+                .map_err(e500)?;
+            FlashMessage::info(format!("{} has been tagged {}.", email, tag.as_ref())).send();
+        }
+        TagAction::Remove => {
+            remove_tag(&connection_pool, subscriber_id, &tag)
+                .await
+                .map_err(e500)?;
+            FlashMessage::info(format!("{} is no longer tagged {}.", email, tag.as_ref())).send();
+        }
+    }
+    Ok(see_other("/admin/tags"))
+}
+
+#[tracing::instrument(name = "Get subscriber id from email", skip(connection_pool))]
+async fn get_subscriber_id(
+    connection_pool: &PgPool,
+    email: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email.trim()
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.map(|r| r.id))
+}
+
+#[tracing::instrument(name = "Tag a subscriber", skip(connection_pool))]
+async fn add_tag(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+    tag: &SubscriberTag,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag, tagged_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        tag.as_ref()
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Untag a subscriber", skip(connection_pool))]
+async fn remove_tag(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+    tag: &SubscriberTag,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = $2"#,
+        subscriber_id,
+        tag.as_ref()
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(())
+}
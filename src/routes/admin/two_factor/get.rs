@@ -0,0 +1,82 @@
+use crate::authentication::{
+    count_unused_recovery_codes, get_two_factor_settings, totp_provisioning_uri,
+    totp_secret_base32, CsrfToken, TotpCipher, UserId,
+};
+use crate::routes::admin::dashboard::get_username;
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use sqlx::PgPool;
+
+enum TwoFactorStatus {
+    On {
+        n_recovery_codes: i64,
+    },
+    // Enrolment started: show the secret until a code confirms the app has it
+    Enrolling {
+        qr_code: String,
+        provisioning_uri: String,
+        secret: String,
+    },
+    Off,
+}
+
+#[derive(Template)]
+#[template(path = "admin/two_factor/settings.html")]
+struct TwoFactorSettingsTemplate {
+    flash_messages: IncomingFlashMessages,
+    status: TwoFactorStatus,
+    csrf_token: CsrfToken,
+}
+
+pub async fn two_factor_settings(
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    totp_cipher: web::Data<TotpCipher>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    let settings = get_two_factor_settings(&connection_pool, *user_id)
+        .await
+        .map_err(e500)?;
+    let status = match settings {
+        Some(settings) if settings.enabled => {
+            let n_recovery_codes = count_unused_recovery_codes(&connection_pool, *user_id)
+                .await
+                .map_err(e500)?;
+            TwoFactorStatus::On { n_recovery_codes }
+        }
+        Some(settings) => {
+            let secret = totp_cipher
+                .decrypt(*user_id, &settings.encrypted_secret)
+                .map_err(e500)?;
+            let username = get_username(*user_id, &connection_pool)
+                .await
+                .map_err(e500)?;
+            let provisioning_uri = totp_provisioning_uri(&username, &secret);
+            let qr_code = QrCode::new(provisioning_uri.as_bytes())
+                .map_err(e500)?
+                .render::<svg::Color>()
+                .min_dimensions(200, 200)
+                .build();
+            TwoFactorStatus::Enrolling {
+                qr_code,
+                provisioning_uri,
+                secret: totp_secret_base32(&secret),
+            }
+        }
+        None => TwoFactorStatus::Off,
+    };
+
+    render_page(&TwoFactorSettingsTemplate {
+        flash_messages,
+        status,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::two_factor_settings;
+mod post;
+pub use post::{confirm_two_factor_enrolment, start_two_factor_enrolment, turn_off_two_factor};
@@ -0,0 +1,143 @@
+use crate::authentication::{
+    disable_two_factor, enable_two_factor, generate_recovery_codes, generate_totp_secret,
+    get_two_factor_settings, store_pending_totp_secret, verify_second_factor, verify_totp_code,
+    TotpCipher, UserId,
+};
+use crate::routes::admin::dashboard::get_username;
+use crate::security_events::{record_security_event, TWO_FACTOR_DISABLED, TWO_FACTOR_ENABLED};
+use crate::utils::{e500, render_page, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use askama::Template;
+use chrono::Utc;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/two_factor/recovery_codes.html")]
+struct RecoveryCodesTemplate {
+    recovery_codes: Vec<String>,
+}
+
+#[tracing::instrument(
+    name = "Start two-factor enrolment",
+    skip(connection_pool, totp_cipher)
+)]
+pub async fn start_two_factor_enrolment(
+    connection_pool: web::Data<PgPool>,
+    totp_cipher: web::Data<TotpCipher>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let secret = generate_totp_secret();
+    store_pending_totp_secret(
+        &connection_pool,
+        *user_id,
+        &totp_cipher.encrypt(*user_id, &secret),
+    )
+    .await
+    .map_err(e500)?;
+    Ok(see_other("/admin/two_factor"))
+}
+
+#[tracing::instrument(
+    name = "Confirm two-factor enrolment",
+    skip(form, connection_pool, totp_cipher)
+)]
+pub async fn confirm_two_factor_enrolment(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+    totp_cipher: web::Data<TotpCipher>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let settings = match get_two_factor_settings(&connection_pool, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(settings) if !settings.enabled => settings,
+        _ => {
+            FlashMessage::error("There is no two-factor enrolment to confirm.").send();
+            return Ok(see_other("/admin/two_factor"));
+        }
+    };
+    let secret = totp_cipher
+        .decrypt(*user_id, &settings.encrypted_secret)
+        .map_err(e500)?;
+    let step = match verify_totp_code(&secret, &form.code, Utc::now()) {
+        Some(step) => step,
+        None => {
+            FlashMessage::error("The code is not valid. Check the clock of your device.").send();
+            return Ok(see_other("/admin/two_factor"));
+        }
+    };
+
+    let recovery_codes = generate_recovery_codes();
+    let mut transaction = connection_pool.begin().await.map_err(e500)?;
+    enable_two_factor(&mut transaction, *user_id, step, &recovery_codes)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    let username = get_username(*user_id, &connection_pool)
+        .await
+        .map_err(e500)?;
+    record_security_event(
+        &connection_pool,
+        TWO_FACTOR_ENABLED,
+        &username,
+        Some(*user_id),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+
+    // Recovery codes are only stored hashed, so this is the one chance to see them
+    render_page(&RecoveryCodesTemplate { recovery_codes }).map_err(e500)
+}
+
+#[tracing::instrument(
+    name = "Turn off two-factor authentication",
+    skip(form, connection_pool, totp_cipher)
+)]
+pub async fn turn_off_two_factor(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+    totp_cipher: web::Data<TotpCipher>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    // Someone walking up to an unattended session must not be able to remove the second factor
+    if verify_second_factor(&connection_pool, &totp_cipher, *user_id, &form.code)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        FlashMessage::error("The code is not valid.").send();
+        return Ok(see_other("/admin/two_factor"));
+    }
+
+    let mut transaction = connection_pool.begin().await.map_err(e500)?;
+    disable_two_factor(&mut transaction, *user_id)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    let username = get_username(*user_id, &connection_pool)
+        .await
+        .map_err(e500)?;
+    record_security_event(
+        &connection_pool,
+        TWO_FACTOR_DISABLED,
+        &username,
+        Some(*user_id),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("Two-factor authentication is off.").send();
+    Ok(see_other("/admin/two_factor"))
+}
@@ -0,0 +1,42 @@
+use crate::authentication::{
+    get_admin_users, get_pending_invitations, AdminUser, CsrfToken, Invitation, Role, UserId,
+};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "admin/users.html")]
+struct AdminUsersTemplate {
+    flash_messages: IncomingFlashMessages,
+    users: Vec<AdminUser>,
+    current_user_id: Uuid,
+    invitations: Vec<Invitation>,
+    roles: [Role; 3],
+    csrf_token: CsrfToken,
+}
+
+pub async fn admin_users(
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let users = get_admin_users(&connection_pool).await.map_err(e500)?;
+    let invitations = get_pending_invitations(&connection_pool)
+        .await
+        .map_err(e500)?;
+
+    render_page(&AdminUsersTemplate {
+        flash_messages,
+        users,
+        current_user_id: **user_id,
+        invitations,
+        roles: Role::ALL,
+        csrf_token: csrf_token.into_inner(),
+    })
+    .map_err(e500)
+}
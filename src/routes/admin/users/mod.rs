@@ -0,0 +1,4 @@
+mod get;
+pub use get::admin_users;
+mod post;
+pub use post::{change_user_role, invite_admin_user, revoke_admin_invitation, update_admin_user};
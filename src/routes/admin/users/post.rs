@@ -0,0 +1,329 @@
+use crate::authentication::{
+    delete_user, email_belongs_to_user, end_all_sessions, lock_active_owners,
+    revoke_all_api_tokens, revoke_invitation, set_user_disabled, set_user_role, store_invitation,
+    Role, UserId,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::routes::admin::dashboard::get_username;
+use crate::security_events::{
+    record_security_event, USER_DELETED, USER_DISABLED, USER_ENABLED, USER_ROLE_CHANGED,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct InvitationFormData {
+    email: String,
+    role: Role,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeFormData {
+    invitation_id: Uuid,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    user_id: Uuid,
+    role: Role,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum UserAction {
+    Disable,
+    Enable,
+    Delete,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UpdateFormData {
+    user_id: Uuid,
+    action: UserAction,
+}
+
+#[tracing::instrument(
+    name = "Invite an admin user",
+    skip(form, connection_pool, email_client, base_url),
+    fields(email = %form.email, role = %form.role)
+)]
+pub async fn invite_admin_user(
+    form: web::Form<InvitationFormData>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    admin_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let InvitationFormData { email, role } = form.0;
+    let email = match SubscriberEmail::parse(email.trim().to_string()) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    if email_belongs_to_user(&connection_pool, email.as_ref())
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(format!(
+            "{} already belongs to an admin user.",
+            email.as_ref()
+        ))
+        .send();
+        return Ok(see_other("/admin/users"));
+    }
+    let admin_id = admin_id.into_inner();
+    let admin_username = get_username(*admin_id, &connection_pool)
+        .await
+        .map_err(e500)?;
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let token = store_invitation(&mut transaction, email.as_ref(), role, *admin_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an invitation")
+        .map_err(e500)?;
+    // The link is only sent once it works. If sending fails, the invitation can be sent again,
+    // which revokes this one
+    send_invitation_email(
+        &email_client,
+        &base_url.0,
+        &email,
+        role,
+        &admin_username,
+        &token,
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "An invitation to join as {} has been sent to {}.",
+        role,
+        email.as_ref()
+    ))
+    .send();
+    Ok(see_other("/admin/users"))
+}
+
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    base_url: &str,
+    email: &SubscriberEmail,
+    role: Role,
+    invited_by: &str,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    let invitation_link = format!("{}/invitations/accept?token={}", base_url, token);
+    let plain_body = format!(
+        "{} invited you to help run our newsletter as {}.\n\
+        Visit {} within the next week to choose your username and password.",
+        invited_by, role, invitation_link
+    );
+    let html_body = format!(
+        "{} invited you to help run our newsletter as {}. <br />\
+        Click <a href=\"{}\">here</a> within the next week to choose your username and password.",
+        htmlescape::encode_minimal(invited_by),
+        role,
+        invitation_link
+    );
+    email_client
+        .send_email(
+            email,
+            "You are invited to our newsletter admin",
+            &html_body,
+            &plain_body,
+        )
+        .await
+        .context("Failed to send the invitation email")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Revoke an admin invitation",
+    skip(form, connection_pool),
+    fields(invitation_id = %form.invitation_id)
+)]
+pub async fn revoke_admin_invitation(
+    form: web::Form<RevokeFormData>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match revoke_invitation(&connection_pool, form.invitation_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(email) => {
+            FlashMessage::info(format!("The invitation of {} has been revoked.", email)).send()
+        }
+        None => FlashMessage::error("The invitation is no longer pending.").send(),
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(
+    name = "Change the role of an admin user",
+    skip(form, connection_pool),
+    fields(user_id = %form.user_id, role = %form.role)
+)]
+pub async fn change_user_role(
+    form: web::Form<RoleFormData>,
+    connection_pool: web::Data<PgPool>,
+    admin_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let RoleFormData { user_id, role } = form.0;
+    let admin_id = admin_id.into_inner();
+    if user_id == *admin_id {
+        FlashMessage::error("You cannot change your own account here.").send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let owners = lock_active_owners(&mut transaction).await.map_err(e500)?;
+    if role != Role::Owner && is_last_owner(&owners, user_id) {
+        FlashMessage::error("There must be at least one active owner.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let username = match set_user_role(&mut transaction, user_id, role)
+        .await
+        .map_err(e500)?
+    {
+        Some(username) => username,
+        None => {
+            FlashMessage::error("The user does not exist.").send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change a role")
+        .map_err(e500)?;
+
+    let details = format!(
+        "Made {} by {}",
+        role,
+        get_username(*admin_id, &connection_pool)
+            .await
+            .map_err(e500)?
+    );
+    record_security_event(
+        &connection_pool,
+        USER_ROLE_CHANGED,
+        &username,
+        Some(user_id),
+        Some(&details),
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info(format!("{} is now {}.", username, role)).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(
+    name = "Apply an admin action to an admin user",
+    skip(form, connection_pool),
+    fields(user_id = %form.user_id, action = ?form.action)
+)]
+pub async fn update_admin_user(
+    form: web::Form<UpdateFormData>,
+    connection_pool: web::Data<PgPool>,
+    admin_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let UpdateFormData { user_id, action } = form.0;
+    let admin_id = admin_id.into_inner();
+    if user_id == *admin_id {
+        FlashMessage::error("You cannot change your own account here.").send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let owners = lock_active_owners(&mut transaction).await.map_err(e500)?;
+    if !matches!(action, UserAction::Enable) && is_last_owner(&owners, user_id) {
+        FlashMessage::error("There must be at least one active owner.").send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let (username, event_type, message) = match action {
+        UserAction::Disable => {
+            let username = set_user_disabled(&mut transaction, user_id, true)
+                .await
+                .map_err(e500)?;
+            // Disabled admins are logged out right away, and their tokens stop working for good
+            end_all_sessions(&mut transaction, user_id)
+                .await
+                .map_err(e500)?;
+            revoke_all_api_tokens(&mut transaction, user_id)
+                .await
+                .map_err(e500)?;
+            (username, USER_DISABLED, "has been disabled")
+        }
+        UserAction::Enable => {
+            let username = set_user_disabled(&mut transaction, user_id, false)
+                .await
+                .map_err(e500)?;
+            (username, USER_ENABLED, "can log in again")
+        }
+        UserAction::Delete => {
+            let username = delete_user(&mut transaction, user_id).await.map_err(e500)?;
+            (username, USER_DELETED, "has been deleted")
+        }
+    };
+    let username = match username {
+        Some(username) => username,
+        None => {
+            FlashMessage::error("The user does not exist.").send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update an admin user")
+        .map_err(e500)?;
+
+    let details = format!(
+        "By {}",
+        get_username(*admin_id, &connection_pool)
+            .await
+            .map_err(e500)?
+    );
+    record_security_event(
+        &connection_pool,
+        event_type,
+        &username,
+        Some(user_id),
+        Some(&details),
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info(format!("{} {}.", username, message)).send();
+    Ok(see_other("/admin/users"))
+}
+
+// Whether the user is the only active owner left, who cannot be demoted, disabled or deleted
+fn is_last_owner(active_owners: &[Uuid], user_id: Uuid) -> bool {
+    active_owners.iter().all(|owner| *owner == user_id)
+}
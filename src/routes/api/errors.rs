@@ -0,0 +1,94 @@
+use crate::routes::{error_chain_fmt, PublishError, SubscribeError};
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+
+/// The error of an API request, sent back as a JSON body with a stable code and a message.
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error(transparent)]
+    Subscribe(#[from] SubscribeError),
+    #[error(transparent)]
+    Publish(#[from] PublishError),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    // The request could not be read, before any of the domain checks
+    #[error("{0}")]
+    InvalidRequest(String),
+}
+
+#[derive(serde::Serialize)]
+struct ErrorBody {
+    error: ErrorDetails,
+}
+
+#[derive(serde::Serialize)]
+struct ErrorDetails {
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::Subscribe(SubscribeError::ValidationError(_))
+            | ApiError::Publish(PublishError::ValidationError(_)) => "validation_error",
+            ApiError::Subscribe(SubscribeError::TooManyRequests) => "too_many_requests",
+            ApiError::Publish(PublishError::AlreadyPublished) => "already_published",
+            ApiError::Publish(PublishError::IdempotencyKeyError(_))
+            | ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::Subscribe(SubscribeError::UnexpectedError(_))
+            | ApiError::Publish(PublishError::UnexpectedError(_)) => "unexpected_error",
+        }
+    }
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Subscribe(e) => e.status_code(),
+            ApiError::Publish(e) => e.status_code(),
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status_code = self.status_code();
+        // The details of unexpected errors go to the logs, not to the client
+        let message = if status_code.is_server_error() {
+            "Something went wrong. Please try again later.".into()
+        } else {
+            self.to_string()
+        };
+        let mut response = HttpResponse::build(status_code);
+        if let ApiError::Unauthorized(_) = self {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        }
+        response.json(ErrorBody {
+            error: ErrorDetails {
+                code: self.code(),
+                message,
+            },
+        })
+    }
+}
+
+/// Turns the errors of the JSON, query and path extractors into JSON error bodies.
+pub fn invalid_request<E: std::fmt::Display>(e: E, _req: &HttpRequest) -> actix_web::Error {
+    ApiError::InvalidRequest(e.to_string()).into()
+}
@@ -0,0 +1,369 @@
+use super::pagination::parse_limit;
+use super::ApiError;
+use crate::domain::Segment;
+use crate::mailing_lists::{list_exists, DEFAULT_LIST_ID};
+use crate::routes::{enqueue_delivery_tasks, PublishError};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct NewIssueBody {
+    title: String,
+    html_content: String,
+    text_content: String,
+    list_id: Option<Uuid>,
+    segment: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct IssueRecord {
+    newsletter_issue_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    list_id: Uuid,
+    segment: Option<String>,
+    created_at: DateTime<Utc>,
+    // Drafts have not been published yet
+    published_at: Option<DateTime<Utc>>,
+}
+
+/// Stores a draft issue. Nothing is sent until it is published.
+#[tracing::instrument(name = "Create a newsletter issue through the API", skip_all)]
+pub async fn create_issue(
+    body: web::Json<NewIssueBody>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let NewIssueBody {
+        title,
+        html_content,
+        text_content,
+        list_id,
+        segment,
+    } = body.into_inner();
+    for (field, value) in [
+        ("title", &title),
+        ("html_content", &html_content),
+        ("text_content", &text_content),
+    ] {
+        if value.trim().is_empty() {
+            return Err(
+                PublishError::ValidationError(format!("The {} cannot be empty.", field)).into(),
+            );
+        }
+    }
+    let list_id = list_id.unwrap_or(DEFAULT_LIST_ID);
+    if !list_exists(&connection_pool, list_id)
+        .await
+        .context("Failed to check the target mailing list")
+        .map_err(PublishError::UnexpectedError)?
+    {
+        return Err(PublishError::ValidationError(format!(
+            "{} is not a valid mailing list.",
+            list_id
+        ))
+        .into());
+    }
+    // A blank segment targets the whole list
+    let segment = segment
+        .filter(|s| !s.trim().is_empty())
+        .map(Segment::parse)
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+
+    let newsletter_issue_id = Uuid::new_v4();
+    // Keep the normalised expression around to know who an issue was aimed at
+    let segment = segment.map(|s| s.to_string());
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            list_id,
+            segment,
+            title,
+            text_content,
+            html_content
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        newsletter_issue_id,
+        list_id,
+        segment,
+        title,
+        text_content,
+        html_content
+    )
+    .execute(connection_pool.get_ref())
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(PublishError::UnexpectedError)?;
+
+    let issue = get_issue(&connection_pool, newsletter_issue_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("The new newsletter issue is missing"))
+        .map_err(PublishError::UnexpectedError)?;
+    Ok(HttpResponse::Created().json(issue))
+}
+
+#[derive(serde::Deserialize)]
+pub struct IssueListQuery {
+    // Keyset cursor: the last issue of the previous page
+    after_created_at: Option<String>,
+    after_id: Option<Uuid>,
+    limit: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct IssuePage {
+    issues: Vec<IssueRecord>,
+    next_cursor: Option<Cursor>,
+}
+
+#[derive(serde::Serialize)]
+struct Cursor {
+    after_created_at: DateTime<Utc>,
+    after_id: Uuid,
+}
+
+/// Lists the issues, drafts included, newest first.
+pub async fn list_issues(
+    query: web::Query<IssueListQuery>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let IssueListQuery {
+        after_created_at,
+        after_id,
+        limit,
+    } = query.into_inner();
+    let limit = parse_limit(limit.as_deref()).map_err(PublishError::ValidationError)?;
+    let cursor = match (after_created_at, after_id) {
+        (Some(created_at), Some(id)) => {
+            let created_at = DateTime::parse_from_rfc3339(&created_at).map_err(|_| {
+                PublishError::ValidationError(format!("{} is not a valid cursor.", created_at))
+            })?;
+            Some((created_at.with_timezone(&Utc), id))
+        }
+        _ => None,
+    };
+
+    let mut issues = sqlx::query_as!(
+        IssueRecord,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            list_id,
+            segment,
+            created_at,
+            published_at::timestamptz AS "published_at: DateTime<Utc>"
+        FROM newsletter_issues
+        WHERE $1::timestamptz IS NULL OR (created_at, newsletter_issue_id) < ($1, $2)
+        ORDER BY created_at DESC, newsletter_issue_id DESC
+        LIMIT $3
+        "#,
+        cursor.map(|(created_at, _)| created_at),
+        cursor.map(|(_, id)| id),
+        limit + 1
+    )
+    .fetch_all(connection_pool.get_ref())
+    .await
+    .context("Failed to get the newsletter issues")
+    .map_err(PublishError::UnexpectedError)?;
+    // One extra row is fetched to know whether there is a next page
+    let has_next_page = issues.len() as i64 > limit;
+    issues.truncate(limit as usize);
+    let next_cursor = match (has_next_page, issues.last()) {
+        (true, Some(last)) => Some(Cursor {
+            after_created_at: last.created_at,
+            after_id: last.newsletter_issue_id,
+        }),
+        _ => None,
+    };
+
+    Ok(HttpResponse::Ok().json(IssuePage {
+        issues,
+        next_cursor,
+    }))
+}
+
+pub async fn issue_details(
+    newsletter_issue_id: web::Path<Uuid>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue = get_issue(&connection_pool, newsletter_issue_id)
+        .await?
+        .ok_or_else(|| issue_not_found(newsletter_issue_id))?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+/// Queues the deliveries of a draft issue, which cannot be published twice.
+#[tracing::instrument(
+    name = "Publish a newsletter issue through the API",
+    skip(connection_pool)
+)]
+pub async fn publish_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(PublishError::UnexpectedError)?;
+    // Lock the issue so that concurrent requests cannot both publish it
+    let issue = sqlx::query!(
+        r#"
+        SELECT list_id, segment, published_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to get the newsletter issue")
+    .map_err(PublishError::UnexpectedError)?
+    .ok_or_else(|| issue_not_found(newsletter_issue_id))?;
+    if issue.published_at.is_some() {
+        return Err(PublishError::AlreadyPublished.into());
+    }
+
+    let segment = issue
+        .segment
+        .map(Segment::parse)
+        .transpose()
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("The stored segment of the newsletter issue is invalid")
+        .map_err(PublishError::UnexpectedError)?;
+    enqueue_delivery_tasks(
+        &mut transaction,
+        newsletter_issue_id,
+        issue.list_id,
+        segment.as_ref(),
+    )
+    .await
+    .context("Failed to enqueue delivery tasks")
+    .map_err(PublishError::UnexpectedError)?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to mark the newsletter issue as published")
+    .map_err(PublishError::UnexpectedError)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a newsletter issue")
+        .map_err(PublishError::UnexpectedError)?;
+
+    let issue = get_issue(&connection_pool, newsletter_issue_id)
+        .await?
+        .ok_or_else(|| issue_not_found(newsletter_issue_id))?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+#[derive(serde::Serialize)]
+struct IssueStats {
+    newsletter_issue_id: Uuid,
+    // Deliveries still waiting in the queue
+    queued: i64,
+    // Deliveries made, by their outcome
+    outcomes: BTreeMap<String, i64>,
+}
+
+pub async fn issue_stats(
+    newsletter_issue_id: web::Path<Uuid>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    if get_issue(&connection_pool, newsletter_issue_id)
+        .await?
+        .is_none()
+    {
+        return Err(issue_not_found(newsletter_issue_id));
+    }
+
+    let queued = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(connection_pool.get_ref())
+    .await
+    .context("Failed to count the queued deliveries")
+    .map_err(PublishError::UnexpectedError)?;
+    let outcomes = sqlx::query!(
+        r#"
+        SELECT outcome, COUNT(*) AS "count!"
+        FROM newsletter_deliveries
+        WHERE newsletter_issue_id = $1
+        GROUP BY outcome
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(connection_pool.get_ref())
+    .await
+    .context("Failed to count the deliveries")
+    .map_err(PublishError::UnexpectedError)?
+    .into_iter()
+    .map(|r| (r.outcome, r.count))
+    .collect();
+
+    Ok(HttpResponse::Ok().json(IssueStats {
+        newsletter_issue_id,
+        queued,
+        outcomes,
+    }))
+}
+
+fn issue_not_found(newsletter_issue_id: Uuid) -> ApiError {
+    ApiError::NotFound(format!(
+        "There is no newsletter issue with the id {}.",
+        newsletter_issue_id
+    ))
+}
+
+async fn get_issue(
+    connection_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueRecord>, PublishError> {
+    let issue = sqlx::query_as!(
+        IssueRecord,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            list_id,
+            segment,
+            created_at,
+            published_at::timestamptz AS "published_at: DateTime<Utc>"
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(connection_pool)
+    .await
+    .context("Failed to get the newsletter issue")?;
+    Ok(issue)
+}
@@ -0,0 +1,10 @@
+mod errors;
+mod issues;
+mod openapi;
+mod pagination;
+mod subscribers;
+
+pub use errors::*;
+pub use issues::*;
+pub use openapi::*;
+pub use subscribers::*;
@@ -0,0 +1,48 @@
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+
+// Written by hand: it has to be kept in line with the routes of the `/api/v1` scope
+const OPENAPI_DOCUMENT: &str = include_str!("openapi.json");
+
+/// The OpenAPI document describing the `/api/v1` endpoints.
+pub async fn openapi_document() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(OPENAPI_DOCUMENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OPENAPI_DOCUMENT;
+
+    #[test]
+    fn the_document_describes_every_endpoint() {
+        let document: serde_json::Value = serde_json::from_str(OPENAPI_DOCUMENT).unwrap();
+        let paths = document["paths"].as_object().unwrap();
+        let mut operations: Vec<String> = paths
+            .iter()
+            .flat_map(|(path, item)| {
+                item.as_object()
+                    .unwrap()
+                    .keys()
+                    .filter(|key| *key != "parameters")
+                    .map(move |method| format!("{} {}", method, path))
+            })
+            .collect();
+        operations.sort();
+        assert_eq!(
+            operations,
+            [
+                "delete /subscribers/{subscriber_id}",
+                "get /issues",
+                "get /issues/{newsletter_issue_id}",
+                "get /issues/{newsletter_issue_id}/stats",
+                "get /subscribers",
+                "get /subscribers/{subscriber_id}",
+                "post /issues",
+                "post /issues/{newsletter_issue_id}/publish",
+                "post /subscribers",
+            ]
+        );
+    }
+}
@@ -0,0 +1,35 @@
+pub(super) const DEFAULT_PAGE_SIZE: i64 = 50;
+pub(super) const MAX_PAGE_SIZE: i64 = 500;
+
+pub(super) fn parse_limit(limit: Option<&str>) -> Result<i64, String> {
+    match limit.map(str::trim).filter(|limit| !limit.is_empty()) {
+        None => Ok(DEFAULT_PAGE_SIZE),
+        Some(limit) => match limit.parse::<i64>() {
+            Ok(limit) if (1..=MAX_PAGE_SIZE).contains(&limit) => Ok(limit),
+            _ => Err(format!(
+                "The limit must be a number between 1 and {}.",
+                MAX_PAGE_SIZE
+            )),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_limit, DEFAULT_PAGE_SIZE};
+    use claim::assert_err;
+
+    #[test]
+    fn the_limit_defaults_to_a_page() {
+        assert_eq!(parse_limit(None), Ok(DEFAULT_PAGE_SIZE));
+        assert_eq!(parse_limit(Some(" ")), Ok(DEFAULT_PAGE_SIZE));
+        assert_eq!(parse_limit(Some("10")), Ok(10));
+    }
+
+    #[test]
+    fn the_limit_must_be_within_bounds() {
+        assert_err!(parse_limit(Some("0")));
+        assert_err!(parse_limit(Some("501")));
+        assert_err!(parse_limit(Some("ten")));
+    }
+}
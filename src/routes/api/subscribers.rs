@@ -0,0 +1,204 @@
+use super::pagination::parse_limit;
+use super::ApiError;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::mailing_lists::DEFAULT_LIST_ID;
+use crate::manage_links::ManageLinks;
+use crate::routes::{
+    delete_subscriber, get_subscribers, register_subscriber, FilterParams, SubscribeError,
+    SubscriberRecord,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppression_list::SuppressionKey;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct NewSubscriberBody {
+    email: String,
+    name: String,
+    list_id: Option<Uuid>,
+}
+
+/// Subscribes an address to a mailing list, sending it a confirmation email like the
+/// subscribe form does.
+#[tracing::instrument(
+    name = "Create a subscriber through the API",
+    skip(body, connection_pool, email_client, base_url, manage_links, suppression_key),
+    fields(subscriber_email = %body.email)
+)]
+pub async fn create_subscriber(
+    body: web::Json<NewSubscriberBody>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    manage_links: web::Data<ManageLinks>,
+    suppression_key: web::Data<SuppressionKey>,
+) -> Result<HttpResponse, ApiError> {
+    let NewSubscriberBody {
+        email,
+        name,
+        list_id,
+    } = body.into_inner();
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(email).map_err(SubscribeError::ValidationError)?,
+        name: SubscriberName::parse(name).map_err(SubscribeError::ValidationError)?,
+    };
+    let email = new_subscriber.email.as_ref().to_owned();
+
+    let subscriber_id = register_subscriber(
+        &connection_pool,
+        &email_client,
+        &base_url.0,
+        &manage_links,
+        &suppression_key,
+        new_subscriber,
+        list_id.unwrap_or(DEFAULT_LIST_ID),
+    )
+    .await?
+    // Unlike the public form, admins may know about the suppression list
+    .ok_or_else(|| {
+        SubscribeError::ValidationError(format!("{} is on the suppression list.", email))
+    })?;
+
+    let subscriber = get_subscriber_record(&connection_pool, subscriber_id)
+        .await
+        .context("Failed to get the new subscriber")
+        .map_err(SubscribeError::UnexpectedError)?
+        .ok_or_else(|| anyhow::anyhow!("The new subscriber is missing"))
+        .map_err(SubscribeError::UnexpectedError)?;
+    Ok(HttpResponse::Created().json(subscriber))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListQuery {
+    #[serde(flatten)]
+    filters: FilterParams,
+    // Keyset cursor: the last subscriber of the previous page
+    after_subscribed_at: Option<String>,
+    after_id: Option<Uuid>,
+    // Numbers cannot be deserialized next to a flattened struct, so this is parsed by hand
+    limit: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberPage {
+    subscribers: Vec<SubscriberRecord>,
+    next_cursor: Option<Cursor>,
+}
+
+#[derive(serde::Serialize)]
+struct Cursor {
+    after_subscribed_at: DateTime<Utc>,
+    after_id: Uuid,
+}
+
+/// Lists the subscribers matching the filters of the admin browser, newest first.
+pub async fn list_subscribers(
+    query: web::Query<ListQuery>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let ListQuery {
+        filters,
+        after_subscribed_at,
+        after_id,
+        limit,
+    } = query.into_inner();
+    let filters = filters
+        .normalized()
+        .parse()
+        .map_err(SubscribeError::ValidationError)?;
+    let limit = parse_limit(limit.as_deref()).map_err(SubscribeError::ValidationError)?;
+    let cursor = match (after_subscribed_at, after_id) {
+        (Some(subscribed_at), Some(id)) => {
+            let subscribed_at = DateTime::parse_from_rfc3339(&subscribed_at).map_err(|_| {
+                SubscribeError::ValidationError(format!("{} is not a valid cursor.", subscribed_at))
+            })?;
+            Some((subscribed_at.with_timezone(&Utc), id))
+        }
+        _ => None,
+    };
+
+    let mut subscribers = get_subscribers(&connection_pool, &filters, cursor, limit + 1)
+        .await
+        .context("Failed to get the subscribers")
+        .map_err(SubscribeError::UnexpectedError)?;
+    // One extra row is fetched to know whether there is a next page
+    let has_next_page = subscribers.len() as i64 > limit;
+    subscribers.truncate(limit as usize);
+    let next_cursor = match (has_next_page, subscribers.last()) {
+        (true, Some(last)) => Some(Cursor {
+            after_subscribed_at: last.subscribed_at,
+            after_id: last.id,
+        }),
+        _ => None,
+    };
+
+    Ok(HttpResponse::Ok().json(SubscriberPage {
+        subscribers,
+        next_cursor,
+    }))
+}
+
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber_record(&connection_pool, subscriber_id)
+        .await
+        .context("Failed to get the subscriber")
+        .map_err(SubscribeError::UnexpectedError)?
+        .ok_or_else(|| subscriber_not_found(subscriber_id))?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+/// Deletes the subscriber like the admin browser does, suppressing their address so that
+/// queued deliveries are dropped. Past delivery records are kept until the address is erased.
+pub async fn remove_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let subscriber_id = subscriber_id.into_inner();
+    if get_subscriber_record(&connection_pool, subscriber_id)
+        .await
+        .context("Failed to get the subscriber")
+        .map_err(SubscribeError::UnexpectedError)?
+        .is_none()
+    {
+        return Err(subscriber_not_found(subscriber_id));
+    }
+    delete_subscriber(&connection_pool, subscriber_id)
+        .await
+        .context("Failed to delete the subscriber")
+        .map_err(SubscribeError::UnexpectedError)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn subscriber_not_found(subscriber_id: Uuid) -> ApiError {
+    ApiError::NotFound(format!(
+        "There is no subscriber with the id {}.",
+        subscriber_id
+    ))
+}
+
+#[tracing::instrument(name = "Get subscriber record", skip(connection_pool))]
+async fn get_subscriber_record(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(connection_pool)
+    .await
+}
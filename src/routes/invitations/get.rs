@@ -0,0 +1,45 @@
+use crate::authentication::{get_invitation, Invitation};
+use crate::utils::{e500, render_page, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "invitations/accept.html")]
+struct InvitationTemplate<'a> {
+    flash_messages: IncomingFlashMessages,
+    invited_by: &'a str,
+    invitation: &'a Invitation,
+    token: &'a str,
+}
+
+pub async fn invitation_form(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invitation = match get_invitation(&connection_pool, &parameters.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(invitation) => invitation,
+        None => {
+            FlashMessage::error("The invitation is invalid or has expired.").send();
+            return Ok(see_other("/login"));
+        }
+    };
+
+    render_page(&InvitationTemplate {
+        flash_messages,
+        invited_by: invitation.invited_by.as_deref().unwrap_or("An owner"),
+        invitation: &invitation,
+        token: &parameters.token,
+    })
+    .map_err(e500)
+}
@@ -0,0 +1,114 @@
+use crate::authentication::{
+    accept_invitation, create_user, CreateUserError, PasswordHashing, PasswordPolicy,
+    PasswordPolicyError,
+};
+use crate::security_events::{record_security_event, USER_CREATED};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    token: String,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip(form, connection_pool, password_hashing, password_policy),
+    fields(username = %form.username)
+)]
+pub async fn accept_user_invitation(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+    password_hashing: web::Data<PasswordHashing>,
+    password_policy: web::Data<PasswordPolicy>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData {
+        token,
+        username,
+        password,
+        password_check,
+    } = form.0;
+    let form_page = format!("/invitations/accept?token={}", urlencoding::encode(&token));
+    let username = username.trim();
+
+    if username.is_empty() {
+        FlashMessage::error("The username cannot be empty.").send();
+        return Ok(see_other(&form_page));
+    }
+    if password.expose_secret() != password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&form_page));
+    }
+    match password_policy
+        .check(&connection_pool, None, username, &password)
+        .await
+    {
+        Ok(()) => {}
+        Err(PasswordPolicyError::Violation(message)) => {
+            FlashMessage::error(message).send();
+            return Ok(see_other(&form_page));
+        }
+        Err(e) => return Err(e500(e)),
+    }
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let (email, role) = match accept_invitation(&mut transaction, &token)
+        .await
+        .map_err(e500)?
+    {
+        Some(invitation) => invitation,
+        None => {
+            FlashMessage::error("The invitation is invalid or has expired.").send();
+            return Ok(see_other("/login"));
+        }
+    };
+    // The invitation stays pending if the username is taken
+    let user_id = match create_user(
+        &mut transaction,
+        username,
+        password,
+        role,
+        Some(&email),
+        &password_hashing,
+    )
+    .await
+    {
+        Ok(user_id) => user_id,
+        Err(e @ CreateUserError::UsernameTaken(_)) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other(&form_page));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to accept an invitation")
+        .map_err(e500)?;
+
+    let details = format!("Accepted an invitation as {}", role);
+    record_security_event(
+        &connection_pool,
+        USER_CREATED,
+        username,
+        Some(user_id),
+        Some(&details),
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("Your account has been created. You can now log in.").send();
+    Ok(see_other("/login"))
+}
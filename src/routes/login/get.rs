@@ -1,43 +1,16 @@
-use actix_web::http::header::ContentType;
+use crate::utils::{e500, render_page};
 use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use askama::Template;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut msg_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
+#[derive(Template)]
+#[template(path = "login/login.html")]
+struct LoginTemplate {
+    flash_messages: IncomingFlashMessages,
+}
 
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-    <html lang="en">
-        <head>
-            <meta http-equiv="content-type" content="text/html; charset=utf-8">
-            <title>Login</title>
-        </head>
-        <body>
-            {msg_html}
-            <form action="/login" method="post">
-                <label>Username
-                    <input
-                        type="text"
-                        placeholder="Enter Username"
-                        name="username"
-                    >
-                </label>
-                <label>Password
-                    <input
-                    type="password"
-                    placeholder="Enter Password"
-                    name="password"
-                    >
-                </label>
-                <button type="submit">Login</button>
-            </form>
-        </body>
-    </html>"#,
-        ))
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    render_page(&LoginTemplate { flash_messages }).map_err(e500)
 }
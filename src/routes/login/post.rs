@@ -1,11 +1,15 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::authentication::{
+    is_two_factor_enabled, record_successful_login, start_session, validate_credentials, AuthError,
+    Credentials, PasswordHashing,
+};
 use crate::routes::error_chain_fmt;
-use crate::session_state::TypedSession;
+use crate::session_state::{PendingTwoFactor, TypedSession};
 use actix_web::error::InternalError;
 use actix_web::http::header::LOCATION;
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use actix_web_flash_messages::FlashMessage;
+use chrono::Utc;
 use secrecy::Secret;
 use sqlx::PgPool;
 
@@ -16,13 +20,15 @@ pub struct FormData {
 }
 
 #[tracing::instrument(
-    skip(form, connection_pool, session),
+    skip(form, connection_pool, password_hashing, session, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty) //TODO! Understand what this does
 )]
 pub async fn login(
     form: web::Form<FormData>,
     connection_pool: web::Data<PgPool>,
+    password_hashing: web::Data<PasswordHashing>,
     session: TypedSession,
+    request: HttpRequest,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let credentials = Credentials {
         username: form.0.username,
@@ -30,14 +36,37 @@ pub async fn login(
     };
 
     tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    let username = credentials.username.clone();
 
-    match validate_credentials(credentials, &connection_pool).await {
+    match validate_credentials(credentials, &connection_pool, &password_hashing).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
             session.renew(); // Rorate the session token when a user logs in
-            session
-                .insert_user_id(user_id) // Create session
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+
+            // The session only gets the user id once the second factor is verified too
+            if is_two_factor_enabled(&connection_pool, user_id)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?
+            {
+                let pending = PendingTwoFactor {
+                    user_id,
+                    username,
+                    started_at: Utc::now().timestamp(),
+                };
+                session
+                    .insert_pending_two_factor(&pending)
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                return Ok(HttpResponse::SeeOther()
+                    .insert_header((LOCATION, "/login/two_factor"))
+                    .finish());
+            }
+
+            record_successful_login(&connection_pool, user_id, &username, None)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            start_session(&session, &connection_pool, user_id, &request) // Create session
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
 
             Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
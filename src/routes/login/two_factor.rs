@@ -0,0 +1,114 @@
+use crate::authentication::{
+    is_locked_out, record_failed_login, record_successful_login, start_session,
+    verify_second_factor, SecondFactor, TotpCipher,
+};
+use crate::session_state::{PendingTwoFactor, TypedSession};
+use crate::utils::{e500, render_page, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use chrono::Utc;
+use sqlx::PgPool;
+
+// The second step has to be completed shortly after entering the password
+const PENDING_TWO_FACTOR_TTL_SECONDS: i64 = 5 * 60;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[derive(Template)]
+#[template(path = "login/two_factor.html")]
+struct TwoFactorTemplate {
+    flash_messages: IncomingFlashMessages,
+}
+
+pub async fn login_two_factor_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    if get_pending_two_factor(&session)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    render_page(&TwoFactorTemplate { flash_messages }).map_err(e500)
+}
+
+#[tracing::instrument(
+    skip(form, connection_pool, totp_cipher, session, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn login_two_factor(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+    totp_cipher: web::Data<TotpCipher>,
+    session: TypedSession,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let pending = match get_pending_two_factor(&session)? {
+        Some(pending) => pending,
+        None => {
+            FlashMessage::error("Please log in again.").send();
+            return Ok(see_other("/login"));
+        }
+    };
+    tracing::Span::current()
+        .record("username", &tracing::field::display(&pending.username))
+        .record("user_id", &tracing::field::display(&pending.user_id));
+
+    // Wrong codes count towards the lockout, which ends the pending login
+    if is_locked_out(&connection_pool, pending.user_id)
+        .await
+        .map_err(e500)?
+    {
+        session.remove_pending_two_factor();
+        FlashMessage::error("Authentication failed").send();
+        return Ok(see_other("/login"));
+    }
+
+    match verify_second_factor(&connection_pool, &totp_cipher, pending.user_id, &form.code)
+        .await
+        .map_err(e500)?
+    {
+        Some(second_factor) => {
+            let details = match second_factor {
+                SecondFactor::Totp => None,
+                SecondFactor::RecoveryCode => Some("Used a recovery code"),
+            };
+            record_successful_login(
+                &connection_pool,
+                pending.user_id,
+                &pending.username,
+                details,
+            )
+            .await
+            .map_err(e500)?;
+            session.renew();
+            session.remove_pending_two_factor();
+            start_session(&session, &connection_pool, pending.user_id, &request)
+                .await
+                .map_err(e500)?;
+            Ok(see_other("/admin/dashboard"))
+        }
+        None => {
+            record_failed_login(
+                &connection_pool,
+                pending.user_id,
+                &pending.username,
+                Some("Wrong two-factor code"),
+            )
+            .await
+            .map_err(e500)?;
+            FlashMessage::error("Authentication failed").send();
+            Ok(see_other("/login/two_factor"))
+        }
+    }
+}
+
+fn get_pending_two_factor(
+    session: &TypedSession,
+) -> Result<Option<PendingTwoFactor>, actix_web::Error> {
+    let pending = session.get_pending_two_factor().map_err(e500)?;
+    Ok(pending.filter(|p| Utc::now().timestamp() - p.started_at < PENDING_TWO_FACTOR_TTL_SECONDS))
+}
@@ -1,13 +1,27 @@
 mod admin;
+mod api;
 pub mod health_check;
 mod home;
+mod invitations;
 mod login;
+mod password_reset;
+mod subscribe_form;
 pub mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_manage;
+mod subscriptions_resend;
+mod webhooks;
 
 pub use admin::*;
+pub use api::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitations::*;
 pub use login::*;
+pub use password_reset::*;
+pub use subscribe_form::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_manage::*;
+pub use subscriptions_resend::*;
+pub use webhooks::*;
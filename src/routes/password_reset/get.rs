@@ -0,0 +1,51 @@
+use crate::authentication::get_password_reset_user;
+use crate::utils::{e500, render_page, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "password_reset/request.html")]
+struct PasswordResetTemplate {
+    flash_messages: IncomingFlashMessages,
+}
+
+#[derive(Template)]
+#[template(path = "password_reset/new_password.html")]
+struct NewPasswordTemplate<'a> {
+    flash_messages: IncomingFlashMessages,
+    token: &'a str,
+}
+
+pub async fn password_reset_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    render_page(&PasswordResetTemplate { flash_messages }).map_err(e500)
+}
+
+pub async fn new_password_form(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if get_password_reset_user(&connection_pool, &parameters.token)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        FlashMessage::error("The password reset link is invalid or has expired.").send();
+        return Ok(see_other("/password_reset"));
+    }
+
+    render_page(&NewPasswordTemplate {
+        flash_messages,
+        token: &parameters.token,
+    })
+    .map_err(e500)
+}
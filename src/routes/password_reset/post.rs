@@ -0,0 +1,206 @@
+use crate::authentication::{
+    change_password, clear_failed_logins, end_all_sessions, get_password_reset_recipient,
+    revoke_all_api_tokens, store_password_reset_token, use_password_reset_token, PasswordHashing,
+    PasswordPolicy, PasswordPolicyError, PasswordResetRecipient,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::routes::get_username;
+use crate::security_events::{record_security_event, PASSWORD_RESET, PASSWORD_RESET_REQUESTED};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct RequestFormData {
+    username: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetFormData {
+    token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, connection_pool, email_client, base_url),
+    fields(username = %form.username)
+)]
+pub async fn request_password_reset(
+    form: web::Form<RequestFormData>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(recipient) = get_password_reset_recipient(&connection_pool, &form.username)
+        .await
+        .map_err(e500)?
+    {
+        if let Err(e) =
+            send_password_reset_email(&connection_pool, &email_client, &base_url.0, &recipient)
+                .await
+        {
+            tracing::error!(error.cause_chain = ?e, "Failed to send a password reset email");
+        }
+    }
+
+    // Reply the same way whether or not a link was sent, so that the response does not disclose
+    // which usernames exist
+    FlashMessage::info(
+        "If the account has a recovery email, we have sent a password reset link to it.",
+    )
+    .send();
+    Ok(see_other("/login"))
+}
+
+async fn send_password_reset_email(
+    connection_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    recipient: &PasswordResetRecipient,
+) -> Result<(), anyhow::Error> {
+    let email = SubscriberEmail::parse(recipient.email.clone()).map_err(anyhow::Error::msg)?;
+    let token = store_password_reset_token(connection_pool, recipient.user_id)
+        .await
+        .context("Failed to store a password reset token")?;
+    let reset_link = format!("{}/password_reset/confirm?token={}", base_url, token);
+
+    let plain_body = format!(
+        "Someone asked to reset the password of {}.\n\
+        Visit {} within the next hour to choose a new password.\n\n\
+        If it was not you, you can ignore this email.",
+        recipient.username, reset_link
+    );
+    let html_body = format!(
+        "Someone asked to reset the password of {}. <br />\
+        Click <a href=\"{}\">here</a> within the next hour to choose a new password.\
+        <p>If it was not you, you can ignore this email.</p>",
+        htmlescape::encode_minimal(&recipient.username),
+        reset_link
+    );
+    email_client
+        .send_email(&email, "Reset your password", &html_body, &plain_body)
+        .await
+        .context("Failed to send the password reset email")?;
+
+    record_security_event(
+        connection_pool,
+        PASSWORD_RESET_REQUESTED,
+        &recipient.username,
+        Some(recipient.user_id),
+        None,
+    )
+    .await
+    .context("Failed to record a security event")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Reset a password",
+    skip(form, connection_pool, password_hashing, password_policy),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn reset_password(
+    form: web::Form<ResetFormData>,
+    connection_pool: web::Data<PgPool>,
+    password_hashing: web::Data<PasswordHashing>,
+    password_policy: web::Data<PasswordPolicy>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form_page = format!(
+        "/password_reset/confirm?token={}",
+        urlencoding::encode(&form.token)
+    );
+
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different new passwords - the field values must match.",
+        )
+        .send();
+        return Ok(see_other(&form_page));
+    }
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let user_id = match use_password_reset_token(&mut transaction, &form.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(user_id) => user_id,
+        None => {
+            FlashMessage::error("The password reset link is invalid or has expired.").send();
+            return Ok(see_other("/password_reset"));
+        }
+    };
+    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+
+    // The token stays valid if the password is rejected, as the transaction is rolled back
+    let username = get_username(user_id, &connection_pool)
+        .await
+        .map_err(e500)?;
+    match password_policy
+        .check(
+            &connection_pool,
+            Some(user_id),
+            &username,
+            &form.new_password,
+        )
+        .await
+    {
+        Ok(()) => {}
+        Err(PasswordPolicyError::Violation(message)) => {
+            FlashMessage::error(message).send();
+            return Ok(see_other(&form_page));
+        }
+        Err(e) => return Err(e500(e)),
+    }
+
+    change_password(
+        user_id,
+        form.0.new_password,
+        &mut transaction,
+        &password_hashing,
+        password_policy.history_size(),
+    )
+    .await
+    .map_err(e500)?;
+    // Whoever was logged in with the old password is logged out, and loses the tokens they made
+    end_all_sessions(&mut transaction, user_id)
+        .await
+        .map_err(e500)?;
+    revoke_all_api_tokens(&mut transaction, user_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to reset a password")
+        .map_err(e500)?;
+
+    // Owning the recovery email is enough to lift a lockout
+    let username = clear_failed_logins(&connection_pool, user_id)
+        .await
+        .map_err(e500)?
+        .context("The user does not exist")
+        .map_err(e500)?;
+    record_security_event(
+        &connection_pool,
+        PASSWORD_RESET,
+        &username,
+        Some(user_id),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("Your password has been reset. You can now log in.").send();
+    Ok(see_other("/login"))
+}
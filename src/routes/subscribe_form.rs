@@ -0,0 +1,18 @@
+use crate::mailing_lists::{get_lists, MailingList};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "subscribe.html")]
+struct SubscribeTemplate {
+    lists: Vec<MailingList>,
+}
+
+pub async fn subscribe_form(
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = get_lists(&connection_pool).await.map_err(e500)?;
+    render_page(&SubscribeTemplate { lists }).map_err(e500)
+}
@@ -8,13 +8,18 @@ use rand::{thread_rng, Rng};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use crate::confirmation_throttle::{get_unexpired_token, try_reserve_confirmation_email};
 use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::mailing_lists::{add_list_subscription, list_exists, DEFAULT_LIST_ID};
+use crate::manage_links::ManageLinks;
 use crate::startup::ApplicationBaseUrl;
+use crate::suppression_list::{is_suppressed, is_suppressed_or_erased, SuppressionKey};
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    list_id: Option<Uuid>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -29,7 +34,7 @@ impl TryFrom<FormData> for NewSubscriber {
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, connection_pool, email_client, base_url),
+    skip(form, connection_pool, email_client, base_url, manage_links, suppression_key),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
@@ -40,9 +45,69 @@ pub async fn subscribe(
     connection_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    manage_links: web::Data<ManageLinks>,
+    suppression_key: web::Data<SuppressionKey>,
 ) -> Result<HttpResponse, SubscribeError> {
+    let list_id = form.list_id.unwrap_or(DEFAULT_LIST_ID);
     let new_subscriber: NewSubscriber =
         form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    register_subscriber(
+        &connection_pool,
+        &email_client,
+        &base_url.0,
+        &manage_links,
+        &suppression_key,
+        new_subscriber,
+        list_id,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Adds the subscriber to the mailing list and sends them a confirmation email.
+/// Returns the id of the subscriber, or `None` if the address is on the suppression list.
+#[tracing::instrument(
+    name = "Registering a subscriber",
+    skip(
+        connection_pool,
+        email_client,
+        base_url,
+        manage_links,
+        suppression_key,
+        new_subscriber
+    )
+)]
+pub async fn register_subscriber(
+    connection_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    manage_links: &ManageLinks,
+    suppression_key: &SuppressionKey,
+    new_subscriber: NewSubscriber,
+    list_id: Uuid,
+) -> Result<Option<Uuid>, SubscribeError> {
+    if !list_exists(connection_pool, list_id)
+        .await
+        .context("Failed to check the requested mailing list")?
+    {
+        return Err(SubscribeError::ValidationError(format!(
+            "{} is not a valid mailing list.",
+            list_id
+        )));
+    }
+
+    // The subscribe form responds as if the subscription went through,
+    // so that the suppression list is not disclosed
+    if is_suppressed_or_erased(
+        connection_pool,
+        suppression_key,
+        new_subscriber.email.as_ref(),
+    )
+    .await
+    .context("Failed to check email against the suppression list")?
+    {
+        return Ok(None);
+    }
 
     let check_existing = sqlx::query!(
         r#"
@@ -52,28 +117,41 @@ pub async fn subscribe(
             "#,
         new_subscriber.email.as_ref()
     )
-    .fetch_optional(connection_pool.get_ref())
+    .fetch_optional(connection_pool)
     .await
     .context("Failed to check email against subscriber database")?;
 
     match check_existing {
         None => (),
         Some(record) => {
-            // If the existing subscriber already has a confirmed status, then exit with a success code
-            if record.status == "confirmed" {
-                return Ok(HttpResponse::Ok().finish());
-            }
-
-            let subscription_token = generate_subscription_token();
-
             let mut transaction = connection_pool
                 .begin()
                 .await
                 .context("Failed to acquire a Postgres connection from the pool")?;
 
-            store_token(&mut transaction, record.id, &subscription_token)
+            let list_status = add_list_subscription(&mut transaction, record.id, list_id)
                 .await
-                .context("Failed to store subscription token in database")?;
+                .context("Failed to add the subscriber to the mailing list")?;
+
+            // If the existing subscriber is already confirmed on this list, then exit with a success code
+            if record.status == "confirmed" && list_status == "confirmed" {
+                return Ok(Some(record.id));
+            }
+
+            // Send the pending link again rather than minting a new one on every request
+            let subscription_token =
+                get_or_store_token(&mut transaction, record.id, list_id).await?;
+
+            if !try_reserve_confirmation_email(&mut transaction, new_subscriber.email.as_ref())
+                .await
+                .context("Failed to check the confirmation email throttle")?
+            {
+                transaction
+                    .commit()
+                    .await
+                    .context("Failed to commit SQL transaction to store a new subscriber")?;
+                return Err(SubscribeError::TooManyRequests);
+            }
 
             transaction
                 .commit()
@@ -81,15 +159,17 @@ pub async fn subscribe(
                 .context("Failed to commit SQL transaction to store a new subscriber")?;
 
             send_confirmation_email(
-                &email_client,
+                connection_pool,
+                email_client,
                 new_subscriber,
-                &base_url.0,
+                base_url,
                 &subscription_token,
+                &manage_links.link(record.id),
             )
             .await
             .context("Failed to send confirmation email")?;
 
-            return Ok(HttpResponse::Ok().finish());
+            return Ok(Some(record.id));
         }
     }
 
@@ -102,11 +182,28 @@ pub async fn subscribe(
         .await
         .context("Failed to insert new subscriber in the database")?;
 
+    add_list_subscription(&mut transaction, subscriber_id, list_id)
+        .await
+        .context("Failed to add the subscriber to the mailing list")?;
+
     let subscription_token = generate_subscription_token();
 
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        list_id,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to store subscription token in database")?;
+
+    // The address may have been sent confirmations before it was deleted or erased
+    if !try_reserve_confirmation_email(&mut transaction, new_subscriber.email.as_ref())
         .await
-        .context("Failed to store subscription token in database")?;
+        .context("Failed to check the confirmation email throttle")?
+    {
+        return Err(SubscribeError::TooManyRequests);
+    }
 
     transaction
         .commit()
@@ -114,15 +211,17 @@ pub async fn subscribe(
         .context("Failed to commit SQL transaction to store a new subscriber")?;
 
     send_confirmation_email(
-        &email_client,
+        connection_pool,
+        email_client,
         new_subscriber,
-        &base_url.0,
+        base_url,
         &subscription_token,
+        &manage_links.link(subscriber_id),
     )
     .await
     .context("Failed to send confirmation email")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(Some(subscriber_id))
 }
 
 pub fn parse_subscriber(form: FormData) -> Result<NewSubscriber, String> {
@@ -151,8 +250,7 @@ pub async fn insert_subscriber(
         Utc::now()
     )
     .execute(transaction)
-    .await
-    .map_err(|e| e)?;
+    .await?;
 
     Ok(subscriber_id)
 }
@@ -164,15 +262,17 @@ pub async fn insert_subscriber(
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        VALUES ($1, $2, $3)
         "#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id
     )
     .execute(transaction)
     .await
@@ -181,38 +281,75 @@ pub async fn store_token(
     Ok(())
 }
 
+/// Returns the unexpired confirmation token of the subscriber for the list, storing a fresh one
+/// if there is none.
+pub async fn get_or_store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<String, anyhow::Error> {
+    if let Some(subscription_token) = get_unexpired_token(transaction, subscriber_id, list_id)
+        .await
+        .context("Failed to look up an unexpired subscription token")?
+    {
+        return Ok(subscription_token);
+    }
+    let subscription_token = generate_subscription_token();
+    store_token(transaction, subscriber_id, list_id, &subscription_token)
+        .await
+        .context("Failed to store subscription token in database")?;
+    Ok(subscription_token)
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
+    skip(
+        connection_pool,
+        email_client,
+        new_subscriber,
+        base_url,
+        subscription_token,
+        manage_link
+    )
 )]
 pub async fn send_confirmation_email(
+    connection_pool: &PgPool,
     email_client: &EmailClient,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+    manage_link: &str,
+) -> Result<(), anyhow::Error> {
+    if is_suppressed(connection_pool, new_subscriber.email.as_ref()).await? {
+        tracing::warn!("Skipping a confirmation email to a suppressed address");
+        return Ok(());
+    }
+
     let confirmation_link = format!(
         "{}/subscribe/confirm?subscription_token={}",
         base_url, subscription_token
     );
 
     let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
+        "Welcome to our newsletter!\nVisit {} to confirm your subscription.\n\n\
+        Manage your subscription: {}",
+        confirmation_link, manage_link
     );
     let html_body = format!(
         "Welcome to our newsletter! <br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
+        Click <a href=\"{}\">here</a> to confirm your subscription.\
+        <p><a href=\"{}\">Manage your subscription</a></p>",
+        confirmation_link, manage_link
     );
 
     email_client
         .send_email(&new_subscriber.email, "Welcome", &html_body, &plain_body)
-        .await
+        .await?;
+    Ok(())
 }
 
 /// Generate a random 25-character csae-sensitive subscription token for subscription confirmation
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -224,6 +361,8 @@ fn generate_subscription_token() -> String {
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("Too many confirmation emails were sent to this address. Please try again later.")]
+    TooManyRequests,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -232,6 +371,7 @@ impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
             SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -1,3 +1,4 @@
+use crate::confirmation_throttle::SUBSCRIPTION_TOKEN_TTL;
 use actix_web::http::StatusCode;
 use actix_web::ResponseError;
 use actix_web::{web, HttpResponse};
@@ -18,33 +19,63 @@ pub async fn confirm(
     parameters: web::Query<Parameters>,
     connection_pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, ConfirmError> {
-    let id = get_subscriber_id_from_token(&connection_pool, &parameters.subscription_token)
+    let id = get_subscription_token(&connection_pool, &parameters.subscription_token)
         .await
         .context("Failed to retrieve from database subscriber id based on subscription token")?;
 
-    match id {
-        None => Err(ConfirmError::SubscriptionIdNotFound),
-        Some(subscriber_id) => {
-            confirm_subscriber(&connection_pool, subscriber_id)
-                .await
-                .context("Failed to change confirmation status to confirmed for the subscriber")?;
-
-            Ok(HttpResponse::Ok().finish())
-        }
+    let token = id.ok_or(ConfirmError::SubscriptionIdNotFound)?;
+    if let Some(new_email) = &token.new_email {
+        // Email change tokens come from the preference center
+        return match change_subscriber_email(
+            &connection_pool,
+            token.subscriber_id,
+            new_email,
+            &parameters.subscription_token,
+        )
+        .await
+        {
+            Ok(()) => Ok(HttpResponse::Ok().finish()),
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("23505") => {
+                Err(ConfirmError::EmailAlreadySubscribed)
+            }
+            Err(e) => Err(anyhow::Error::new(e)
+                .context("Failed to change the email address of the subscriber")
+                .into()),
+        };
+    }
+    if let Some(list_id) = token.list_id {
+        confirm_subscriber(&connection_pool, token.subscriber_id, list_id)
+            .await
+            .context("Failed to change confirmation status to confirmed for the subscriber")?;
     }
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub struct SubscriptionToken {
+    pub subscriber_id: Uuid,
+    pub list_id: Option<Uuid>,
+    pub new_email: Option<String>,
 }
 
 #[tracing::instrument(
-    name = "Get subscriber_id from token",
+    name = "Get subscription token",
     skip(subscription_token, connection_pool)
 )]
-pub async fn get_subscriber_id_from_token(
+pub async fn get_subscription_token(
     connection_pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens where subscription_token= $1"#,
+) -> Result<Option<SubscriptionToken>, sqlx::Error> {
+    let result = sqlx::query_as!(
+        SubscriptionToken,
+        r#"
+        SELECT subscriber_id, list_id, new_email
+        FROM subscription_tokens
+        WHERE
+            subscription_token = $1 AND
+            (new_email IS NOT NULL OR created_at > now() - $2::text::interval)
+        "#,
         subscription_token,
+        SUBSCRIPTION_TOKEN_TTL,
     )
     .fetch_optional(connection_pool)
     .await
@@ -56,29 +87,76 @@ pub async fn get_subscriber_id_from_token(
         e
     })?;
 
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result)
 }
 
 #[tracing::instrument(
     name = "Mark subscriber as confirmed"
-    skip(subscriber_id, connection_pool)
+    skip(subscriber_id, list_id, connection_pool)
 )]
 pub async fn confirm_subscriber(
     connection_pool: &PgPool,
     subscriber_id: Uuid,
+    list_id: Uuid,
 ) -> Result<(), sqlx::Error> {
+    let mut transaction = connection_pool.begin().await?;
     sqlx::query!(
         r#"
-        UPDATE subscriptions SET status = 'confirmed' WHERE id = $1
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
         "#,
         subscriber_id,
     )
-    .execute(connection_pool)
+    .execute(&mut transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute confirm subscriber query: {:?}", e);
         e
     })?;
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND list_id = $2 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute confirm list subscription query: {:?}", e);
+        e
+    })?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Change the email address of a subscriber"
+    skip(new_email, subscription_token, connection_pool)
+)]
+pub async fn change_subscriber_email(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+    new_email: &str,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = connection_pool.begin().await?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET email = $1 WHERE id = $2"#,
+        new_email,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    // The token is single use, so that an older change cannot be replayed over a newer one
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscription_token = $1"#,
+        subscription_token,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
     Ok(())
 }
 
@@ -88,6 +166,8 @@ pub enum ConfirmError {
     DatabaseError(#[from] anyhow::Error),
     #[error("No subscriber found based on the given subscription token link")]
     SubscriptionIdNotFound,
+    #[error("The new email address is already subscribed")]
+    EmailAlreadySubscribed,
 }
 
 impl ResponseError for ConfirmError {
@@ -95,6 +175,7 @@ impl ResponseError for ConfirmError {
         match self {
             ConfirmError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ConfirmError::SubscriptionIdNotFound => StatusCode::UNAUTHORIZED,
+            ConfirmError::EmailAlreadySubscribed => StatusCode::CONFLICT,
         }
     }
 }
@@ -0,0 +1,158 @@
+use super::{get_subscriber, ManageSubscriptionError};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::manage_links::ManageLinks;
+use crate::personal_data::{
+    erase_personal_data, export_personal_data, get_erasure_request, store_erasure_request,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppression_list::SuppressionKey;
+use crate::utils::render_page;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscriber_id: Uuid,
+    signature: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ErasureParameters {
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "subscriptions_manage/erasure_requested.html")]
+struct ErasureRequestedTemplate;
+
+#[derive(Template)]
+#[template(path = "subscriptions_manage/confirm_erasure.html")]
+struct ConfirmErasureTemplate {
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "subscriptions_manage/data_erased.html")]
+struct DataErasedTemplate;
+
+#[tracing::instrument(
+    name = "Export personal data for a subscriber",
+    skip(parameters, connection_pool, manage_links, suppression_key),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn download_personal_data(
+    parameters: web::Query<Parameters>,
+    connection_pool: web::Data<PgPool>,
+    manage_links: web::Data<ManageLinks>,
+    suppression_key: web::Data<SuppressionKey>,
+) -> Result<HttpResponse, ManageSubscriptionError> {
+    let subscriber = get_subscriber(
+        &connection_pool,
+        &manage_links,
+        parameters.subscriber_id,
+        &parameters.signature,
+    )
+    .await?;
+    let personal_data = export_personal_data(&connection_pool, &suppression_key, &subscriber.email)
+        .await
+        .context("Failed to export personal data")?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("personal-data.json".into())],
+        })
+        .json(personal_data))
+}
+
+/// The manage link does not expire and is in every email sent, so erasure is only carried out
+/// once it has been confirmed from the subscriber's mailbox.
+#[tracing::instrument(
+    name = "Request the erasure of personal data for a subscriber",
+    skip(form, connection_pool, manage_links, email_client, base_url),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn erase_my_personal_data(
+    form: web::Form<Parameters>,
+    connection_pool: web::Data<PgPool>,
+    manage_links: web::Data<ManageLinks>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ManageSubscriptionError> {
+    let subscriber = get_subscriber(
+        &connection_pool,
+        &manage_links,
+        form.subscriber_id,
+        &form.signature,
+    )
+    .await?;
+    let email = SubscriberEmail::parse(subscriber.email).map_err(anyhow::Error::msg)?;
+    let token = store_erasure_request(&connection_pool, subscriber.id)
+        .await
+        .context("Failed to store an erasure request")?;
+    let erasure_link = format!(
+        "{}/subscriptions/manage/erase/confirm?token={}",
+        base_url.0, token
+    );
+
+    let plain_body = format!(
+        "Someone asked to delete your subscription and all the data we stored about you.\n\
+        Visit {} within the next hour to confirm.\n\n\
+        If it was not you, you can ignore this email.",
+        erasure_link
+    );
+    let html_body = format!(
+        "Someone asked to delete your subscription and all the data we stored about you. <br />\
+        Click <a href=\"{}\">here</a> within the next hour to confirm.\
+        <p>If it was not you, you can ignore this email.</p>",
+        erasure_link
+    );
+    email_client
+        .send_email(
+            &email,
+            "Confirm the deletion of your data",
+            &html_body,
+            &plain_body,
+        )
+        .await
+        .context("Failed to send the erasure confirmation email")?;
+    Ok(render_page(&ErasureRequestedTemplate).context("Failed to render page")?)
+}
+
+// The link only shows a form, so that a mail client following links does not erase anything
+pub async fn confirm_erasure_form(
+    parameters: web::Query<ErasureParameters>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ManageSubscriptionError> {
+    get_erasure_request(&connection_pool, &parameters.token)
+        .await
+        .context("Failed to look up the erasure request")?
+        .ok_or(ManageSubscriptionError::InvalidLink)?;
+    Ok(render_page(&ConfirmErasureTemplate {
+        token: parameters.0.token,
+    })
+    .context("Failed to render page")?)
+}
+
+#[tracing::instrument(
+    name = "Erase personal data for a subscriber",
+    skip(form, connection_pool, suppression_key)
+)]
+pub async fn confirm_erasure(
+    form: web::Form<ErasureParameters>,
+    connection_pool: web::Data<PgPool>,
+    suppression_key: web::Data<SuppressionKey>,
+) -> Result<HttpResponse, ManageSubscriptionError> {
+    let email = get_erasure_request(&connection_pool, &form.token)
+        .await
+        .context("Failed to look up the erasure request")?
+        .ok_or(ManageSubscriptionError::InvalidLink)?;
+    erase_personal_data(&connection_pool, &suppression_key, &email)
+        .await
+        .context("Failed to erase personal data")?;
+    Ok(render_page(&DataErasedTemplate).context("Failed to render page")?)
+}
@@ -0,0 +1,109 @@
+use super::{get_subscriber, ManageSubscriptionError};
+use crate::mailing_lists::get_lists;
+use crate::manage_links::ManageLinks;
+use crate::utils::render_page;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use chrono::Utc;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscriber_id: Uuid,
+    signature: String,
+}
+
+struct ListChoice {
+    list_id: Uuid,
+    name: String,
+    subscribed: bool,
+    pending: bool,
+}
+
+#[derive(Template)]
+#[template(path = "subscriptions_manage/preferences.html")]
+struct PreferencesTemplate<'a> {
+    flash_messages: IncomingFlashMessages,
+    subscriber_id: Uuid,
+    signature: &'a str,
+    name: &'a str,
+    email: &'a str,
+    lists: Vec<ListChoice>,
+    paused_until: String,
+}
+
+#[tracing::instrument(
+    name = "Show the subscription preference center",
+    skip(parameters, flash_messages, connection_pool, manage_links),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn manage_subscription_form(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    manage_links: web::Data<ManageLinks>,
+) -> Result<HttpResponse, ManageSubscriptionError> {
+    let subscriber = get_subscriber(
+        &connection_pool,
+        &manage_links,
+        parameters.subscriber_id,
+        &parameters.signature,
+    )
+    .await?;
+
+    let memberships = get_list_statuses(&connection_pool, subscriber.id)
+        .await
+        .context("Failed to fetch the list subscriptions of the subscriber")?;
+    let lists = get_lists(&connection_pool)
+        .await
+        .context("Failed to fetch mailing lists")?
+        .into_iter()
+        .map(|list| {
+            let status = memberships.get(&list.list_id).map(String::as_str);
+            ListChoice {
+                list_id: list.list_id,
+                name: list.name,
+                subscribed: status.is_some(),
+                pending: status.map_or(false, |status| status != "confirmed"),
+            }
+        })
+        .collect();
+
+    let paused_until = subscriber
+        .paused_until
+        .filter(|paused_until| *paused_until > Utc::now())
+        .map(|paused_until| paused_until.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+
+    Ok(render_page(&PreferencesTemplate {
+        flash_messages,
+        subscriber_id: subscriber.id,
+        signature: &parameters.signature,
+        name: &subscriber.name,
+        email: &subscriber.email,
+        lists,
+        paused_until,
+    })
+    .context("Failed to render page")?)
+}
+
+#[tracing::instrument(
+    name = "Get the list subscriptions of a subscriber",
+    skip(connection_pool)
+)]
+async fn get_list_statuses(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<HashMap<Uuid, String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT list_id, status FROM list_subscriptions WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_all(connection_pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.list_id, r.status)).collect())
+}
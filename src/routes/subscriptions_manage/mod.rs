@@ -0,0 +1,73 @@
+mod data;
+pub use data::{
+    confirm_erasure, confirm_erasure_form, download_personal_data, erase_my_personal_data,
+};
+mod get;
+pub use get::manage_subscription_form;
+mod post;
+pub use post::update_subscription;
+
+use crate::manage_links::ManageLinks;
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::ResponseError;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    paused_until: Option<DateTime<Utc>>,
+}
+
+/// Returns the subscriber the signed link was issued for.
+async fn get_subscriber(
+    connection_pool: &PgPool,
+    manage_links: &ManageLinks,
+    subscriber_id: Uuid,
+    signature: &str,
+) -> Result<Subscriber, ManageSubscriptionError> {
+    if !manage_links.verify(subscriber_id, signature) {
+        return Err(ManageSubscriptionError::InvalidLink);
+    }
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, paused_until
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(connection_pool)
+    .await
+    .map_err(|e| ManageSubscriptionError::UnexpectedError(e.into()))?
+    // The subscriber may have been deleted since the link was sent
+    .ok_or(ManageSubscriptionError::InvalidLink)
+}
+
+#[derive(thiserror::Error)]
+pub enum ManageSubscriptionError {
+    #[error("This link is invalid or has expired")]
+    InvalidLink,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for ManageSubscriptionError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ManageSubscriptionError::InvalidLink => StatusCode::UNAUTHORIZED,
+            ManageSubscriptionError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Debug for ManageSubscriptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
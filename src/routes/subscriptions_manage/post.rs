@@ -0,0 +1,337 @@
+use super::{get_subscriber, ManageSubscriptionError, Subscriber};
+use crate::confirmation_throttle::{
+    get_unexpired_email_change_token, try_reserve_confirmation_email,
+};
+use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::mailing_lists::get_lists;
+use crate::manage_links::ManageLinks;
+use crate::routes::generate_subscription_token;
+use crate::startup::ApplicationBaseUrl;
+use crate::suppression_list::{is_suppressed_or_erased, SuppressionKey};
+use crate::utils::see_other;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    subscriber_id: Uuid,
+    signature: String,
+    name: String,
+    email: String,
+    paused_until: String,
+    // One `list_<list id>` checkbox per mailing list, only submitted when ticked
+    #[serde(flatten)]
+    lists: HashMap<String, String>,
+}
+
+struct Preferences {
+    name: SubscriberName,
+    email: SubscriberEmail,
+    paused_until: Option<DateTime<Utc>>,
+    list_ids: Vec<Uuid>,
+}
+
+fn parse_preferences(form: FormData) -> Result<Preferences, String> {
+    let name = SubscriberName::parse(form.name)?;
+    let email = SubscriberEmail::parse(form.email)?;
+    let paused_until = match form.paused_until.trim() {
+        "" => None,
+        date => {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| format!("{} is not a valid date.", date))?;
+            let paused_until = DateTime::<Utc>::from_utc(date.and_hms(0, 0, 0), Utc);
+            if paused_until <= Utc::now() {
+                return Err("The pause must end in the future.".into());
+            }
+            Some(paused_until)
+        }
+    };
+    let list_ids = form
+        .lists
+        .keys()
+        .filter_map(|key| key.strip_prefix("list_"))
+        .map(|list_id| {
+            Uuid::parse_str(list_id).map_err(|_| format!("{} is not a valid list.", list_id))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Preferences {
+        name,
+        email,
+        paused_until,
+        list_ids,
+    })
+}
+
+#[tracing::instrument(
+    name = "Update subscription preferences",
+    skip(form, connection_pool, email_client, base_url, manage_links, suppression_key),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn update_subscription(
+    form: web::Form<FormData>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    manage_links: web::Data<ManageLinks>,
+    suppression_key: web::Data<SuppressionKey>,
+) -> Result<HttpResponse, ManageSubscriptionError> {
+    let subscriber = get_subscriber(
+        &connection_pool,
+        &manage_links,
+        form.subscriber_id,
+        &form.signature,
+    )
+    .await?;
+    let redirect_to = manage_links.path(subscriber.id);
+
+    let mut preferences = match parse_preferences(form.0) {
+        Ok(preferences) => preferences,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&redirect_to));
+        }
+    };
+    // Ignore lists that have been deleted since the form was rendered
+    let known_lists: Vec<Uuid> = get_lists(&connection_pool)
+        .await
+        .context("Failed to fetch mailing lists")?
+        .into_iter()
+        .map(|list| list.list_id)
+        .collect();
+    preferences
+        .list_ids
+        .retain(|list_id| known_lists.contains(list_id));
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let joined_lists = update_preferences(&mut transaction, &subscriber, &preferences)
+        .await
+        .context("Failed to update the subscription preferences")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update subscription preferences")?;
+
+    if !preferences
+        .email
+        .as_ref()
+        .eq_ignore_ascii_case(&subscriber.email)
+    {
+        // The address only changes once the subscriber proves they own it
+        let sent = request_email_change(
+            &connection_pool,
+            &email_client,
+            &base_url.0,
+            &suppression_key,
+            subscriber.id,
+            &preferences.email,
+        )
+        .await?;
+        if sent {
+            FlashMessage::info(format!(
+                "We sent a confirmation link to {}. Your address will change once you click it.",
+                preferences.email.as_ref()
+            ))
+            .send();
+        } else {
+            FlashMessage::error(format!(
+                "Too many confirmation emails were sent to {}. Please try again later.",
+                preferences.email.as_ref()
+            ))
+            .send();
+        }
+    }
+    if !joined_lists {
+        FlashMessage::error("Please confirm your subscription before joining other lists.").send();
+    }
+    FlashMessage::info("Your preferences have been updated.").send();
+    Ok(see_other(&redirect_to))
+}
+
+/// Returns `false` if the subscriber asked to join lists they cannot join yet.
+#[tracing::instrument(name = "Save subscription preferences", skip_all)]
+async fn update_preferences(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber: &Subscriber,
+    preferences: &Preferences,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = $1, paused_until = $2 WHERE id = $3"#,
+        preferences.name.as_ref(),
+        preferences.paused_until,
+        subscriber.id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM list_subscriptions
+        WHERE subscriber_id = $1 AND NOT (list_id = ANY($2))
+        "#,
+        subscriber.id,
+        &preferences.list_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    // The signed link proves ownership of the address, so a confirmed subscriber
+    // joins new lists straight away. Until then there is no confirmation that would cover them.
+    if subscriber.status != "confirmed" {
+        let n_new_lists = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "n_new_lists!"
+            FROM UNNEST($2::uuid[]) AS requested(list_id)
+            WHERE NOT EXISTS (
+                SELECT 1 FROM list_subscriptions ls
+                WHERE ls.subscriber_id = $1 AND ls.list_id = requested.list_id
+            )
+            "#,
+            subscriber.id,
+            &preferences.list_ids
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        return Ok(n_new_lists == 0);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (subscriber_id, list_id, status, subscribed_at)
+        SELECT $1, list_id, 'confirmed', now()
+        FROM UNNEST($2::uuid[]) AS list_id
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber.id,
+        &preferences.list_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(true)
+}
+
+#[tracing::instrument(
+    name = "Request an email address change",
+    skip(connection_pool, email_client, base_url, suppression_key, new_email)
+)]
+async fn request_email_change(
+    connection_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    suppression_key: &SuppressionKey,
+    subscriber_id: Uuid,
+    new_email: &SubscriberEmail,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // Throttle every request for the address, whether or not the email goes out, so that the
+    // reply does not disclose anything either
+    if !try_reserve_confirmation_email(&mut transaction, new_email.as_ref())
+        .await
+        .context("Failed to check the confirmation email throttle")?
+    {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to record a confirmation email")?;
+        return Ok(false);
+    }
+
+    // Respond as if the change went through so that existing subscribers
+    // and the suppression list are not disclosed
+    let skip =
+        if is_suppressed_or_erased(connection_pool, suppression_key, new_email.as_ref()).await? {
+            tracing::warn!("Skipping an email change to a suppressed address");
+            true
+        } else if sqlx::query!(
+            r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1)"#,
+            new_email.as_ref()
+        )
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to check email against subscriber database")?
+        .is_some()
+        {
+            tracing::warn!("Skipping an email change to an address that is already subscribed");
+            true
+        } else {
+            false
+        };
+    if skip {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to record a confirmation email")?;
+        return Ok(true);
+    }
+
+    let subscription_token =
+        get_or_store_email_change_token(&mut transaction, subscriber_id, new_email.as_ref())
+            .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an email change token")?;
+
+    let confirmation_link = format!(
+        "{}/subscribe/confirm?subscription_token={}",
+        base_url, subscription_token
+    );
+    let plain_body = format!(
+        "Visit {} to confirm your new email address.",
+        confirmation_link
+    );
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to confirm your new email address.",
+        confirmation_link
+    );
+    email_client
+        .send_email(
+            new_email,
+            "Confirm your new email address",
+            &html_body,
+            &plain_body,
+        )
+        .await
+        .context("Failed to send email change confirmation")?;
+    Ok(true)
+}
+
+/// Returns the unexpired token of a change to `new_email`, storing a fresh one if there is none,
+/// so that asking again does not pile up tokens.
+async fn get_or_store_email_change_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_email: &str,
+) -> Result<String, anyhow::Error> {
+    if let Some(subscription_token) =
+        get_unexpired_email_change_token(transaction, subscriber_id, new_email)
+            .await
+            .context("Failed to look up an unexpired email change token")?
+    {
+        return Ok(subscription_token);
+    }
+    let subscription_token = generate_subscription_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, new_email)
+        VALUES ($1, $2, $3)
+        "#,
+        subscription_token,
+        subscriber_id,
+        new_email
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to store email change token in database")?;
+    Ok(subscription_token)
+}
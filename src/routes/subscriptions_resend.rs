@@ -0,0 +1,128 @@
+use crate::confirmation_throttle::try_reserve_confirmation_email;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::manage_links::ManageLinks;
+use crate::routes::{get_or_store_token, send_confirmation_email, SubscribeError};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppression_list::is_suppressed;
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+    email: String,
+}
+
+#[derive(Template)]
+#[template(path = "resend_confirmation.html")]
+struct ResendConfirmationTemplate;
+
+#[derive(Template)]
+#[template(path = "confirmation_resent.html")]
+struct ConfirmationResentTemplate<'a> {
+    email: &'a str,
+}
+
+pub async fn resend_confirmation_form() -> Result<HttpResponse, actix_web::Error> {
+    render_page(&ResendConfirmationTemplate).map_err(e500)
+}
+
+#[tracing::instrument(
+    name = "Resend a confirmation email on request",
+    skip(form, connection_pool, email_client, base_url, manage_links),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation_email(
+    form: web::Form<ResendFormData>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    manage_links: web::Data<ManageLinks>,
+) -> Result<HttpResponse, SubscribeError> {
+    let email = SubscriberEmail::parse(form.0.email).map_err(SubscribeError::ValidationError)?;
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    // Throttle every request for the address, whether or not it is subscribed, so that the
+    // response does not disclose who is on the list
+    if !try_reserve_confirmation_email(&mut transaction, email.as_ref())
+        .await
+        .context("Failed to check the confirmation email throttle")?
+    {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to record a confirmation email")?;
+        return Err(SubscribeError::TooManyRequests);
+    }
+
+    let pending = sqlx::query!(
+        r#"
+        SELECT s.id, s.name, ls.list_id
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        WHERE lower(s.email) = lower($1) AND ls.status = 'pending_confirmation'
+        ORDER BY ls.subscribed_at DESC
+        LIMIT 1
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to look up the pending subscription")?;
+
+    let pending = match pending {
+        Some(pending)
+            if !is_suppressed(&connection_pool, email.as_ref())
+                .await
+                .context("Failed to check email against the suppression list")? =>
+        {
+            pending
+        }
+        _ => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to record a confirmation email")?;
+            return Ok(resent_page(&email)?);
+        }
+    };
+
+    let subscription_token =
+        get_or_store_token(&mut transaction, pending.id, pending.list_id).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a subscription token")?;
+
+    let response = resent_page(&email)?;
+    let new_subscriber = NewSubscriber {
+        email,
+        name: SubscriberName::parse(pending.name).map_err(anyhow::Error::msg)?,
+    };
+    send_confirmation_email(
+        &connection_pool,
+        &email_client,
+        new_subscriber,
+        &base_url.0,
+        &subscription_token,
+        &manage_links.link(pending.id),
+    )
+    .await
+    .context("Failed to send confirmation email")?;
+
+    Ok(response)
+}
+
+fn resent_page(email: &SubscriberEmail) -> Result<HttpResponse, anyhow::Error> {
+    render_page(&ConfirmationResentTemplate {
+        email: email.as_ref(),
+    })
+    .context("Failed to render the confirmation page")
+}
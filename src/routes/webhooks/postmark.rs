@@ -0,0 +1,222 @@
+use crate::authentication::{tokens_match, Credentials};
+use crate::configuration::WebhookSettings;
+use crate::routes::error_chain_fmt;
+use crate::suppression_list::suppress_email;
+use actix_web::http::header::{HeaderMap, HeaderValue};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+
+// Only the fields we act upon are deserialized - Postmark sends many more
+#[derive(serde::Deserialize)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    Bounce {
+        #[serde(rename = "MessageID")]
+        message_id: String,
+        #[serde(rename = "Type")]
+        bounce_type: String,
+        #[serde(rename = "Email")]
+        email: String,
+    },
+    SpamComplaint {
+        #[serde(rename = "MessageID")]
+        message_id: String,
+        #[serde(rename = "Email")]
+        email: String,
+    },
+    Delivery {
+        #[serde(rename = "MessageID")]
+        message_id: String,
+    },
+    // Opens, clicks, etc. are acknowledged and ignored
+    #[serde(other)]
+    Other,
+}
+
+#[tracing::instrument(
+    name = "Handle a Postmark webhook event",
+    skip(body, request, connection_pool, webhook_settings)
+)]
+pub async fn postmark_webhook(
+    body: web::Bytes,
+    request: HttpRequest,
+    connection_pool: web::Data<PgPool>,
+    webhook_settings: web::Data<WebhookSettings>,
+) -> Result<HttpResponse, WebhookError> {
+    let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+    // Both fields are compared in full, so that the time taken does not leak how much matched
+    let username_matches = tokens_match(&credentials.username, &webhook_settings.username);
+    let password_matches = tokens_match(
+        credentials.password.expose_secret(),
+        webhook_settings.password.expose_secret(),
+    );
+    if !(username_matches && password_matches) {
+        return Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook credentials."
+        )));
+    }
+    // The body is only parsed once the caller is known to be the email provider
+    let event: PostmarkEvent =
+        serde_json::from_slice(&body).map_err(WebhookError::InvalidPayload)?;
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    match event {
+        PostmarkEvent::Bounce {
+            message_id,
+            bounce_type,
+            email,
+        } => {
+            // Soft bounces (full mailbox, auto-responders, ...) are transient - keep mailing the address
+            let outcome = if bounce_type == "HardBounce" {
+                update_subscriber_status(&mut transaction, &email, "bounced")
+                    .await
+                    .context("Failed to mark the subscriber as bounced")?;
+                suppress_email(&mut transaction, &email, "bounced")
+                    .await
+                    .context("Failed to suppress the bounced address")?;
+                "bounced"
+            } else {
+                "soft_bounced"
+            };
+            update_delivery_outcome(&mut transaction, &message_id, outcome)
+                .await
+                .context("Failed to record the bounce in the delivery log")?;
+        }
+        PostmarkEvent::SpamComplaint { message_id, email } => {
+            update_subscriber_status(&mut transaction, &email, "complained")
+                .await
+                .context("Failed to mark the subscriber as complained")?;
+            suppress_email(&mut transaction, &email, "complained")
+                .await
+                .context("Failed to suppress the complaining address")?;
+            update_delivery_outcome(&mut transaction, &message_id, "complained")
+                .await
+                .context("Failed to record the spam complaint in the delivery log")?;
+        }
+        PostmarkEvent::Delivery { message_id } => {
+            update_delivery_outcome(&mut transaction, &message_id, "delivered")
+                .await
+                .context("Failed to record the delivery in the delivery log")?;
+        }
+        PostmarkEvent::Other => {}
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to handle a webhook event")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    // The header value, if present, must be a valid UTF8 string
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::decode_config(base64encoded_segment, base64::STANDARD)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    // Split into two segments, using ':' as delimiter
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
+        .to_string();
+
+    Ok(Credentials {
+        username,
+        password: Secret::new(password),
+    })
+}
+
+#[tracing::instrument(name = "Update subscriber status", skip(transaction))]
+async fn update_subscriber_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = $1 WHERE email = $2
+        "#,
+        status,
+        email
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Update delivery outcome", skip(transaction))]
+async fn update_delivery_outcome(
+    transaction: &mut Transaction<'_, Postgres>,
+    provider_message_id: &str,
+    outcome: &str,
+) -> Result<(), sqlx::Error> {
+    // Confirmation emails are not part of the delivery log - their events match no row
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_deliveries SET outcome = $1 WHERE provider_message_id = $2
+        "#,
+        outcome,
+        provider_message_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("The webhook payload is invalid.")]
+    InvalidPayload(#[source] serde_json::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    // Implement error_response instead of status_code to insert a custom header
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            WebhookError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            WebhookError::InvalidPayload(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            WebhookError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="postmark""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+        }
+    }
+}
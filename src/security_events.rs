@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// Authentication events kept for security review
+pub const LOGIN_SUCCEEDED: &str = "login_succeeded";
+pub const LOGIN_FAILED: &str = "login_failed";
+pub const LOGIN_WHILE_LOCKED: &str = "login_while_locked";
+pub const ACCOUNT_LOCKED: &str = "account_locked";
+pub const LOCKOUT_CLEARED: &str = "lockout_cleared";
+pub const TWO_FACTOR_ENABLED: &str = "two_factor_enabled";
+pub const TWO_FACTOR_DISABLED: &str = "two_factor_disabled";
+pub const PASSWORD_RESET_REQUESTED: &str = "password_reset_requested";
+pub const PASSWORD_RESET: &str = "password_reset";
+pub const USER_CREATED: &str = "user_created";
+pub const USER_ROLE_CHANGED: &str = "user_role_changed";
+pub const USER_DISABLED: &str = "user_disabled";
+pub const USER_ENABLED: &str = "user_enabled";
+pub const USER_DELETED: &str = "user_deleted";
+
+pub struct SecurityEvent {
+    pub event_type: String,
+    pub username: String,
+    pub details: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Stores the event and mirrors it to the logs.
+#[tracing::instrument(name = "Record a security event", skip(connection_pool))]
+pub async fn record_security_event(
+    connection_pool: &PgPool,
+    event_type: &str,
+    username: &str,
+    user_id: Option<Uuid>,
+    details: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    tracing::info!(
+        security_event = event_type,
+        username,
+        details,
+        "Security event"
+    );
+    sqlx::query!(
+        r#"
+        INSERT INTO security_events (event_id, event_type, username, user_id, details, occurred_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        event_type,
+        username,
+        user_id,
+        details
+    )
+    .execute(connection_pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get recent security events", skip(connection_pool))]
+pub async fn get_recent_security_events(
+    connection_pool: &PgPool,
+    limit: i64,
+) -> Result<Vec<SecurityEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        SecurityEvent,
+        r#"
+        SELECT event_type, username, details, occurred_at
+        FROM security_events
+        ORDER BY occurred_at DESC
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(connection_pool)
+    .await
+}
@@ -0,0 +1,142 @@
+use crate::configuration::SecurityHeadersSettings;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::web;
+use actix_web_lab::middleware::Next;
+use anyhow::Context;
+
+/// The configured security headers, checked once at startup.
+#[derive(Debug)]
+pub struct SecurityHeaders(Vec<(HeaderName, HeaderValue)>);
+
+impl SecurityHeaders {
+    pub fn new(settings: &SecurityHeadersSettings) -> Result<Self, anyhow::Error> {
+        header_values(settings)
+            .into_iter()
+            .map(|(name, value)| {
+                let header_value = HeaderValue::from_str(&value).with_context(|| {
+                    format!("Invalid value for the {} header: {:?}", name, value)
+                })?;
+                Ok((name, header_value))
+            })
+            .collect::<Result<_, anyhow::Error>>()
+            .map(Self)
+    }
+
+    fn add_to(&self, headers: &mut HeaderMap) {
+        for (name, value) in &self.0 {
+            if !headers.contains_key(name) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Adds the security headers to every response, error responses included.
+/// Handlers can still set their own value of a header, which is then left as is.
+pub async fn add_security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let security_headers = req
+        .app_data::<web::Data<SecurityHeaders>>()
+        .expect("The security headers are missing from the application data")
+        .clone();
+
+    match next.call(req).await {
+        Ok(mut response) => {
+            security_headers.add_to(response.headers_mut());
+            Ok(response)
+        }
+        // Errors of other middleware only become a response further out, so it is built here
+        Err(e) => {
+            let mut response = e.error_response();
+            security_headers.add_to(response.headers_mut());
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+fn header_values(settings: &SecurityHeadersSettings) -> Vec<(HeaderName, String)> {
+    let mut headers = vec![
+        (
+            header::CONTENT_SECURITY_POLICY,
+            settings.content_security_policy.clone(),
+        ),
+        (header::X_FRAME_OPTIONS, settings.frame_options.clone()),
+        (header::REFERRER_POLICY, settings.referrer_policy.clone()),
+    ];
+    if settings.content_type_nosniff {
+        headers.push((header::X_CONTENT_TYPE_OPTIONS, "nosniff".into()));
+    }
+    if settings.hsts_max_age_seconds > 0 {
+        headers.push((
+            header::STRICT_TRANSPORT_SECURITY,
+            format!("max-age={}", settings.hsts_max_age_seconds),
+        ));
+    }
+    headers.retain(|(_, value)| !value.trim().is_empty());
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{header_values, SecurityHeaders};
+    use crate::configuration::SecurityHeadersSettings;
+    use actix_web::http::header;
+    use claim::assert_err;
+
+    fn settings() -> SecurityHeadersSettings {
+        SecurityHeadersSettings {
+            content_security_policy: "default-src 'self'".into(),
+            frame_options: "DENY".into(),
+            referrer_policy: "same-origin".into(),
+            content_type_nosniff: true,
+            hsts_max_age_seconds: 0,
+        }
+    }
+
+    #[test]
+    fn hsts_is_only_sent_with_a_max_age() {
+        let names: Vec<_> = header_values(&settings())
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert!(!names.contains(&header::STRICT_TRANSPORT_SECURITY));
+
+        let settings = SecurityHeadersSettings {
+            hsts_max_age_seconds: 31536000,
+            ..settings()
+        };
+        assert!(header_values(&settings)
+            .contains(&(header::STRICT_TRANSPORT_SECURITY, "max-age=31536000".into())));
+    }
+
+    #[test]
+    fn empty_values_leave_a_header_out() {
+        let settings = SecurityHeadersSettings {
+            content_security_policy: "".into(),
+            content_type_nosniff: false,
+            ..settings()
+        };
+        let names: Vec<_> = header_values(&settings)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            vec![header::X_FRAME_OPTIONS, header::REFERRER_POLICY]
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_rejected_at_startup() {
+        let settings = SecurityHeadersSettings {
+            referrer_policy: "same-origin\nx-injected: 1".into(),
+            ..settings()
+        };
+        assert_err!(SecurityHeaders::new(&settings));
+    }
+}
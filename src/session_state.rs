@@ -8,8 +8,20 @@ use uuid::Uuid;
 // Wrap Session in a strongly-typed API to access and modify session state
 pub struct TypedSession(Session);
 
+/// An admin who entered the right password and still has to enter a second factor.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PendingTwoFactor {
+    pub user_id: Uuid,
+    pub username: String,
+    // Unix timestamp, so that the second step can expire
+    pub started_at: i64,
+}
+
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const PENDING_TWO_FACTOR_KEY: &'static str = "pending_two_factor";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -23,6 +35,38 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    // Identifies the session in `user_sessions`, unlike the session key it survives renewals
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, serde_json::Error> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    pub fn insert_pending_two_factor(
+        &self,
+        pending: &PendingTwoFactor,
+    ) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::PENDING_TWO_FACTOR_KEY, pending)
+    }
+
+    pub fn get_pending_two_factor(&self) -> Result<Option<PendingTwoFactor>, serde_json::Error> {
+        self.0.get(Self::PENDING_TWO_FACTOR_KEY)
+    }
+
+    pub fn remove_pending_two_factor(&self) {
+        self.0.remove(Self::PENDING_TWO_FACTOR_KEY);
+    }
+
+    pub fn insert_csrf_token(&self, csrf_token: &str) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, csrf_token)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, serde_json::Error> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
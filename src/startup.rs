@@ -1,22 +1,49 @@
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::authentication::{
+    authenticate_api_tokens, reject_anonymous_users, reject_invalid_csrf_tokens,
+    reject_unauthorized_users, PasswordHashing, PasswordPolicy, TotpCipher,
+};
+use crate::client_ip::TrustedProxies;
+use crate::configuration::{
+    DatabaseSettings, PasswordHashingSettings, PasswordPolicySettings, RateLimitSettings,
+    SecurityHeadersSettings, Settings, WebhookSettings,
+};
+use crate::manage_links::ManageLinks;
+use crate::rate_limiting::{rate_limit, KeyScope, RateLimiter};
 use crate::routes::send_newsletter;
+use crate::security_headers::{add_security_headers, SecurityHeaders};
+use crate::suppression_list::SuppressionKey;
 use crate::{
     email_client::EmailClient,
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm, health_check, home,
-        log_out, login, login_form, submit_newsletter_to_send_form, subscribe,
+        accept_user_invitation, admin_dashboard, admin_sessions, admin_users, api_tokens,
+        browse_subscribers, change_password, change_password_form, change_recovery_email,
+        change_user_role, clear_lockout, confirm, confirm_erasure, confirm_erasure_form,
+        confirm_recovery_email, confirm_two_factor_enrolment, create_admin_api_token, create_issue,
+        create_mailing_list, create_subscriber, delivery_log, download_personal_data,
+        erase_my_personal_data, erase_personal_data_of, export_personal_data_of,
+        export_subscribers, health_check, home, import_subscribers, import_subscribers_form,
+        invalid_request, invitation_form, invite_admin_user, issue_details, issue_stats,
+        list_issues, list_subscribers, log_out, login, login_form, login_lockouts,
+        login_two_factor, login_two_factor_form, mailing_lists_form, manage_subscription_form,
+        new_password_form, openapi_document, password_reset_form, postmark_webhook, privacy_form,
+        publish_issue, recovery_email_form, remove_subscriber, remove_suppression,
+        request_password_reset, resend_confirmation_email, resend_confirmation_form,
+        reset_password, revoke_admin_api_token, revoke_admin_invitation, revoke_admin_session,
+        start_two_factor_enrolment, submit_newsletter_to_send_form, subscribe, subscribe_form,
+        subscriber_details, subscriber_tags_form, suppression_list, turn_off_two_factor,
+        two_factor_settings, update_admin_user, update_subscriber, update_subscriber_tag,
+        update_subscription,
     },
 };
 use actix_session::{storage::RedisSessionStore, SessionMiddleware};
 use actix_web::cookie::Key;
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_web::{dev::Server, guard, web, App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_lab::middleware::from_fn;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::net::TcpListener;
+use std::net::{IpAddr, TcpListener};
 use tracing_actix_web::TracingLogger;
 
 /// A new type to hold the server that is and its port
@@ -43,7 +70,14 @@ impl Application {
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            configuration.application.totp_encryption_key,
+            configuration.application.trusted_proxies,
             configuration.redis_uri,
+            configuration.postmark_webhook,
+            configuration.rate_limits,
+            configuration.password_hashing,
+            configuration.password_policy,
+            configuration.security_headers,
         )
         .await?;
         Ok(Self { port, server })
@@ -68,23 +102,45 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 // Retrieval from the context, in actix-web, is type-based: using a raw 'String' would expose conflicts
 pub struct ApplicationBaseUrl(pub String);
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     connection_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
     hmac_secret: Secret<String>,
+    totp_encryption_key: Secret<String>,
+    trusted_proxies: Vec<IpAddr>,
     redis_uri: Secret<String>,
+    webhook_settings: WebhookSettings,
+    rate_limit_settings: RateLimitSettings,
+    password_hashing_settings: PasswordHashingSettings,
+    password_policy_settings: PasswordPolicySettings,
+    security_headers_settings: SecurityHeadersSettings,
 ) -> Result<Server, anyhow::Error> {
     let connection_pool = web::Data::new(connection_pool);
     let email_client = web::Data::new(email_client);
+    let manage_links = web::Data::new(ManageLinks::new(base_url.clone(), hmac_secret.clone()));
+    let suppression_key = web::Data::new(SuppressionKey::new(hmac_secret.clone()));
+    let trusted_proxies = web::Data::new(TrustedProxies::new(trusted_proxies));
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let webhook_settings = web::Data::new(webhook_settings);
+    let totp_cipher = web::Data::new(TotpCipher::new(&totp_encryption_key)?);
+    let password_hashing = web::Data::new(PasswordHashing::new(&password_hashing_settings)?);
+    let password_policy = web::Data::new(PasswordPolicy::new(&password_policy_settings)?);
+    let security_headers = web::Data::new(SecurityHeaders::new(&security_headers_settings)?);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let rate_limiter = web::Data::new(
+        RateLimiter::new(redis_uri.expose_secret(), rate_limit_settings.key_prefix).await?,
+    );
+    let subscribe_limit = rate_limit_settings.subscribe;
+    let login_limit = rate_limit_settings.login;
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(from_fn(add_security_headers))
             .wrap(TracingLogger::default())
             .wrap(message_framework.clone())
             .wrap(SessionMiddleware::new(
@@ -92,13 +148,157 @@ pub async fn run(
                 secret_key.clone(),
             ))
             .route("/", web::get().to(home))
-            .route("/login", web::post().to(login))
+            .service(
+                web::resource("/login")
+                    .guard(guard::Post())
+                    .wrap(from_fn(move |req, next| {
+                        rate_limit(
+                            req,
+                            next,
+                            "login",
+                            "username",
+                            KeyScope::PerClient,
+                            login_limit,
+                        )
+                    }))
+                    .to(login),
+            )
             .route("/login", web::get().to(login_form))
+            .route("/login/two_factor", web::get().to(login_two_factor_form))
+            .service(
+                web::resource("/login/two_factor")
+                    .guard(guard::Post())
+                    // The account lockout alone would leave six digit codes open to guessing
+                    .wrap(from_fn(move |req, next| {
+                        rate_limit(
+                            req,
+                            next,
+                            "login_two_factor",
+                            "code",
+                            KeyScope::PerClient,
+                            login_limit,
+                        )
+                    }))
+                    .to(login_two_factor),
+            )
+            .route("/password_reset", web::get().to(password_reset_form))
+            .service(
+                web::resource("/password_reset")
+                    .guard(guard::Post())
+                    .wrap(from_fn(move |req, next| {
+                        rate_limit(
+                            req,
+                            next,
+                            "password_reset",
+                            "username",
+                            KeyScope::Shared,
+                            login_limit,
+                        )
+                    }))
+                    .to(request_password_reset),
+            )
+            .route("/password_reset/confirm", web::get().to(new_password_form))
+            .service(
+                web::resource("/password_reset/confirm")
+                    .guard(guard::Post())
+                    .wrap(from_fn(move |req, next| {
+                        rate_limit(
+                            req,
+                            next,
+                            "password_reset_confirm",
+                            "token",
+                            KeyScope::PerClient,
+                            login_limit,
+                        )
+                    }))
+                    .to(reset_password),
+            )
+            .route(
+                "/recovery_email/confirm",
+                web::get().to(confirm_recovery_email),
+            )
+            .route("/invitations/accept", web::get().to(invitation_form))
+            .route(
+                "/invitations/accept",
+                web::post().to(accept_user_invitation),
+            )
             .route("/health_check", web::get().to(health_check))
-            .route("/subscribe", web::post().to(subscribe))
+            .route("/subscribe", web::get().to(subscribe_form))
+            .service(
+                web::resource("/subscribe")
+                    .guard(guard::Post())
+                    .wrap(from_fn(move |req, next| {
+                        rate_limit(
+                            req,
+                            next,
+                            "subscribe",
+                            "email",
+                            KeyScope::Shared,
+                            subscribe_limit,
+                        )
+                    }))
+                    .to(subscribe),
+            )
             .route("/subscribe/confirm", web::get().to(confirm))
+            .route("/subscribe/resend", web::get().to(resend_confirmation_form))
+            .service(
+                web::resource("/subscribe/resend")
+                    .guard(guard::Post())
+                    .wrap(from_fn(move |req, next| {
+                        rate_limit(
+                            req,
+                            next,
+                            "subscribe",
+                            "email",
+                            KeyScope::Shared,
+                            subscribe_limit,
+                        )
+                    }))
+                    .to(resend_confirmation_email),
+            )
+            .route(
+                "/subscriptions/manage",
+                web::get().to(manage_subscription_form),
+            )
+            .service(
+                web::resource("/subscriptions/manage")
+                    .guard(guard::Post())
+                    // Changing the address sends a confirmation email, like subscribing does
+                    .wrap(from_fn(move |req, next| {
+                        rate_limit(
+                            req,
+                            next,
+                            "subscribe",
+                            "email",
+                            KeyScope::Shared,
+                            subscribe_limit,
+                        )
+                    }))
+                    .to(update_subscription),
+            )
+            .route(
+                "/subscriptions/manage/data",
+                web::get().to(download_personal_data),
+            )
+            .route(
+                "/subscriptions/manage/erase",
+                web::post().to(erase_my_personal_data),
+            )
+            .route(
+                "/subscriptions/manage/erase/confirm",
+                web::get().to(confirm_erasure_form),
+            )
+            .route(
+                "/subscriptions/manage/erase/confirm",
+                web::post().to(confirm_erasure),
+            )
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
             .service(
                 web::scope("/admin")
+                    // Middleware registered last runs first: the session is checked before the role,
+                    // and the role before the CSRF token
+                    .wrap(from_fn(reject_invalid_csrf_tokens))
+                    .wrap(from_fn(reject_unauthorized_users))
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/password", web::get().to(change_password_form))
                     .route("/dashboard", web::get().to(admin_dashboard))
@@ -108,11 +308,102 @@ pub async fn run(
                         "/newsletters",
                         web::get().to(submit_newsletter_to_send_form),
                     )
-                    .route("/newsletters", web::post().to(send_newsletter)),
+                    .route("/newsletters", web::post().to(send_newsletter))
+                    .route("/lists", web::get().to(mailing_lists_form))
+                    .route("/lists", web::post().to(create_mailing_list))
+                    .route("/deliveries", web::get().to(delivery_log))
+                    .route("/suppressions", web::get().to(suppression_list))
+                    .route("/suppressions/remove", web::post().to(remove_suppression))
+                    .route("/subscribers", web::get().to(browse_subscribers))
+                    .route("/subscribers", web::post().to(update_subscriber))
+                    .route(
+                        "/subscribers/import",
+                        web::get().to(import_subscribers_form),
+                    )
+                    .route("/subscribers/import", web::post().to(import_subscribers))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route("/privacy", web::get().to(privacy_form))
+                    .route("/privacy/export", web::get().to(export_personal_data_of))
+                    .route("/privacy/erase", web::post().to(erase_personal_data_of))
+                    .route("/tags", web::get().to(subscriber_tags_form))
+                    .route("/tags", web::post().to(update_subscriber_tag))
+                    .route("/lockouts", web::get().to(login_lockouts))
+                    .route("/lockouts/clear", web::post().to(clear_lockout))
+                    .route("/recovery_email", web::get().to(recovery_email_form))
+                    .route("/recovery_email", web::post().to(change_recovery_email))
+                    .route("/sessions", web::get().to(admin_sessions))
+                    .route("/sessions/revoke", web::post().to(revoke_admin_session))
+                    .route("/api_tokens", web::get().to(api_tokens))
+                    .route("/api_tokens", web::post().to(create_admin_api_token))
+                    .route("/api_tokens/revoke", web::post().to(revoke_admin_api_token))
+                    .route("/two_factor", web::get().to(two_factor_settings))
+                    .route(
+                        "/two_factor/enroll",
+                        web::post().to(start_two_factor_enrolment),
+                    )
+                    .route(
+                        "/two_factor/confirm",
+                        web::post().to(confirm_two_factor_enrolment),
+                    )
+                    .route("/two_factor/disable", web::post().to(turn_off_two_factor))
+                    .route("/users", web::get().to(admin_users))
+                    .route("/users/invite", web::post().to(invite_admin_user))
+                    .route(
+                        "/users/invitations/revoke",
+                        web::post().to(revoke_admin_invitation),
+                    )
+                    .route("/users/role", web::post().to(change_user_role))
+                    .route("/users/update", web::post().to(update_admin_user)),
+            )
+            .route("/api/v1/openapi.json", web::get().to(openapi_document))
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_csrf_tokens))
+                    .wrap(from_fn(reject_unauthorized_users))
+                    .wrap(from_fn(reject_anonymous_users))
+                    // Requests with an API token skip the session and CSRF checks
+                    .wrap(from_fn(authenticate_api_tokens))
+                    // Requests that cannot be read get a JSON error body too
+                    .app_data(web::JsonConfig::default().error_handler(invalid_request))
+                    .app_data(web::QueryConfig::default().error_handler(invalid_request))
+                    .app_data(web::PathConfig::default().error_handler(invalid_request))
+                    .route("/subscribers", web::get().to(list_subscribers))
+                    .route("/subscribers", web::post().to(create_subscriber))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::delete().to(remove_subscriber),
+                    )
+                    .route("/issues", web::get().to(list_issues))
+                    .route("/issues", web::post().to(create_issue))
+                    .route(
+                        "/issues/{newsletter_issue_id}",
+                        web::get().to(issue_details),
+                    )
+                    .route(
+                        "/issues/{newsletter_issue_id}/publish",
+                        web::post().to(publish_issue),
+                    )
+                    .route(
+                        "/issues/{newsletter_issue_id}/stats",
+                        web::get().to(issue_stats),
+                    ),
             )
             .app_data(connection_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(manage_links.clone())
+            .app_data(suppression_key.clone())
+            .app_data(webhook_settings.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(totp_cipher.clone())
+            .app_data(password_hashing.clone())
+            .app_data(password_policy.clone())
+            .app_data(security_headers.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
     })
     .listen(listener)?
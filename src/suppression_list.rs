@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+
+// Addresses on the suppression list are never mailed again, even if they are deleted from
+// `subscriptions` and later re-submitted through `/subscribe`
+pub struct SuppressedEmail {
+    pub email: String,
+    pub reason: String,
+    pub suppressed_at: DateTime<Utc>,
+}
+
+/// Hashes the addresses that stay suppressed after their personal data was erased.
+/// The hash is keyed, so that it cannot be matched against a list of known addresses.
+#[derive(Clone)]
+pub struct SuppressionKey(Secret<String>);
+
+impl SuppressionKey {
+    pub fn new(secret: Secret<String>) -> Self {
+        Self(secret)
+    }
+
+    pub fn hash(&self, email: &str) -> String {
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(self.0.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(b"suppressed-email:");
+        mac.update(email.to_lowercase().as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+#[tracing::instrument(name = "Check the suppression list", skip(connection_pool))]
+pub async fn is_suppressed(connection_pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email FROM suppressed_emails WHERE lower(email) = lower($1)
+        "#,
+        email
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+/// Also checks the addresses that were erased while suppressed. Only stored subscribers are
+/// checked with `is_suppressed`: an erased address cannot come back without going through here.
+#[tracing::instrument(
+    name = "Check the suppression list and erased addresses",
+    skip(connection_pool, suppression_key)
+)]
+pub async fn is_suppressed_or_erased(
+    connection_pool: &PgPool,
+    suppression_key: &SuppressionKey,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    if is_suppressed(connection_pool, email).await? {
+        return Ok(true);
+    }
+    let row = sqlx::query!(
+        r#"
+        SELECT email_hash FROM erased_suppressed_emails WHERE email_hash = $1
+        "#,
+        suppression_key.hash(email)
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+#[tracing::instrument(name = "Add an email to the suppression list", skip(transaction))]
+pub async fn suppress_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    // Keep the original reason if the address is already suppressed
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressed_emails (email, reason, suppressed_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        email,
+        reason
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Replaces the suppression list entry of an address by its keyed hash, when its personal data
+/// is erased.
+#[tracing::instrument(
+    name = "Keep an erased email on the suppression list",
+    skip(transaction, suppression_key, email)
+)]
+pub async fn anonymise_suppression(
+    transaction: &mut Transaction<'_, Postgres>,
+    suppression_key: &SuppressionKey,
+    email: &str,
+) -> Result<u64, sqlx::Error> {
+    let n_rows = sqlx::query!(
+        r#"
+        WITH erased AS (
+            DELETE FROM suppressed_emails
+            WHERE lower(email) = lower($1)
+            RETURNING reason, suppressed_at
+        )
+        INSERT INTO erased_suppressed_emails (email_hash, reason, suppressed_at)
+        SELECT $2, reason, suppressed_at FROM erased
+        ON CONFLICT DO NOTHING
+        "#,
+        email,
+        suppression_key.hash(email)
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok(n_rows)
+}
+
+#[tracing::instrument(
+    name = "Remove an email from the suppression list",
+    skip(connection_pool)
+)]
+pub async fn unsuppress_email(connection_pool: &PgPool, email: &str) -> Result<u64, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM suppressed_emails WHERE lower(email) = lower($1)
+        "#,
+        email
+    )
+    .execute(connection_pool)
+    .await?
+    .rows_affected();
+    Ok(n_deleted_rows)
+}
+
+#[tracing::instrument(name = "List suppressed emails", skip(connection_pool))]
+pub async fn get_suppressed_emails(
+    connection_pool: &PgPool,
+) -> Result<Vec<SuppressedEmail>, sqlx::Error> {
+    sqlx::query_as!(
+        SuppressedEmail,
+        r#"
+        SELECT email, reason, suppressed_at
+        FROM suppressed_emails
+        ORDER BY suppressed_at DESC
+        "#
+    )
+    .fetch_all(connection_pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::suppression_list::SuppressionKey;
+    use secrecy::Secret;
+
+    fn suppression_key(secret: &str) -> SuppressionKey {
+        SuppressionKey::new(Secret::new(secret.into()))
+    }
+
+    #[test]
+    fn the_hash_does_not_depend_on_the_case_of_the_address() {
+        let key = suppression_key("secret");
+        assert_eq!(
+            key.hash("Ursula@Example.com"),
+            key.hash("ursula@example.com")
+        );
+    }
+
+    #[test]
+    fn the_hash_depends_on_the_key() {
+        assert_ne!(
+            suppression_key("secret").hash("ursula@example.com"),
+            suppression_key("another secret").hash("ursula@example.com")
+        );
+    }
+}
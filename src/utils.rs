@@ -1,5 +1,6 @@
-use actix_web::http::header::LOCATION;
+use actix_web::http::header::{ContentType, LOCATION};
 use actix_web::HttpResponse;
+use askama::Template;
 
 // Return an opaque 500 while preserving an error's root cause for logging
 pub fn e500<T>(e: T) -> actix_web::Error
@@ -15,6 +16,13 @@ pub fn see_other(location: &str) -> HttpResponse {
         .finish()
 }
 
+// Render a page from its template, which escapes every value it is given
+pub fn render_page<T: Template>(template: &T) -> Result<HttpResponse, askama::Error> {
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(template.render()?))
+}
+
 // Return a 400 with user-representation of the validation error as body.
 // Error root cause is preserved for loggin
 pub fn e400<T>(e: T) -> actix_web::Error
@@ -1,4 +1,4 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestUser};
 
 #[tokio::test]
 async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
@@ -33,3 +33,19 @@ async fn logout_clears_session_state() {
     let response = test_app.get_admin_dashboard().await;
     assert_is_redirect_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn the_username_is_escaped_on_the_dashboard() {
+    let test_app = spawn_app().await;
+    let user = TestUser {
+        username: r#"<script>alert("hi")</script>"#.into(),
+        ..TestUser::generate()
+    };
+    user.store(&test_app.db_pool).await;
+    test_app.login_as(&user).await;
+
+    let html_page = test_app.get_admin_dashboard_html().await;
+
+    assert!(html_page.contains("Welcome &lt;script&gt;alert(&quot;hi&quot;)&lt;/script&gt;!"));
+    assert!(!html_page.contains("<script>"));
+}
@@ -0,0 +1,196 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+const OTHER_USER_AGENT: &str = "Other <browser>";
+
+// Logs the test user in from a second browser
+async fn log_in_elsewhere(test_app: &TestApp) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent(OTHER_USER_AGENT)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", &test_app.address))
+        .form(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_dashboard_status(test_app: &TestApp, client: &reqwest::Client) -> u16 {
+    client
+        .get(format!("{}/admin/dashboard", &test_app.address))
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+async fn get_session_ids(test_app: &TestApp) -> Vec<(Uuid, Option<String>)> {
+    sqlx::query!(
+        r#"
+        SELECT session_id, user_agent FROM user_sessions
+        WHERE user_id = $1 AND revoked_at IS NULL
+        "#,
+        test_app.test_user.user_id
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.session_id, r.user_agent))
+    .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .api_client
+        .get(format!("{}/admin/sessions", &test_app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn sessions_are_listed_with_their_browser_and_ip_address() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    log_in_elsewhere(&test_app).await;
+
+    let html_page = test_app.get_admin_sessions_html().await;
+
+    assert_eq!(get_session_ids(&test_app).await.len(), 2);
+    assert_eq!(html_page.matches(" (this session)").count(), 1);
+    assert!(html_page.contains("<td>Other &lt;browser&gt;</td>"));
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+}
+
+#[tokio::test]
+async fn revoked_sessions_are_logged_out() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let other_client = log_in_elsewhere(&test_app).await;
+    let (other_session_id, _) = get_session_ids(&test_app)
+        .await
+        .into_iter()
+        .find(|(_, user_agent)| user_agent.as_deref() == Some(OTHER_USER_AGENT))
+        .unwrap();
+
+    let response = test_app.post_revoke_session(other_session_id).await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = test_app.get_admin_sessions_html().await;
+    assert!(html_page.contains("<p><i>The session has been revoked.</i></p>"));
+    assert!(!html_page.contains(OTHER_USER_AGENT));
+    assert_eq!(get_dashboard_status(&test_app, &other_client).await, 303);
+    let response = test_app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_revoked() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let session_id = sqlx::query!(
+        "SELECT session_id FROM user_sessions WHERE user_id = $1",
+        test_app.test_user.user_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .session_id;
+    sqlx::query!(
+        "UPDATE user_sessions SET user_id = (SELECT user_id FROM users WHERE username = 'admin')"
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    test_app.login().await;
+
+    let response = test_app.post_revoke_session(session_id).await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = test_app.get_admin_sessions_html().await;
+    assert!(html_page.contains("The session does not exist or has already ended."));
+}
+
+#[tokio::test]
+async fn logging_out_revokes_the_session() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    test_app.post_logout().await;
+
+    assert!(get_session_ids(&test_app).await.is_empty());
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_the_other_sessions() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let other_client = log_in_elsewhere(&test_app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = test_app
+        .post_change_password(&serde_json::json!({
+            "current_password": &test_app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/password");
+    assert_eq!(get_dashboard_status(&test_app, &other_client).await, 303);
+    let response = test_app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn idle_sessions_are_logged_out() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let other_client = log_in_elsewhere(&test_app).await;
+
+    sqlx::query!(
+        "UPDATE user_sessions SET last_seen_at = now() - interval '2 days' WHERE user_agent = $1",
+        OTHER_USER_AGENT
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(get_dashboard_status(&test_app, &other_client).await, 303);
+    let response = test_app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn sessions_record_the_client_address_reported_by_a_trusted_proxy() {
+    let test_app = spawn_app().await;
+    let response = test_app
+        .post_login_from(
+            &serde_json::json!({
+                "username": &test_app.test_user.username,
+                "password": &test_app.test_user.password
+            }),
+            "203.0.113.7",
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = test_app.get_admin_sessions_html().await;
+    assert!(html_page.contains("<td>203.0.113.7</td>"));
+}
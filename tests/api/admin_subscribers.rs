@@ -0,0 +1,432 @@
+use crate::helpers::{assert_is_redirect_to, create_unconfirmed_subscriber, spawn_app, TestApp};
+use chrono::{TimeZone, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::mailing_lists::DEFAULT_LIST_ID;
+
+async fn insert_subscriber(
+    test_app: &TestApp,
+    email: &str,
+    name: &str,
+    status: &str,
+    day: u32,
+) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        id,
+        email,
+        name,
+        Utc.ymd(2026, 1, day).and_hms(12, 0, 0),
+        status
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .expect("Failed to insert subscriber");
+    id
+}
+
+async fn subscriber_status(test_app: &TestApp, id: Uuid) -> Option<String> {
+    sqlx::query!("SELECT status FROM subscriptions WHERE id = $1", id)
+        .fetch_optional(&test_app.db_pool)
+        .await
+        .unwrap()
+        .map(|r| r.status)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_browse_subscribers() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_admin_subscribers(&[]).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name() {
+    let test_app = spawn_app().await;
+    insert_subscriber(
+        &test_app,
+        "ursula@example.com",
+        "Ursula Le Guin",
+        "confirmed",
+        1,
+    )
+    .await;
+    insert_subscriber(
+        &test_app,
+        "octavia@example.com",
+        "Octavia Butler",
+        "confirmed",
+        2,
+    )
+    .await;
+    test_app.login().await;
+
+    let html_page = test_app
+        .get_admin_subscribers_html(&[("search", "ursula@")])
+        .await;
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("octavia@example.com"));
+
+    let html_page = test_app
+        .get_admin_subscribers_html(&[("search", "butler")])
+        .await;
+    assert!(html_page.contains("octavia@example.com"));
+    assert!(!html_page.contains("ursula@example.com"));
+
+    // LIKE wildcards in the search term are matched literally
+    let html_page = test_app
+        .get_admin_subscribers_html(&[("search", "%")])
+        .await;
+    assert!(!html_page.contains("@example.com"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status_and_subscription_date() {
+    let test_app = spawn_app().await;
+    insert_subscriber(&test_app, "a@example.com", "a", "confirmed", 1).await;
+    insert_subscriber(&test_app, "b@example.com", "b", "pending_confirmation", 10).await;
+    insert_subscriber(&test_app, "c@example.com", "c", "confirmed", 20).await;
+    test_app.login().await;
+
+    let html_page = test_app
+        .get_admin_subscribers_html(&[("status", "confirmed")])
+        .await;
+    assert!(html_page.contains("a@example.com"));
+    assert!(!html_page.contains("b@example.com"));
+    assert!(html_page.contains("c@example.com"));
+
+    let html_page = test_app
+        .get_admin_subscribers_html(&[
+            ("subscribed_from", "2026-01-10"),
+            ("subscribed_to", "2026-01-20"),
+        ])
+        .await;
+    assert!(!html_page.contains("a@example.com"));
+    assert!(html_page.contains("b@example.com"));
+    assert!(html_page.contains("c@example.com"));
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_newest_first() {
+    let test_app = spawn_app().await;
+    // 51 subscribers sharing the same day: the id breaks the ties
+    for i in 0..51 {
+        insert_subscriber(
+            &test_app,
+            &format!("subscriber{}@example.com", i),
+            "name",
+            "confirmed",
+            1,
+        )
+        .await;
+    }
+    test_app.login().await;
+
+    let first_page = test_app.get_admin_subscribers_html(&[]).await;
+    assert_eq!(first_page.matches("@example.com").count(), 50);
+    let next_page_link = first_page
+        .split(r#"<a href=""#)
+        .find(|s| s.starts_with("/admin/subscribers?"))
+        .and_then(|s| s.split('"').next())
+        .expect("The first page has no link to the next page")
+        .replace("&amp;", "&");
+
+    let second_page = test_app
+        .api_client
+        .get(format!("{}{}", test_app.address, next_page_link))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(second_page.matches("@example.com").count(), 1);
+    assert!(!second_page.contains("Next page"));
+
+    // Every subscriber shows up exactly once across both pages
+    for i in 0..51 {
+        let email = format!(">subscriber{}@example.com<", i);
+        assert_eq!(
+            first_page.matches(&email).count() + second_page.matches(&email).count(),
+            1
+        );
+    }
+}
+
+#[tokio::test]
+async fn an_admin_can_confirm_unsubscribe_and_delete_subscribers() {
+    let test_app = spawn_app().await;
+    let id = insert_subscriber(&test_app, "a@example.com", "a", "pending_confirmation", 1).await;
+    test_app.login().await;
+
+    for (action, expected_status, message) in [
+        (
+            "confirm",
+            Some("confirmed"),
+            "a@example.com has been confirmed.",
+        ),
+        (
+            "unsubscribe",
+            Some("unsubscribed"),
+            "a@example.com has been unsubscribed.",
+        ),
+        ("delete", None, "a@example.com has been deleted."),
+    ] {
+        let response = test_app
+            .post_admin_subscribers(&serde_json::json!({
+                "subscriber_id": id,
+                "action": action,
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/subscribers");
+        let html_page = test_app.get_admin_subscribers_html(&[]).await;
+        assert!(html_page.contains(&format!("<p><i>{}</i></p>", message)));
+        assert_eq!(
+            subscriber_status(&test_app, id).await.as_deref(),
+            expected_status
+        );
+    }
+}
+
+#[tokio::test]
+async fn unsubscribed_and_deleted_addresses_are_suppressed() {
+    let test_app = spawn_app().await;
+    let unsubscribed = insert_subscriber(&test_app, "a@example.com", "a", "confirmed", 1).await;
+    let deleted = insert_subscriber(&test_app, "b@example.com", "b", "confirmed", 2).await;
+    test_app.login().await;
+
+    for (id, action) in [(unsubscribed, "unsubscribe"), (deleted, "delete")] {
+        test_app
+            .post_admin_subscribers(&serde_json::json!({
+                "subscriber_id": id,
+                "action": action,
+            }))
+            .await;
+    }
+
+    let suppressed = sqlx::query!("SELECT email, reason FROM suppressed_emails ORDER BY email")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    let suppressed: Vec<_> = suppressed
+        .into_iter()
+        .map(|r| (r.email, r.reason))
+        .collect();
+    assert_eq!(
+        suppressed,
+        vec![
+            ("a@example.com".to_string(), "unsubscribed".to_string()),
+            ("b@example.com".to_string(), "deleted".to_string())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn an_old_confirmation_link_does_not_resubscribe_an_unsubscribed_subscriber() {
+    let test_app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&test_app).await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    test_app.login().await;
+    test_app
+        .post_admin_subscribers(&serde_json::json!({
+            "subscriber_id": id,
+            "action": "unsubscribe",
+        }))
+        .await;
+
+    reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(
+        subscriber_status(&test_app, id).await.as_deref(),
+        Some("unsubscribed")
+    );
+}
+
+#[tokio::test]
+async fn an_admin_can_resend_a_confirmation_email() {
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(&test_app).await;
+    let subscriber = sqlx::query!("SELECT id, email FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    test_app.login().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_admin_subscribers(&serde_json::json!({
+            "subscriber_id": subscriber.id,
+            "action": "resend_confirmation",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    // The new link confirms the subscriber
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = test_app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        subscriber_status(&test_app, subscriber.id).await.as_deref(),
+        Some("confirmed")
+    );
+
+    let html_page = test_app.get_admin_subscribers_html(&[]).await;
+    assert!(html_page.contains(&format!(
+        "<p><i>A confirmation email has been sent to {}.</i></p>",
+        subscriber.email
+    )));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_list_and_tag() {
+    let test_app = spawn_app().await;
+    let a = insert_subscriber(&test_app, "a@example.com", "a", "confirmed", 1).await;
+    let b = insert_subscriber(&test_app, "b@example.com", "b", "confirmed", 2).await;
+    sqlx::query!(
+        "INSERT INTO list_subscriptions (subscriber_id, list_id, status, subscribed_at) \
+        VALUES ($1, $2, 'confirmed', now())",
+        a,
+        DEFAULT_LIST_ID
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO subscriber_tags (subscriber_id, tag, tagged_at) VALUES ($1, 'beta', now())",
+        b
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    test_app.login().await;
+
+    let html_page = test_app
+        .get_admin_subscribers_html(&[("list_id", &DEFAULT_LIST_ID.to_string())])
+        .await;
+    assert!(html_page.contains("a@example.com"));
+    assert!(!html_page.contains("b@example.com"));
+
+    let html_page = test_app
+        .get_admin_subscribers_html(&[("tag", "beta")])
+        .await;
+    assert!(!html_page.contains("a@example.com"));
+    assert!(html_page.contains("b@example.com"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .get_admin_subscribers_export(&[("format", "csv")])
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_matching_the_filters_are_exported_as_csv() {
+    let test_app = spawn_app().await;
+    let id = insert_subscriber(
+        &test_app,
+        "a@example.com",
+        "Le Guin, Ursula",
+        "confirmed",
+        1,
+    )
+    .await;
+    insert_subscriber(&test_app, "b@example.com", "b", "pending_confirmation", 2).await;
+    test_app.login().await;
+
+    let response = test_app
+        .get_admin_subscribers_export(&[("format", "csv"), ("status", "confirmed")])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+
+    let csv = response.text().await.unwrap();
+    assert_eq!(
+        csv,
+        format!(
+            "id,email,name,status,subscribed_at\n\
+            {},a@example.com,\"Le Guin, Ursula\",confirmed,2026-01-01T12:00:00+00:00\n",
+            id
+        )
+    );
+}
+
+#[tokio::test]
+async fn an_export_without_matches_still_has_a_csv_header() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let csv = test_app
+        .get_admin_subscribers_export(&[("format", "csv")])
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(csv, "id,email,name,status,subscribed_at\n");
+}
+
+#[tokio::test]
+async fn large_exports_are_streamed_in_batches_as_json_lines() {
+    let test_app = spawn_app().await;
+    // More subscribers than fit in a single batch, all subscribed at the same time
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+        SELECT gen_random_uuid(), 'subscriber' || i || '@example.com', 'name', now(), 'confirmed' \
+        FROM generate_series(1, 2500) AS i"
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    test_app.login().await;
+
+    let response = test_app
+        .get_admin_subscribers_export(&[("format", "jsonl")])
+        .await;
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+
+    let body = response.text().await.unwrap();
+    let emails: std::collections::HashSet<String> = body
+        .lines()
+        .map(|line| {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            record["email"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(body.lines().count(), 2500);
+    assert_eq!(emails.len(), 2500);
+}
@@ -0,0 +1,188 @@
+use crate::helpers::{assert_is_redirect_to, extract_csrf_token, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+
+async fn store_user(test_app: &TestApp, role: &str) -> TestUser {
+    let user = TestUser::with_role(role);
+    user.store(&test_app.db_pool).await;
+    user
+}
+
+async fn get_status(test_app: &TestApp, path: &str) -> u16 {
+    test_app
+        .api_client
+        .get(format!("{}{}", &test_app.address, path))
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn editors_can_send_newsletters_but_not_manage_subscribers_or_users() {
+    let test_app = spawn_app().await;
+    let editor = store_user(&test_app, "editor").await;
+    test_app.login_as(&editor).await;
+
+    assert_eq!(get_status(&test_app, "/admin/newsletters").await, 200);
+    assert_eq!(get_status(&test_app, "/admin/deliveries").await, 200);
+    assert_eq!(get_status(&test_app, "/admin/subscribers").await, 403);
+    assert_eq!(get_status(&test_app, "/admin/users").await, 403);
+    let response = test_app
+        .post_admin_user_role(test_app.test_user.user_id, "viewer")
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let html_page = test_app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(r#"<a href="/admin/newsletters">"#));
+    assert!(!html_page.contains(r#"<a href="/admin/subscribers">"#));
+}
+
+#[tokio::test]
+async fn viewers_can_only_read_the_delivery_log() {
+    let test_app = spawn_app().await;
+    let viewer = store_user(&test_app, "viewer").await;
+    test_app.login_as(&viewer).await;
+
+    assert_eq!(get_status(&test_app, "/admin/deliveries").await, 200);
+    assert_eq!(get_status(&test_app, "/admin/password").await, 200);
+    assert_eq!(get_status(&test_app, "/admin/newsletters").await, 403);
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn owners_can_change_roles() {
+    let test_app = spawn_app().await;
+    let other = store_user(&test_app, "viewer").await;
+    test_app.login().await;
+
+    let response = test_app.post_admin_user_role(other.user_id, "owner").await;
+
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = test_app.get_admin_users_html().await;
+    assert!(html_page.contains(&format!("<p><i>{} is now owner.</i></p>", other.username)));
+    let role = sqlx::query!("SELECT role FROM users WHERE user_id = $1", other.user_id)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .role;
+    assert_eq!(role, "owner");
+}
+
+#[tokio::test]
+async fn owners_cannot_change_their_own_account() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let response = test_app
+        .post_admin_user_role(test_app.test_user.user_id, "viewer")
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = test_app.get_admin_users_html().await;
+    assert!(html_page.contains("You cannot change your own account here."));
+
+    let response = test_app
+        .post_admin_user_update(test_app.test_user.user_id, "delete")
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = test_app.get_admin_users_html().await;
+    assert!(html_page.contains("You cannot change your own account here."));
+
+    let html_page = test_app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("You are logged in as owner."));
+}
+
+#[tokio::test]
+async fn disabled_users_are_logged_out_and_cannot_log_in() {
+    let test_app = spawn_app().await;
+    let editor = store_user(&test_app, "editor").await;
+    test_app.login_as(&editor).await;
+    assert_eq!(get_status(&test_app, "/admin/dashboard").await, 200);
+    let response = test_app
+        .post_admin_api_token(&editor.password, "CRM sync", &["issues:read"])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The owner disables the editor from another browser
+    let owner_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    owner_client
+        .post(format!("{}/login", &test_app.address))
+        .form(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password
+        }))
+        .send()
+        .await
+        .unwrap();
+    let dashboard_html = owner_client
+        .get(format!("{}/admin/dashboard", &test_app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let response = owner_client
+        .post(format!("{}/admin/users/update", &test_app.address))
+        .form(&[
+            ("user_id", editor.user_id.to_string().as_str()),
+            ("action", "disable"),
+            ("csrf_token", &extract_csrf_token(&dashboard_html).unwrap()),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = test_app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = test_app.login_as(&editor).await;
+    assert_is_redirect_to(&response, "/login");
+    // Their API tokens are revoked for good, not only refused while they are disabled
+    let n_live_tokens =
+        sqlx::query!(r#"SELECT count(*) AS "count!" FROM api_tokens WHERE revoked_at IS NULL"#)
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_live_tokens, 0);
+}
+
+#[tokio::test]
+async fn owners_can_delete_users() {
+    let test_app = spawn_app().await;
+    let other = store_user(&test_app, "editor").await;
+    test_app.login().await;
+
+    let response = test_app
+        .post_admin_user_update(other.user_id, "delete")
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = test_app.get_admin_users_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>{} has been deleted.</i></p>",
+        other.username
+    )));
+    let n_users = sqlx::query!(
+        "SELECT COUNT(*) AS \"n!\" FROM users WHERE user_id = $1",
+        other.user_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .n;
+    assert_eq!(n_users, 0);
+}
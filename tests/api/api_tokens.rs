@@ -0,0 +1,306 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+
+/// Creates a token for the test user from the admin page and reads it from the page it is shown on.
+async fn create_token(test_app: &TestApp, name: &str, scopes: &[&str]) -> String {
+    create_token_as(test_app, &test_app.test_user, name, scopes).await
+}
+
+async fn create_token_as(
+    test_app: &TestApp,
+    user: &TestUser,
+    name: &str,
+    scopes: &[&str],
+) -> String {
+    let response = test_app
+        .post_admin_api_token(&user.password, name, scopes)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let start = html_page.find("<code>").unwrap() + "<code>".len();
+    let end = html_page.find("</code>").unwrap();
+    html_page[start..end].to_string()
+}
+
+/// Calls the API with a token only, from a client without the session cookie.
+async fn get_api_with_token(test_app: &TestApp, path: &str, token: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/api/v1{}", &test_app.address, path))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn error_code(response: reqwest::Response) -> String {
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["error"]["code"].as_str().unwrap().to_string()
+}
+
+async fn token_id(test_app: &TestApp, name: &str) -> Uuid {
+    sqlx::query!("SELECT token_id FROM api_tokens WHERE name = $1", name)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .token_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_api_tokens() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .api_client
+        .get(format!("{}/admin/api_tokens", &test_app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_a_hash_of_a_token_is_stored() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let token = create_token(&test_app, "CRM sync", &["subscribers:read"]).await;
+
+    assert!(token.starts_with("z2p_"));
+    let saved = sqlx::query!("SELECT name, token_hash, scopes FROM api_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "CRM sync");
+    assert_ne!(saved.token_hash, token);
+    assert!(!saved.token_hash.contains(&token[4..]));
+    assert_eq!(saved.scopes, vec!["subscribers:read".to_string()]);
+    let html_page = test_app.get_admin_api_tokens_html().await;
+    assert!(html_page.contains("CRM sync"));
+    assert!(!html_page.contains(&token));
+}
+
+#[tokio::test]
+async fn a_token_needs_a_name_and_a_scope() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let response = test_app
+        .post_admin_api_token(&test_app.test_user.password, "CRM sync", &[])
+        .await;
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+    let html_page = test_app.get_admin_api_tokens_html().await;
+    assert!(html_page.contains("Choose at least one scope for the token."));
+
+    let response = test_app
+        .post_admin_api_token(&test_app.test_user.password, " ", &["issues:read"])
+        .await;
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+
+    let count = sqlx::query!("SELECT count(*) AS \"count!\" FROM api_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn a_token_authenticates_api_requests_without_a_session() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let token = create_token(
+        &test_app,
+        "Publishing bot",
+        &["issues:read", "issues:write"],
+    )
+    .await;
+
+    let response = get_api_with_token(&test_app, "/issues", &token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // No CSRF token is needed either
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/issues", &test_app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+#[tokio::test]
+async fn a_token_only_allows_its_scopes() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let token = create_token(&test_app, "Stats dashboard", &["issues:read"]).await;
+
+    let response = get_api_with_token(&test_app, "/subscribers", &token).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(error_code(response).await, "forbidden");
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/issues", &test_app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    // Tokens are for the API only
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(format!("{}/admin/dashboard", &test_app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_token_does_not_allow_more_than_the_role_of_its_user() {
+    let test_app = spawn_app().await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&test_app.db_pool).await;
+    test_app.login_as(&viewer).await;
+    let token = create_token_as(&test_app, &viewer, "Export", &["subscribers:read"]).await;
+
+    let response = get_api_with_token(&test_app, "/subscribers", &token).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn invalid_tokens_are_rejected() {
+    let test_app = spawn_app().await;
+
+    let response = get_api_with_token(&test_app, "/issues", "z2p_not-a-real-token").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    assert_eq!(error_code(response).await, "unauthorized");
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_rejected() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let token = create_token(&test_app, "Old integration", &["issues:read"]).await;
+
+    let response = test_app
+        .post_revoke_api_token(token_id(&test_app, "Old integration").await)
+        .await;
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+    let html_page = test_app.get_admin_api_tokens_html().await;
+    assert!(html_page.contains("The API token has been revoked."));
+    assert!(!html_page.contains("Old integration"));
+
+    let response = get_api_with_token(&test_app, "/issues", &token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn you_cannot_revoke_the_tokens_of_another_user() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let token = create_token(&test_app, "Owner integration", &["issues:read"]).await;
+    let token_id = token_id(&test_app, "Owner integration").await;
+    test_app.post_logout().await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&test_app.db_pool).await;
+    test_app.login_as(&viewer).await;
+
+    test_app.post_revoke_api_token(token_id).await;
+
+    let html_page = test_app.get_admin_api_tokens_html().await;
+    assert!(html_page.contains("The API token does not exist or has already been revoked."));
+    let response = get_api_with_token(&test_app, "/issues", &token).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_last_use_of_a_token_is_recorded() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let token = create_token(&test_app, "Nightly report", &["issues:read"]).await;
+    let html_page = test_app.get_admin_api_tokens_html().await;
+    assert!(html_page.contains("Never"));
+
+    get_api_with_token(&test_app, "/issues", &token).await;
+
+    let last_used_at = sqlx::query!("SELECT last_used_at FROM api_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .last_used_at;
+    assert!(last_used_at.is_some());
+    let html_page = test_app.get_admin_api_tokens_html().await;
+    assert!(!html_page.contains("Never"));
+}
+
+#[tokio::test]
+async fn tokens_of_disabled_users_are_rejected() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let token = create_token(&test_app, "CRM sync", &["issues:read"]).await;
+
+    sqlx::query!(
+        "UPDATE users SET disabled = true WHERE user_id = $1",
+        test_app.test_user.user_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    let response = get_api_with_token(&test_app, "/issues", &token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn creating_a_token_requires_the_current_password() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let response = test_app
+        .post_admin_api_token("wrong-password", "CRM sync", &["issues:read"])
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+    let html_page = test_app.get_admin_api_tokens_html().await;
+    assert!(html_page.contains("The current password is incorrect."));
+    let count = sqlx::query!("SELECT count(*) AS \"count!\" FROM api_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn changing_the_password_revokes_every_token() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let token = create_token(&test_app, "CRM sync", &["issues:read"]).await;
+
+    let new_password = Uuid::new_v4().to_string();
+    let response = test_app
+        .post_change_password(&serde_json::json!({
+            "current_password": &test_app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let response = get_api_with_token(&test_app, "/issues", &token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
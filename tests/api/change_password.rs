@@ -1,7 +1,8 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use uuid::Uuid;
+use zero2prod::authentication::MAX_FAILED_LOGIN_ATTEMPTS;
 
 #[tokio::test]
 async fn you_must_be_logged_in_to_see_change_password_form() {
@@ -112,17 +113,16 @@ async fn new_password_must_be_longer_than_12_characters() {
     assert_is_redirect_to(&response, "/admin/password");
 
     let html_page = test_app.get_change_password_html().await;
-    assert!(html_page.contains(
-        "<p><i>The new password must be longer than 12 characters and shorter than 128 characters.</i></p>"
-    ));
+    assert!(html_page
+        .contains("<p><i>The new password must be between 13 and 128 characters long.</i></p>"));
 }
 
 #[tokio::test]
-async fn new_password_must_be_shorter_than_256_characters() {
+async fn new_password_must_be_at_most_128_characters() {
     let test_app = spawn_app().await;
     let too_long_password: String = thread_rng()
         .sample_iter(&Alphanumeric)
-        .take(256)
+        .take(129)
         .map(char::from)
         .collect();
 
@@ -144,9 +144,8 @@ async fn new_password_must_be_shorter_than_256_characters() {
     assert_is_redirect_to(&response, "/admin/password");
 
     let html_page = test_app.get_change_password_html().await;
-    assert!(html_page.contains(
-        "<p><i>The new password must be longer than 12 characters and shorter than 128 characters.</i></p>"
-    ));
+    assert!(html_page
+        .contains("<p><i>The new password must be between 13 and 128 characters long.</i></p>"));
 }
 
 #[tokio::test]
@@ -191,3 +190,120 @@ async fn changing_password_works() {
     // Login with new password works
     assert_is_redirect_to(&response, "/admin/dashboard");
 }
+
+// Logs in and submits a new password, returning the message shown on the form
+async fn change_password_to(
+    test_app: &TestApp,
+    current_password: &str,
+    new_password: &str,
+) -> String {
+    let response = test_app
+        .post_change_password(&serde_json::json!({
+            "current_password": current_password,
+            "new_password": new_password,
+            "new_password_check": new_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+    test_app.get_change_password_html().await
+}
+
+#[tokio::test]
+async fn new_password_cannot_be_a_common_password() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let html_page =
+        change_password_to(&test_app, &test_app.test_user.password, "QwertyuiopAsdfgh").await;
+
+    assert!(html_page
+        .contains("<p><i>The new password is too common. Please choose another one.</i></p>"));
+}
+
+#[tokio::test]
+async fn new_password_cannot_be_too_easy_to_guess() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let html_page =
+        change_password_to(&test_app, &test_app.test_user.password, "abababababababab").await;
+
+    assert!(html_page.contains(
+        "<p><i>The new password is too easy to guess. Use more varied characters.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn new_password_cannot_contain_the_username() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let new_password = format!("{}-Xy7#kd9!", test_app.test_user.username.to_uppercase());
+
+    let html_page =
+        change_password_to(&test_app, &test_app.test_user.password, &new_password).await;
+
+    assert!(html_page.contains("<p><i>The new password cannot contain your username.</i></p>"));
+}
+
+#[tokio::test]
+async fn recent_passwords_cannot_be_reused() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let new_password = Uuid::new_v4().to_string();
+    let html_page =
+        change_password_to(&test_app, &test_app.test_user.password, &new_password).await;
+    assert!(html_page.contains("<p><i>Your password has been changed.</i></p>"));
+
+    let html_page = change_password_to(&test_app, &new_password, &new_password).await;
+    assert!(html_page
+        .contains("<p><i>The new password must be different from your last 5 passwords.</i></p>"));
+    let html_page =
+        change_password_to(&test_app, &new_password, &test_app.test_user.password).await;
+    assert!(html_page
+        .contains("<p><i>The new password must be different from your last 5 passwords.</i></p>"));
+}
+
+#[tokio::test]
+async fn only_the_last_passwords_are_kept() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let mut current_password = test_app.test_user.password.clone();
+    for _ in 0..7 {
+        let new_password = Uuid::new_v4().to_string();
+        let html_page = change_password_to(&test_app, &current_password, &new_password).await;
+        assert!(html_page.contains("<p><i>Your password has been changed.</i></p>"));
+        current_password = new_password;
+    }
+
+    // The current password is the fifth one
+    let n_old_passwords = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM password_history WHERE user_id = $1"#,
+        test_app.test_user.user_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_old_passwords, 4);
+}
+
+#[tokio::test]
+async fn mistyping_the_current_password_does_not_lock_the_account() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    for _ in 0..MAX_FAILED_LOGIN_ATTEMPTS {
+        let html_page = change_password_to(&test_app, "wrong-password", "Xy7#kd9!Lm2qP").await;
+        assert!(html_page.contains("<p><i>The current password is incorrect.</i></p>"));
+    }
+
+    let user = sqlx::query!(
+        "SELECT failed_login_attempts, locked_until FROM users WHERE user_id = $1",
+        test_app.test_user.user_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(user.failed_login_attempts, 0);
+    assert!(user.locked_until.is_none());
+}
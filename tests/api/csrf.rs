@@ -0,0 +1,96 @@
+use crate::helpers::{assert_is_redirect_to, extract_csrf_token, spawn_app, TestApp};
+
+const REJECTION: &str =
+    "The form has expired or was not sent from this site. Please reload the page and try again.";
+
+async fn post_logout_with(test_app: &TestApp, form: &[(&str, &str)]) -> reqwest::Response {
+    test_app
+        .api_client
+        .post(format!("{}/admin/logout", &test_app.address))
+        .form(form)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn admin_forms_carry_the_csrf_token_of_the_session() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let csrf_token = test_app.csrf_token().await;
+
+    assert_eq!(csrf_token.len(), 32);
+    let html_page = test_app.get_change_password_html().await;
+    assert_eq!(extract_csrf_token(&html_page), Some(csrf_token));
+}
+
+#[tokio::test]
+async fn posts_without_a_csrf_token_are_rejected() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let response = post_logout_with(&test_app, &[]).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(response.text().await.unwrap(), REJECTION);
+    // The admin is still logged in
+    let response = test_app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn posts_with_a_wrong_csrf_token_are_rejected() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let csrf_token = test_app.csrf_token().await;
+    let wrong_token: String = csrf_token.chars().rev().collect();
+
+    let response = post_logout_with(&test_app, &[("csrf_token", &wrong_token)]).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    let response = post_logout_with(&test_app, &[("csrf_token", &csrf_token)]).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn csrf_tokens_do_not_outlive_the_session() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let csrf_token = test_app.csrf_token().await;
+    test_app.post_logout().await;
+    test_app.login().await;
+
+    let response = post_logout_with(&test_app, &[("csrf_token", &csrf_token)]).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn file_uploads_without_a_csrf_token_are_rejected() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let response = test_app
+        .api_client
+        .post(format!("{}/admin/subscribers/import", &test_app.address))
+        .header(
+            "Content-Type",
+            "multipart/form-data; boundary=import-boundary",
+        )
+        .body("--import-boundary--\r\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn anonymous_posts_are_sent_to_the_login_page() {
+    let test_app = spawn_app().await;
+
+    let response = post_logout_with(&test_app, &[]).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
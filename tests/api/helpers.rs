@@ -1,14 +1,22 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
 use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 use zero2prod::configuration::get_configuration;
-use zero2prod::configuration::DatabaseSettings;
+use zero2prod::configuration::{DatabaseSettings, RateLimitSettings, WebhookSettings};
+use zero2prod::confirmation_email_queue::try_send_confirmation_email;
 use zero2prod::email_client::EmailClient;
 use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::manage_links::ManageLinks;
 use zero2prod::startup::{get_connection_pool, Application};
+use zero2prod::suppression_list::SuppressionKey;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 //Ensurce that tracing stack is only initialized once
@@ -33,6 +41,11 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub postmark_webhook: WebhookSettings,
+    pub rate_limits: RateLimitSettings,
+    pub manage_links: ManageLinks,
+    pub suppression_key: SuppressionKey,
+    pub base_url: String,
 }
 
 /// Confirmation links embedded in the request to the email API
@@ -44,7 +57,7 @@ pub struct ConfirmationLinks {
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscribe", &self.address))
+            .post(format!("{}/subscribe", &self.address))
             .header("Content-type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -52,14 +65,43 @@ impl TestApp {
             .expect("Failed to execute post subscriptions.")
     }
 
+    pub async fn post_resend_confirmation(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscribe/resend", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links_to(email_request, "/subscribe/confirm")
+    }
+
+    pub fn get_manage_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links_to(email_request, "/subscriptions/manage")
+    }
+
+    pub fn get_erasure_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links_to(email_request, "/subscriptions/manage/erase/confirm")
+    }
+
+    pub fn get_password_reset_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links_to(email_request, "/password_reset/confirm")
+    }
+
+    pub fn get_invitation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links_to(email_request, "/invitations/accept")
+    }
+
+    fn get_links_to(&self, email_request: &wiremock::Request, path: &str) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
-        // Extract the link from the body
+        // Extract the link to `path` from the body
         let get_link = |s: &str| {
             let links: Vec<_> = linkify::LinkFinder::new()
                 .links(s)
-                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .filter(|l| *l.kind() == linkify::LinkKind::Url && l.as_str().contains(path))
                 .collect();
             assert_eq!(links.len(), 1);
             let raw_link = links[0].as_str().to_owned();
@@ -70,8 +112,8 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
 
         ConfirmationLinks { html, plain_text }
     }
@@ -80,17 +122,12 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
-        self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
-            .form(body)
-            .send()
-            .await
-            .expect("Failed to execute request")
+        self.post_admin_form("/admin/newsletters", body).await
     }
 
     pub async fn get_admin_newsletters(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to get admin newsletter page")
@@ -98,7 +135,70 @@ impl TestApp {
 
     pub async fn get_admin_newsletters_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
+            .send()
+            .await
+            .expect("Failed to get admin newsletters")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_deliveries(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/deliveries", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to get admin deliveries page")
+    }
+
+    pub async fn get_admin_deliveries_html(&self, query: &[(&str, &str)]) -> String {
+        self.get_admin_deliveries(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_admin_suppressions(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("Failed to get admin suppressions page")
+    }
+
+    pub async fn get_admin_suppressions_html(&self) -> String {
+        self.get_admin_suppressions().await.text().await.unwrap()
+    }
+
+    pub async fn post_remove_suppression<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_admin_form("/admin/suppressions/remove", body)
+            .await
+    }
+
+    pub async fn get_admin_lists_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to get admin lists page")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_admin_lists<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_admin_form("/admin/lists", body).await
+    }
+
+    pub async fn get_admin_newsletters_audience_html(&self, query: &[(&str, &str)]) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters", &self.address))
+            .query(query)
             .send()
             .await
             .expect("Failed to get admin newsletters")
@@ -107,18 +207,401 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn get_admin_tags_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/tags", &self.address))
+            .send()
+            .await
+            .expect("Failed to get admin tags page")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_admin_tags<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_admin_form("/admin/tags", body).await
+    }
+
+    pub async fn get_manage_subscription_html(&self, subscriber_id: Uuid) -> String {
+        self.api_client
+            .get(format!(
+                "{}{}",
+                &self.address,
+                self.manage_links.path(subscriber_id)
+            ))
+            .send()
+            .await
+            .expect("Failed to get the preference center")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_manage_subscription<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/manage", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute update preferences request")
+    }
+
+    pub async fn get_admin_subscribers(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to get admin subscribers page")
+    }
+
+    pub async fn get_admin_subscribers_html(&self, query: &[(&str, &str)]) -> String {
+        self.get_admin_subscribers(query)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_subscribers_export(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/export", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to get admin subscribers export")
+    }
+
+    pub async fn post_admin_subscribers<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_admin_form("/admin/subscribers", body).await
+    }
+
+    /// Uploads `csv` through the import form, which sends the options before the file
+    pub async fn post_import_subscribers(&self, status: &str, csv: &str) -> reqwest::Response {
+        self.post_import_form(&[("status", status)], csv).await
+    }
+
+    pub async fn post_import_subscribers_to_list(
+        &self,
+        list_id: Uuid,
+        status: &str,
+        csv: &str,
+    ) -> reqwest::Response {
+        let list_id = list_id.to_string();
+        self.post_import_form(&[("status", status), ("list_id", &list_id)], csv)
+            .await
+    }
+
+    async fn post_import_form(&self, fields: &[(&str, &str)], csv: &str) -> reqwest::Response {
+        let boundary = "import-boundary";
+        let mut body = String::new();
+        for (name, value) in fields {
+            body.push_str(&format!(
+                "--{boundary}\r\n\
+                Content-Disposition: form-data; name=\"{name}\"\r\n\r\n\
+                {value}\r\n",
+                boundary = boundary,
+                name = name,
+                value = value
+            ));
+        }
+        body.push_str(&format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"subscribers.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            {csv}\r\n\
+            --{boundary}--\r\n",
+            boundary = boundary,
+            csv = csv
+        ));
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/import?csrf_token={}",
+                &self.address,
+                self.csrf_token().await
+            ))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute import request")
+    }
+
+    pub async fn get_admin_privacy_export(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/privacy/export", &self.address))
+            .query(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to get personal data export")
+    }
+
+    pub async fn post_admin_privacy_erase(&self, email: &str) -> reqwest::Response {
+        self.post_admin_form("/admin/privacy/erase", &[("email", email)])
+            .await
+    }
+
+    pub async fn get_admin_privacy_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/privacy", &self.address))
+            .send()
+            .await
+            .expect("Failed to get admin privacy page")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_lockouts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/lockouts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_clear_lockout(&self, user_id: Uuid) -> reqwest::Response {
+        self.post_admin_form("/admin/lockouts/clear", &[("user_id", user_id.to_string())])
+            .await
+    }
+
+    pub async fn get_admin_sessions_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_revoke_session(&self, session_id: Uuid) -> reqwest::Response {
+        self.post_admin_form(
+            "/admin/sessions/revoke",
+            &[("session_id", session_id.to_string())],
+        )
+        .await
+    }
+
+    pub async fn get_admin_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api_tokens", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_admin_api_token(
+        &self,
+        current_password: &str,
+        name: &str,
+        scopes: &[&str],
+    ) -> reqwest::Response {
+        let mut form = vec![("current_password", current_password), ("name", name)];
+        form.extend(scopes.iter().map(|scope| ("scopes", *scope)));
+        self.post_admin_form("/admin/api_tokens", &form).await
+    }
+
+    pub async fn post_revoke_api_token(&self, token_id: Uuid) -> reqwest::Response {
+        self.post_admin_form(
+            "/admin/api_tokens/revoke",
+            &[("token_id", token_id.to_string())],
+        )
+        .await
+    }
+
+    pub async fn get_admin_two_factor_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/two_factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_admin_two_factor(&self, action: &str, code: &str) -> reqwest::Response {
+        self.post_admin_form(&format!("/admin/two_factor/{}", action), &[("code", code)])
+            .await
+    }
+
+    pub async fn post_login_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/two_factor", &self.address))
+            .form(&[("code", code)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_two_factor_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login/two_factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_users_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_admin_user_invite(&self, email: &str, role: &str) -> reqwest::Response {
+        self.post_admin_form("/admin/users/invite", &[("email", email), ("role", role)])
+            .await
+    }
+
+    pub async fn post_revoke_invitation(&self, invitation_id: Uuid) -> reqwest::Response {
+        self.post_admin_form(
+            "/admin/users/invitations/revoke",
+            &[("invitation_id", invitation_id.to_string())],
+        )
+        .await
+    }
+
+    pub async fn post_accept_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/invitations/accept", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_user_role(&self, user_id: Uuid, role: &str) -> reqwest::Response {
+        self.post_admin_form(
+            "/admin/users/role",
+            &[("user_id", user_id.to_string().as_str()), ("role", role)],
+        )
+        .await
+    }
+
+    pub async fn post_admin_user_update(&self, user_id: Uuid, action: &str) -> reqwest::Response {
+        self.post_admin_form(
+            "/admin/users/update",
+            &[
+                ("user_id", user_id.to_string().as_str()),
+                ("action", action),
+            ],
+        )
+        .await
+    }
+
+    pub async fn login_as(&self, user: &TestUser) -> reqwest::Response {
+        self.post_login(&serde_json::json!({
+            "username": &user.username,
+            "password": &user.password
+        }))
+        .await
+    }
+
+    pub async fn post_admin_recovery_email(
+        &self,
+        current_password: &str,
+        email: &str,
+    ) -> reqwest::Response {
+        self.post_admin_form(
+            "/admin/recovery_email",
+            &[("current_password", current_password), ("email", email)],
+        )
+        .await
+    }
+
+    pub fn get_recovery_email_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links_to(email_request, "/recovery_email/confirm")
+    }
+
+    pub async fn get_admin_recovery_email_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/recovery_email", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_password_reset(&self, username: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/password_reset", &self.address))
+            .form(&[("username", username)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/password_reset/confirm", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body) // this method makes sure URL-encoded and Content-Type header is set
             .send()
             .await
             .expect("Failed to execute post_login request")
     }
 
+    /// Logs in as if through a reverse proxy, from `client_ip`.
+    pub async fn post_login_from<Body>(&self, body: &Body, client_ip: &str) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .header("X-Forwarded-For", client_ip)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute post_login request")
+    }
+
     pub async fn login(&self) -> reqwest::Response {
         let login_body = serde_json::json!({
             "username": &self.test_user.username,
@@ -126,7 +609,7 @@ impl TestApp {
         });
 
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(&login_body) // this method makes sure URL-encoded and Content-Type header is set
             .send()
             .await
@@ -135,7 +618,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to get login html")
@@ -144,9 +627,65 @@ impl TestApp {
             .unwrap()
     }
 
+    /// The CSRF token of the session, which the admin pages add to their forms.
+    /// Empty if the user is not logged in.
+    pub async fn csrf_token(&self) -> String {
+        let html_page = self.get_admin_dashboard_html().await;
+        extract_csrf_token(&html_page).unwrap_or_default()
+    }
+
+    /// Posts a form to an admin page along with the CSRF token, like the admin pages do.
+    pub async fn post_admin_form<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize + ?Sized,
+    {
+        let mut form = serde_urlencoded::to_string(body).unwrap();
+        if !form.is_empty() {
+            form.push('&');
+        }
+        form.push_str(
+            &serde_urlencoded::to_string([("csrf_token", self.csrf_token().await)]).unwrap(),
+        );
+        self.api_client
+            .post(format!("{}{}", &self.address, path))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/api/v1{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Posts a JSON body to the API, with the CSRF token of the session in the query string.
+    pub async fn post_api(&self, path: &str, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/api/v1{}", &self.address, path))
+            .query(&[("csrf_token", self.csrf_token().await)])
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_api(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!("{}/api/v1{}", &self.address, path))
+            .query(&[("csrf_token", self.csrf_token().await)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -158,7 +697,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", self.address))
+            .get(format!("{}/admin/password", self.address))
             .send()
             .await
             .expect("Failed to execute admin/password get request")
@@ -168,12 +707,7 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
-        self.api_client
-            .post(&format!("{}/admin/password", self.address))
-            .form(body)
-            .send()
-            .await
-            .expect("Failed to execute change password post request")
+        self.post_admin_form("/admin/password", body).await
     }
 
     pub async fn get_change_password_html(&self) -> String {
@@ -181,19 +715,57 @@ impl TestApp {
     }
 
     pub async fn post_logout(&self) -> reqwest::Response {
+        self.post_admin_form("/admin/logout", &()).await
+    }
+
+    pub async fn post_postmark_webhook(&self, body: &serde_json::Value) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", self.address))
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                &self.postmark_webhook.username,
+                Some(self.postmark_webhook.password.expose_secret()),
+            )
+            .json(body)
             .send()
             .await
-            .expect("Failed to execute logout request")
+            .expect("Failed to execute postmark webhook request")
+    }
+
+    /// Makes the failed deliveries and confirmation emails waiting for a retry due straight away.
+    pub async fn expire_retry_delays(&self) {
+        sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+            .execute(&self.db_pool)
+            .await
+            .unwrap();
+        sqlx::query!("UPDATE confirmation_email_queue SET execute_after = now()")
+            .execute(&self.db_pool)
+            .await
+            .unwrap();
     }
 
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_send_confirmation_email(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.manage_links,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.manage_links,
+                &self.suppression_key,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
@@ -217,6 +789,10 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // Use the mock server as email API
         c.email_client.base_url = email_server.uri();
+        // Keep rate limit counters apart in the shared Redis instance
+        c.rate_limits.key_prefix = Uuid::new_v4().to_string();
+        // Let tests stand in for a reverse proxy, to send requests from other client addresses
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
         c
     };
 
@@ -228,6 +804,7 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application in tests");
     let application_port = application.port();
 
+    #[allow(clippy::let_underscore_future)]
     let _ = tokio::spawn(application.run_until_stopped());
 
     let test_user = TestUser::generate();
@@ -246,6 +823,14 @@ pub async fn spawn_app() -> TestApp {
         test_user,
         api_client: client,
         email_client: configuration.email_client.client(),
+        postmark_webhook: configuration.postmark_webhook,
+        rate_limits: configuration.rate_limits,
+        suppression_key: SuppressionKey::new(configuration.application.hmac_secret.clone()),
+        manage_links: ManageLinks::new(
+            configuration.application.base_url.clone(),
+            configuration.application.hmac_secret,
+        ),
+        base_url: configuration.application.base_url,
     };
 
     test_app.test_user.store(&test_app.db_pool).await;
@@ -279,6 +864,7 @@ pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: String,
 }
 
 impl TestUser {
@@ -287,10 +873,18 @@ impl TestUser {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            role: "owner".into(),
+        }
+    }
+
+    pub fn with_role(role: &str) -> Self {
+        Self {
+            role: role.into(),
+            ..Self::generate()
         }
     }
 
-    async fn store(&self, connection_pool: &PgPool) {
+    pub async fn store(&self, connection_pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
 
         let password_hash = Argon2::new(
@@ -303,11 +897,12 @@ impl TestUser {
         .to_string();
 
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash)
-            VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)",
             self.user_id,
             self.username,
-            password_hash
+            password_hash,
+            self.role
         )
         .execute(connection_pool)
         .await
@@ -315,6 +910,59 @@ impl TestUser {
     }
 }
 
+pub async fn create_unconfirmed_subscriber(test_app: &TestApp) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": name,
+        "email": email
+    }))
+    .unwrap();
+
+    // Use mock guard and mount_as_scoped instead of mount to drop this server / not interfere with server in "main" test
+    // Confirm that confirmation email was sent out to /email server after new individual sub&scribers
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    test_app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(test_app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(test_app).await;
+
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+pub fn extract_csrf_token(html_page: &str) -> Option<String> {
+    let start =
+        html_page.find(r#"name="csrf_token" value=""#)? + r#"name="csrf_token" value=""#.len();
+    let length = html_page[start..].find('"')?;
+    Some(html_page[start..start + length].to_string())
+}
+
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
@@ -36,3 +36,29 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     let html_page = test_app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}", test_app.test_user.username)));
 }
+
+#[tokio::test]
+async fn logging_in_upgrades_a_hash_with_weaker_parameters() {
+    let test_app = spawn_app().await;
+    let get_password_hash = || async {
+        sqlx::query!(
+            "SELECT password_hash FROM users WHERE user_id = $1",
+            test_app.test_user.user_id
+        )
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .password_hash
+    };
+    // The test user is stored with the parameters the hashes used to be computed with
+    assert!(get_password_hash().await.contains("$m=15000,t=2,p=1$"));
+
+    let response = test_app.login().await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert!(get_password_hash().await.contains("$m=19456,t=2,p=1$"));
+    // The upgraded hash still verifies the same password
+    test_app.post_logout().await;
+    let response = test_app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,130 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use zero2prod::authentication::MAX_FAILED_LOGIN_ATTEMPTS;
+
+async fn fail_to_login(test_app: &TestApp, username: &str, n_attempts: i32) {
+    for _ in 0..n_attempts {
+        let response = test_app
+            .post_login(&serde_json::json!({
+                "username": username,
+                "password": "wrong-password"
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+async fn security_events(test_app: &TestApp, username: &str) -> Vec<String> {
+    sqlx::query!(
+        "SELECT event_type FROM security_events WHERE username = $1 ORDER BY occurred_at",
+        username
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.event_type)
+    .collect()
+}
+
+#[tokio::test]
+async fn repeated_failed_logins_lock_the_account() {
+    let test_app = spawn_app().await;
+    let username = test_app.test_user.username.clone();
+
+    fail_to_login(&test_app, &username, MAX_FAILED_LOGIN_ATTEMPTS).await;
+
+    // The right password is rejected while the account is locked
+    let response = test_app.login().await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("Authentication failed"));
+
+    let user = sqlx::query!(
+        "SELECT failed_login_attempts, locked_until FROM users WHERE username = $1",
+        username
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(user.failed_login_attempts, MAX_FAILED_LOGIN_ATTEMPTS);
+    assert!(user.locked_until.is_some());
+
+    let events = security_events(&test_app, &username).await;
+    assert!(events.contains(&"account_locked".to_string()));
+    assert_eq!(events.last().unwrap(), "login_while_locked");
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failure_count() {
+    let test_app = spawn_app().await;
+    let username = test_app.test_user.username.clone();
+
+    fail_to_login(&test_app, &username, MAX_FAILED_LOGIN_ATTEMPTS - 1).await;
+    let response = test_app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    test_app.post_logout().await;
+
+    // Another round of failures below the limit does not lock the account
+    fail_to_login(&test_app, &username, MAX_FAILED_LOGIN_ATTEMPTS - 1).await;
+    let response = test_app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn failed_logins_for_unknown_usernames_are_recorded() {
+    let test_app = spawn_app().await;
+
+    fail_to_login(&test_app, "not-an-admin", 1).await;
+
+    assert_eq!(
+        security_events(&test_app, "not-an-admin").await,
+        vec!["login_failed".to_string()]
+    );
+}
+
+#[tokio::test]
+async fn another_admin_can_see_and_clear_a_lockout() {
+    let test_app = spawn_app().await;
+    let locked_user = &test_app.test_user;
+    fail_to_login(&test_app, &locked_user.username, MAX_FAILED_LOGIN_ATTEMPTS).await;
+
+    let other_admin = TestUser::generate();
+    other_admin.store(&test_app.db_pool).await;
+    test_app
+        .post_login(&serde_json::json!({
+            "username": &other_admin.username,
+            "password": &other_admin.password
+        }))
+        .await;
+
+    let html_page = test_app.get_admin_lockouts_html().await;
+    assert!(html_page.contains(&locked_user.username));
+    assert!(html_page.contains("account_locked"));
+
+    let response = test_app.post_clear_lockout(locked_user.user_id).await;
+    assert_is_redirect_to(&response, "/admin/lockouts");
+    let html_page = test_app.get_admin_lockouts_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>{} can log in again.</i></p>",
+        locked_user.username
+    )));
+    assert!(html_page.contains(&format!("Cleared by {}", other_admin.username)));
+    test_app.post_logout().await;
+
+    let response = test_app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_login_lockouts() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .api_client
+        .get(format!("{}/admin/lockouts", &test_app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,134 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_list(test_app: &TestApp, name: &str) -> Uuid {
+    let list_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, name, created_at) VALUES ($1, $2, now())",
+        list_id,
+        name
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .expect("Failed to create list");
+    list_id
+}
+
+async fn subscribe_and_confirm(test_app: &TestApp, list_id: Uuid) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "list_id": list_id,
+    }))
+    .unwrap();
+    test_app
+        .post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn confirming_a_subscription_confirms_the_chosen_list_membership() {
+    let test_app = spawn_app().await;
+    let list_id = create_list(&test_app, "Rust weekly").await;
+
+    subscribe_and_confirm(&test_app, list_id).await;
+
+    let memberships = sqlx::query!("SELECT list_id, status FROM list_subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(memberships.len(), 1);
+    assert_eq!(memberships[0].list_id, list_id);
+    assert_eq!(memberships[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn newsletters_are_only_delivered_to_the_chosen_list() {
+    let test_app = spawn_app().await;
+    let rust_list = create_list(&test_app, "Rust weekly").await;
+    let go_list = create_list(&test_app, "Go weekly").await;
+    subscribe_and_confirm(&test_app, rust_list).await;
+    test_app.login().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "list_id": go_list,
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let test_app = spawn_app().await;
+
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "list_id": Uuid::new_v4(),
+    }))
+    .unwrap();
+    let response = test_app.post_subscriptions(body).await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_admin_can_create_a_mailing_list() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let response = test_app
+        .post_admin_lists(&serde_json::json!({ "name": "Rust weekly" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    let html_page = test_app.get_admin_lists_html().await;
+    assert!(html_page.contains("<p><i>The list Rust weekly has been created.</i></p>"));
+
+    // The new list is offered on the public subscribe form
+    let html_page = reqwest::get(format!("{}/subscribe", &test_app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Rust weekly"));
+}
@@ -1,8 +1,28 @@
 mod admin_dashboard;
+mod admin_sessions;
+mod admin_subscribers;
+mod admin_users;
+mod api_tokens;
 mod change_password;
+mod csrf;
 mod health_check;
 mod helpers;
 mod login;
+mod login_lockout;
+mod mailing_lists;
 mod newsletter;
+mod password_reset;
+mod personal_data;
+mod postmark_webhook;
+mod rate_limiting;
+mod rest_api;
+mod security_headers;
+mod segments;
+mod subscriber_import;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_manage;
+mod subscriptions_resend;
+mod suppression_list;
+mod two_factor;
+mod user_invitations;
@@ -1,57 +1,10 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
-use fake::faker::internet::en::SafeEmail;
-use fake::faker::name::en::Name;
-use fake::Fake;
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+};
 use std::time::Duration;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, MockBuilder, ResponseTemplate};
 
-async fn create_unconfirmed_subscriber(test_app: &TestApp) -> ConfirmationLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
-        "name": name,
-        "email": email
-    }))
-    .unwrap();
-
-    // Use mock guard and mount_as_scoped instead of mount to drop this server / not interfere with server in "main" test
-    // Confirm that confirmation email was sent out to /email server after new individual sub&scribers
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&test_app.email_server)
-        .await;
-
-    test_app
-        .post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = &test_app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-
-    test_app.get_confirmation_links(email_request)
-}
-
-async fn create_confirmed_subscriber(test_app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(test_app).await;
-
-    reqwest::get(confirmation_link.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
-
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let test_app = spawn_app().await;
@@ -245,6 +198,256 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     // Mock verifies on Drop that only one newsetter was sent
 }
 
+#[tokio::test]
+async fn successful_deliveries_are_recorded_in_the_delivery_log() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.login().await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "ErrorCode": 0,
+            "Message": "OK",
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"
+        })))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    test_app.post_newsletters(&newsletter_request_body).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let subscriber = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    let delivery = sqlx::query!(
+        "SELECT subscriber_email, provider_message_id, outcome, n_attempts FROM newsletter_deliveries"
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .expect("Failed to fetch the delivery record");
+    assert_eq!(delivery.subscriber_email, subscriber.email);
+    assert_eq!(
+        delivery.provider_message_id.as_deref(),
+        Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+    );
+    assert_eq!(delivery.outcome, "sent");
+    assert_eq!(delivery.n_attempts, 1);
+
+    // The delivery can be looked up by subscriber from the admin UI
+    let html_page = test_app
+        .get_admin_deliveries_html(&[("subscriber_email", &subscriber.email)])
+        .await;
+    assert!(html_page.contains("b7bc2f4a-e38e-4336-af7d-e6c392c2f817"));
+    assert!(html_page.contains("Newsletter title"));
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_and_counted() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.login().await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    test_app.post_newsletters(&newsletter_request_body).await;
+
+    // The first attempt fails, the second one goes through
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.dispatch_all_pending_emails().await;
+    // The retry waits for its delay
+    let delivery = sqlx::query!("SELECT outcome FROM newsletter_deliveries")
+        .fetch_optional(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(delivery.is_none());
+    test_app.expire_retry_delays().await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let delivery = sqlx::query!("SELECT outcome, n_attempts FROM newsletter_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery record");
+    assert_eq!(delivery.outcome, "sent");
+    assert_eq!(delivery.n_attempts, 2);
+}
+
+#[tokio::test]
+async fn deliveries_that_keep_failing_are_given_up_on() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.login().await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    test_app.post_newsletters(&newsletter_request_body).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(5)
+        .mount(&test_app.email_server)
+        .await;
+
+    for _ in 0..10 {
+        test_app.expire_retry_delays().await;
+        test_app.dispatch_all_pending_emails().await;
+    }
+
+    let delivery = sqlx::query!("SELECT outcome, n_attempts FROM newsletter_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery record");
+    assert_eq!(delivery.outcome, "failed");
+    assert_eq!(delivery.n_attempts, 5);
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_optional(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.is_none());
+}
+
+#[tokio::test]
+async fn queued_deliveries_are_dropped_once_the_subscriber_no_longer_receives_the_list() {
+    let changes = [
+        "INSERT INTO suppressed_emails (email, reason, suppressed_at) \
+            SELECT email, 'bounced', now() FROM subscriptions",
+        "UPDATE list_subscriptions SET status = 'unsubscribed'",
+    ];
+    for change in changes {
+        let test_app = spawn_app().await;
+        create_confirmed_subscriber(&test_app).await;
+        test_app.login().await;
+
+        let newsletter_request_body = serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        });
+        test_app.post_newsletters(&newsletter_request_body).await;
+        // The subscriber changes their mind between the publication and the delivery
+        sqlx::query(change)
+            .execute(&test_app.db_pool)
+            .await
+            .unwrap();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&test_app.email_server)
+            .await;
+        test_app.dispatch_all_pending_emails().await;
+
+        let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+            .fetch_optional(&test_app.db_pool)
+            .await
+            .unwrap();
+        assert!(
+            queued.is_none(),
+            "The delivery is still queued after: {}",
+            change
+        );
+    }
+}
+
+#[tokio::test]
+async fn queued_deliveries_to_deleted_subscribers_are_dropped() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.login().await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    test_app.post_newsletters(&newsletter_request_body).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let response = test_app
+        .delete_api(&format!("/subscribers/{}", subscriber_id))
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn invalid_addresses_are_recorded_after_one_attempt() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.login().await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    test_app.post_newsletters(&newsletter_request_body).await;
+    sqlx::query!("UPDATE issue_delivery_queue SET subscriber_email = 'not-an-email'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let delivery = sqlx::query!("SELECT outcome, n_attempts FROM newsletter_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery record");
+    assert_eq!(delivery.outcome, "invalid_address");
+    assert_eq!(delivery.n_attempts, 1);
+}
+
+#[tokio::test]
+async fn delivery_log_redirects_to_login_if_not_logged_in() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_admin_deliveries(&[]).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
 fn when_sending_an_email() -> MockBuilder {
     Mock::given(path("/email")).and(method("POST"))
 }
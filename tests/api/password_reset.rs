@@ -0,0 +1,301 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const RECOVERY_EMAIL: &str = "admin@example.com";
+
+async fn set_recovery_email(test_app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        RECOVERY_EMAIL,
+        test_app.test_user.user_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+}
+
+// Requests a reset for the test user and returns the link from the email
+async fn request_reset_link(test_app: &TestApp) -> reqwest::Url {
+    set_recovery_email(test_app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_password_reset(&test_app.test_user.username)
+        .await;
+
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    test_app.get_password_reset_links(email_request).html
+}
+
+fn token_of(reset_link: &reqwest::Url) -> String {
+    reset_link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+// The link points at 127.0.0.1, while the session cookie was set for localhost
+async fn follow_as_logged_in_user(test_app: &TestApp, mut link: reqwest::Url) -> reqwest::Response {
+    link.set_host(Some("localhost")).unwrap();
+    test_app.api_client.get(link).send().await.unwrap()
+}
+
+async fn reset_password(test_app: &TestApp, token: &str, new_password: &str) -> reqwest::Response {
+    test_app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "new_password": new_password,
+            "new_password_check": new_password,
+        }))
+        .await
+}
+
+#[tokio::test]
+async fn the_login_page_links_to_the_password_reset() {
+    let test_app = spawn_app().await;
+
+    let html_page = test_app.get_login_html().await;
+
+    assert!(html_page.contains(r#"<a href="/password_reset">Forgot your password?</a>"#));
+}
+
+#[tokio::test]
+async fn requesting_a_reset_emails_a_link_to_the_recovery_email() {
+    let test_app = spawn_app().await;
+
+    let reset_link = request_reset_link(&test_app).await;
+
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], RECOVERY_EMAIL);
+    let response = reqwest::get(reset_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("new_password_check"));
+}
+
+#[tokio::test]
+async fn unknown_users_and_users_without_a_recovery_email_get_the_same_reply_and_no_email() {
+    let test_app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    for username in [&Uuid::new_v4().to_string(), &test_app.test_user.username] {
+        let response = test_app.post_password_reset(username).await;
+
+        assert_is_redirect_to(&response, "/login");
+        let html_page = test_app.get_login_html().await;
+        assert!(html_page.contains(
+            "If the account has a recovery email, we have sent a password reset link to it."
+        ));
+    }
+}
+
+#[tokio::test]
+async fn the_reset_link_sets_a_new_password_once() {
+    let test_app = spawn_app().await;
+    let token = token_of(&request_reset_link(&test_app).await);
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = reset_password(&test_app, &token, &new_password).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("Your password has been reset. You can now log in."));
+
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &new_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let response = reset_password(&test_app, &token, &Uuid::new_v4().to_string()).await;
+    assert_is_redirect_to(&response, "/password_reset");
+    let response = test_app
+        .api_client
+        .get(format!("{}/password_reset", &test_app.address))
+        .send()
+        .await
+        .unwrap();
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("The password reset link is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn resetting_the_password_ends_every_session_and_api_token_of_the_user() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let html_page = test_app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", test_app.test_user.username)));
+    let response = test_app
+        .post_admin_api_token(&test_app.test_user.password, "CRM sync", &["issues:read"])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = token_of(&request_reset_link(&test_app).await);
+    reset_password(&test_app, &token, &Uuid::new_v4().to_string()).await;
+
+    let response = test_app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let n_live_tokens =
+        sqlx::query!(r#"SELECT count(*) AS "count!" FROM api_tokens WHERE revoked_at IS NULL"#)
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_live_tokens, 0);
+}
+
+#[tokio::test]
+async fn the_new_password_must_follow_the_length_rules() {
+    let test_app = spawn_app().await;
+    let token = token_of(&request_reset_link(&test_app).await);
+
+    let response = reset_password(&test_app, &token, "too-short").await;
+
+    assert_is_redirect_to(
+        &response,
+        &format!("/password_reset/confirm?token={}", token),
+    );
+    let html_page = test_app
+        .api_client
+        .get(format!(
+            "{}/password_reset/confirm?token={}",
+            &test_app.address, token
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The new password must be between 13 and 128 characters long."));
+    // The old password still works
+    let response = test_app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn expired_reset_links_are_rejected() {
+    let test_app = spawn_app().await;
+    let token = token_of(&request_reset_link(&test_app).await);
+    sqlx::query!("UPDATE password_reset_tokens SET created_at = now() - interval '2 hours'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reset_password(&test_app, &token, &Uuid::new_v4().to_string()).await;
+
+    assert_is_redirect_to(&response, "/password_reset");
+    let response = test_app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn admins_can_set_their_recovery_email_once_it_is_verified() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let password = test_app.test_user.password.clone();
+
+    let response = test_app
+        .post_admin_recovery_email(&password, "not-an-email")
+        .await;
+    assert_is_redirect_to(&response, "/admin/recovery_email");
+    let html_page = test_app.get_admin_recovery_email_html().await;
+    assert!(html_page.contains("You have no recovery email"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_admin_recovery_email(&password, RECOVERY_EMAIL)
+        .await;
+    let html_page = test_app.get_admin_recovery_email_html().await;
+    assert!(html_page.contains(&format!(
+        "We sent a verification link to {}.",
+        RECOVERY_EMAIL
+    )));
+    assert!(html_page.contains("You have no recovery email"));
+
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], RECOVERY_EMAIL);
+    let verification_link = test_app.get_recovery_email_links(email_request).html;
+    let response = follow_as_logged_in_user(&test_app, verification_link.clone()).await;
+    assert_is_redirect_to(&response, "/admin/recovery_email");
+    let html_page = test_app.get_admin_recovery_email_html().await;
+    assert!(html_page.contains("Your recovery email has been confirmed."));
+    assert!(html_page.contains(&format!("<b>{}</b>", RECOVERY_EMAIL)));
+
+    // The link cannot be used twice
+    follow_as_logged_in_user(&test_app, verification_link).await;
+    let html_page = test_app.get_admin_recovery_email_html().await;
+    assert!(html_page.contains("The verification link is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn changing_the_recovery_email_requires_the_current_password() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_admin_recovery_email("wrong-password", RECOVERY_EMAIL)
+        .await;
+    assert_is_redirect_to(&response, "/admin/recovery_email");
+    let html_page = test_app.get_admin_recovery_email_html().await;
+    assert!(html_page.contains("The current password is incorrect."));
+    assert!(html_page.contains("You have no recovery email"));
+}
+
+#[tokio::test]
+async fn an_expired_verification_link_does_not_change_the_recovery_email() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_admin_recovery_email(&test_app.test_user.password, RECOVERY_EMAIL)
+        .await;
+    sqlx::query!("UPDATE recovery_email_verifications SET created_at = now() - interval '2 hours'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let verification_link = test_app.get_recovery_email_links(email_request).html;
+    follow_as_logged_in_user(&test_app, verification_link).await;
+    let html_page = test_app.get_admin_recovery_email_html().await;
+    assert!(html_page.contains("The verification link is invalid or has expired."));
+    assert!(html_page.contains("You have no recovery email"));
+}
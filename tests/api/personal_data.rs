@@ -0,0 +1,338 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// A confirmed, tagged subscriber who has been sent one issue. Returns their id and email.
+async fn create_subscriber_with_history(test_app: &TestApp) -> (Uuid, String) {
+    create_confirmed_subscriber(test_app).await;
+    let subscriber = sqlx::query!("SELECT id, email FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "INSERT INTO subscriber_tags (subscriber_id, tag, tagged_at) VALUES ($1, 'beta', now())",
+        subscriber.id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    test_app.login().await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    (subscriber.id, subscriber.email)
+}
+
+async fn n_rows_about(test_app: &TestApp, subscriber_id: Uuid, email: &str) -> i64 {
+    sqlx::query_scalar!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM subscriptions WHERE id = $1) +
+            (SELECT COUNT(*) FROM subscription_tokens WHERE subscriber_id = $1) +
+            (SELECT COUNT(*) FROM list_subscriptions WHERE subscriber_id = $1) +
+            (SELECT COUNT(*) FROM subscriber_tags WHERE subscriber_id = $1) +
+            (SELECT COUNT(*) FROM confirmation_email_sends WHERE email = $2) +
+            (SELECT COUNT(*) FROM issue_delivery_queue WHERE subscriber_email = $2) +
+            (SELECT COUNT(*) FROM newsletter_deliveries WHERE subscriber_email = $2)
+            AS "n_rows!"
+        "#,
+        subscriber_id,
+        email
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+}
+
+/// Asks for the erasure of the subscriber's data with their manage link, returning the link
+/// emailed to confirm it.
+async fn request_erasure(test_app: &TestApp, subscriber_id: Uuid) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/manage/erase", test_app.address))
+        .form(&serde_json::json!({
+            "subscriber_id": subscriber_id,
+            "signature": test_app.manage_links.signature(subscriber_id),
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = test_app.get_erasure_links(&email_request);
+    assert_eq!(links.html, links.plain_text);
+    links.html.to_string()
+}
+
+async fn post_erasure_confirmation(test_app: &TestApp, erasure_link: &str) -> reqwest::Response {
+    let token = reqwest::Url::parse(erasure_link)
+        .unwrap()
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned();
+    reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/manage/erase/confirm",
+            test_app.address
+        ))
+        .form(&[("token", token)])
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_handle_personal_data_requests() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_admin_privacy_export("a@example.com").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = test_app.post_admin_privacy_erase("a@example.com").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_admin_can_export_everything_stored_about_an_email() {
+    let test_app = spawn_app().await;
+    let (subscriber_id, email) = create_subscriber_with_history(&test_app).await;
+
+    let response = test_app
+        .get_admin_privacy_export(&email.to_uppercase())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let bundle: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(bundle["subscriber"]["id"], subscriber_id.to_string());
+    assert_eq!(bundle["subscriber"]["status"], "confirmed");
+    assert_eq!(bundle["lists"][0]["name"], "Newsletter");
+    assert_eq!(bundle["tags"][0]["tag"], "beta");
+    assert_eq!(bundle["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(bundle["confirmation_emails"].as_array().unwrap().len(), 1);
+    assert_eq!(bundle["deliveries"][0]["title"], "Newsletter title");
+    assert_eq!(bundle["deliveries"][0]["outcome"], "sent");
+}
+
+#[tokio::test]
+async fn an_admin_can_erase_everything_stored_about_an_email() {
+    let test_app = spawn_app().await;
+    let (subscriber_id, email) = create_subscriber_with_history(&test_app).await;
+    assert!(n_rows_about(&test_app, subscriber_id, &email).await > 0);
+
+    let response = test_app.post_admin_privacy_erase(&email).await;
+    assert_is_redirect_to(&response, "/admin/privacy");
+
+    let html_page = test_app.get_admin_privacy_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>The personal data of {} has been erased.</i></p>",
+        email
+    )));
+    assert_eq!(n_rows_about(&test_app, subscriber_id, &email).await, 0);
+
+    // Delivery statistics survive, without the address
+    let n_deliveries = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_deliveries"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_deliveries, 1);
+}
+
+#[tokio::test]
+async fn erasing_an_unknown_email_is_reported() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    test_app
+        .post_admin_privacy_erase("nobody@example.com")
+        .await;
+
+    let html_page = test_app.get_admin_privacy_html().await;
+    assert!(html_page.contains("<p><i>Nothing is stored about nobody@example.com.</i></p>"));
+}
+
+#[tokio::test]
+async fn submitted_values_are_escaped_in_flash_messages() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    test_app
+        .post_admin_privacy_erase("<img src=x onerror=alert(1)>")
+        .await;
+
+    let html_page = test_app.get_admin_privacy_html().await;
+    assert!(html_page.contains("Nothing is stored about &lt;img src=x onerror=alert(1)&gt;."));
+    assert!(!html_page.contains("<img"));
+}
+
+#[tokio::test]
+async fn subscribers_can_download_and_erase_their_own_data_with_a_signed_link() {
+    let test_app = spawn_app().await;
+    let (subscriber_id, email) = create_subscriber_with_history(&test_app).await;
+    let signature = test_app.manage_links.signature(subscriber_id);
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/manage/data?subscriber_id={}&signature={}",
+        test_app.address, subscriber_id, signature
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let bundle: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(bundle["subscriber"]["email"], email);
+
+    let erasure_link = request_erasure(&test_app, subscriber_id).await;
+    // Following the link only asks for confirmation
+    let response = reqwest::get(&erasure_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(n_rows_about(&test_app, subscriber_id, &email).await > 0);
+
+    let response = post_erasure_confirmation(&test_app, &erasure_link).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(n_rows_about(&test_app, subscriber_id, &email).await, 0);
+
+    // The link cannot be used twice
+    let response = post_erasure_confirmation(&test_app, &erasure_link).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_invalid_erasure_link_is_rejected() {
+    let test_app = spawn_app().await;
+    let (subscriber_id, email) = create_subscriber_with_history(&test_app).await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/manage/erase/confirm?token=forged",
+        test_app.address
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = post_erasure_confirmation(
+        &test_app,
+        &format!(
+            "{}/subscriptions/manage/erase/confirm?token=forged",
+            test_app.address
+        ),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(n_rows_about(&test_app, subscriber_id, &email).await > 0);
+}
+
+#[tokio::test]
+async fn an_expired_erasure_link_is_rejected() {
+    let test_app = spawn_app().await;
+    let (subscriber_id, email) = create_subscriber_with_history(&test_app).await;
+    let erasure_link = request_erasure(&test_app, subscriber_id).await;
+    sqlx::query!("UPDATE erasure_requests SET created_at = now() - interval '2 hours'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let response = post_erasure_confirmation(&test_app, &erasure_link).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(n_rows_about(&test_app, subscriber_id, &email).await > 0);
+}
+
+#[tokio::test]
+async fn an_erased_suppressed_address_stays_suppressed_without_being_stored() {
+    let test_app = spawn_app().await;
+    let (subscriber_id, email) = create_subscriber_with_history(&test_app).await;
+    sqlx::query!(
+        "INSERT INTO suppressed_emails (email, reason, suppressed_at) VALUES ($1, 'bounced', now())",
+        email
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    let response = test_app.post_admin_privacy_erase(&email).await;
+    assert_is_redirect_to(&response, "/admin/privacy");
+    assert_eq!(n_rows_about(&test_app, subscriber_id, &email).await, 0);
+    let n_plaintext_rows = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM suppressed_emails"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_plaintext_rows, 0);
+
+    // Subscribing again does not send a confirmation email
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let response = test_app
+        .post_subscriptions(format!(
+            "name=le%20guin&email={}",
+            urlencoding::encode(&email)
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let bundle: serde_json::Value = test_app
+        .get_admin_privacy_export(&email)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(bundle["suppression"]["reason"], "bounced");
+}
+
+#[tokio::test]
+async fn personal_data_links_with_an_invalid_signature_are_rejected() {
+    let test_app = spawn_app().await;
+    let (subscriber_id, email) = create_subscriber_with_history(&test_app).await;
+    let forged_signature = test_app.manage_links.signature(Uuid::new_v4());
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/manage/data?subscriber_id={}&signature={}",
+        test_app.address, subscriber_id, forged_signature
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/manage/erase", test_app.address))
+        .form(&serde_json::json!({
+            "subscriber_id": subscriber_id,
+            "signature": forged_signature,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(n_rows_about(&test_app, subscriber_id, &email).await > 0);
+}
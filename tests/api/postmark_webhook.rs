@@ -0,0 +1,172 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &test_app.address))
+        .json(&serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": Uuid::new_v4().to_string(),
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="postmark""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn requests_with_invalid_credentials_are_rejected() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &test_app.address))
+        .basic_auth(&test_app.postmark_webhook.username, Some("wrong-password"))
+        .json(&serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": Uuid::new_v4().to_string(),
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn credentials_are_checked_before_the_body_is_parsed() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &test_app.address))
+        .basic_auth(&test_app.postmark_webhook.username, Some("wrong-password"))
+        .header("Content-Type", "application/json")
+        .body("not json")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(401, response.status().as_u16());
+
+    let response = test_app
+        .post_postmark_webhook(&serde_json::json!({"RecordType": "Bounce"}))
+        .await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_hard_bounce_marks_the_subscriber_as_bounced_and_stops_deliveries() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let response = test_app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Bounce",
+            "MessageID": Uuid::new_v4().to_string(),
+            "Type": "HardBounce",
+            "TypeCode": 1,
+            "Email": subscriber.email,
+        }))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "bounced");
+
+    // No newsletter should go out to the bounced address
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.login().await;
+    test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn a_spam_complaint_marks_the_subscriber_and_updates_the_delivery_log() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.login().await;
+
+    let message_id = Uuid::new_v4().to_string();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({ "MessageID": message_id })),
+        )
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let subscriber = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    let response = test_app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "MessageID": message_id,
+            "Email": subscriber.email,
+        }))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "complained");
+    let delivery = sqlx::query!("SELECT outcome FROM newsletter_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.outcome, "complained");
+}
+
+#[tokio::test]
+async fn unknown_event_types_are_acknowledged() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Open",
+            "MessageID": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+}
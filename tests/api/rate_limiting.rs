@@ -0,0 +1,180 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+const ATTACKER_IP: &str = "203.0.113.7";
+
+#[tokio::test]
+async fn login_attempts_are_limited_per_username_and_client() {
+    let test_app = spawn_app().await;
+    let max_requests = test_app.rate_limits.login.max_requests_per_key;
+    let login_body = serde_json::json!({
+        "username": &test_app.test_user.username,
+        "password": "wrong-password"
+    });
+
+    for _ in 0..max_requests {
+        let response = test_app.post_login_from(&login_body, ATTACKER_IP).await;
+        assert_eq!(response.status().as_u16(), 303);
+    }
+    let response = test_app.post_login_from(&login_body, ATTACKER_IP).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= test_app.rate_limits.login.window_seconds);
+
+    // The right password does not get through either until the window ends...
+    let right_login_body = serde_json::json!({
+        "username": &test_app.test_user.username,
+        "password": &test_app.test_user.password
+    });
+    let response = test_app
+        .post_login_from(&right_login_body, ATTACKER_IP)
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // ...but the user can still log in from elsewhere, once an admin lifts the account lockout
+    // the failures triggered
+    sqlx::query!("UPDATE users SET failed_login_attempts = 0, locked_until = NULL")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    let response = test_app.post_login(&right_login_body).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_login_form_is_not_rate_limited() {
+    let test_app = spawn_app().await;
+
+    for _ in 0..=test_app.rate_limits.login.max_requests_per_ip {
+        test_app.get_login_html().await;
+    }
+
+    test_app.login().await;
+    let html_page = test_app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", test_app.test_user.username)));
+}
+
+#[tokio::test]
+async fn subscriptions_are_limited_per_email() {
+    let test_app = spawn_app().await;
+    let max_requests = test_app.rate_limits.subscribe.max_requests_per_key;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // Invalid names still count towards the limit of the address
+    let body = "name=&email=ursula_le_guin%40gmail.com";
+    for _ in 0..max_requests {
+        let response = test_app.post_subscriptions(body.into()).await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=URSULA_LE_GUIN%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("Retry-After"));
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Please try again in"));
+
+    // Other addresses are unaffected
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscriptions_are_limited_per_client_ip() {
+    let test_app = spawn_app().await;
+    let max_requests = test_app.rate_limits.subscribe.max_requests_per_ip;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    for i in 0..max_requests {
+        let body = format!("name=le%20guin&email=subscriber{}%40example.com", i);
+        let response = test_app.post_subscriptions(body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=one_more%40example.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // Resending confirmation emails shares the limit of the endpoint
+    let response = test_app
+        .post_resend_confirmation("subscriber0@example.com")
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn preference_updates_are_limited_per_email() {
+    let test_app = spawn_app().await;
+    let max_requests = test_app.rate_limits.subscribe.max_requests_per_key;
+
+    // The signature is not checked before the limit
+    let body = serde_json::json!({
+        "subscriber_id": uuid::Uuid::new_v4(),
+        "signature": "forged",
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "paused_until": ""
+    });
+    for _ in 0..max_requests {
+        let response = test_app.post_manage_subscription(&body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    let response = test_app.post_manage_subscription(&body).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn two_factor_codes_and_reset_tokens_are_limited_per_client_ip() {
+    let test_app = spawn_app().await;
+    let max_requests = test_app.rate_limits.login.max_requests_per_ip;
+
+    for i in 0..max_requests {
+        let response = test_app.post_login_two_factor(&format!("{:06}", i)).await;
+        assert_ne!(response.status().as_u16(), 429);
+    }
+    let response = test_app.post_login_two_factor("123456").await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    for i in 0..max_requests {
+        let response = test_app
+            .post_password_reset_confirm(&serde_json::json!({
+                "token": format!("guess-{}", i),
+                "new_password": "Xy7#kd9!Lm2qP",
+                "new_password_check": "Xy7#kd9!Lm2qP",
+            }))
+            .await;
+        assert_ne!(response.status().as_u16(), 429);
+    }
+    let response = test_app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": "one-more-guess",
+            "new_password": "Xy7#kd9!Lm2qP",
+            "new_password_check": "Xy7#kd9!Lm2qP",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+}
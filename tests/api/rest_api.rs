@@ -0,0 +1,344 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestUser};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn issue_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text"
+    })
+}
+
+async fn error_code(response: reqwest::Response) -> String {
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["error"]["code"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_use_the_api() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_api("/subscribers").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(error_code(response).await, "unauthorized");
+}
+
+#[tokio::test]
+async fn the_openapi_document_is_public() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_api("/openapi.json").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let document: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(document["openapi"], "3.0.3");
+    assert!(document["paths"]["/subscribers"].is_object());
+}
+
+#[tokio::test]
+async fn subscribers_can_be_created_fetched_listed_and_deleted() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_api(
+            "/subscribers",
+            &serde_json::json!({"name": "le guin", "email": "ursula_le_guin@gmail.com"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscriber["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscriber["status"], "pending_confirmation");
+    let subscriber_path = format!("/subscribers/{}", subscriber["id"].as_str().unwrap());
+
+    let response = test_app.get_api(&subscriber_path).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let fetched: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(fetched, subscriber);
+
+    let response = test_app.get_api("/subscribers?search=le_guin").await;
+    let page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(page["subscribers"], serde_json::json!([subscriber]));
+    assert!(page["next_cursor"].is_null());
+
+    let response = test_app.delete_api(&subscriber_path).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = test_app.get_api(&subscriber_path).await;
+    assert_eq!(response.status().as_u16(), 404);
+    assert_eq!(error_code(response).await, "not_found");
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_a_page_at_a_time() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.login().await;
+
+    let response = test_app.get_api("/subscribers?limit=1").await;
+    let first_page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(first_page["subscribers"].as_array().unwrap().len(), 1);
+    let cursor = &first_page["next_cursor"];
+
+    let response = test_app
+        .api_client
+        .get(format!("{}/api/v1/subscribers", &test_app.address))
+        .query(&[
+            ("limit", "1"),
+            (
+                "after_subscribed_at",
+                cursor["after_subscribed_at"].as_str().unwrap(),
+            ),
+            ("after_id", cursor["after_id"].as_str().unwrap()),
+        ])
+        .send()
+        .await
+        .unwrap();
+    let second_page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(second_page["subscribers"].as_array().unwrap().len(), 1);
+    assert_ne!(second_page["subscribers"][0], first_page["subscribers"][0]);
+    assert!(second_page["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn invalid_requests_get_a_json_error() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let test_cases = vec![
+        (
+            "/subscribers",
+            serde_json::json!({"name": "le guin", "email": "not-an-email"}),
+            "validation_error",
+        ),
+        (
+            "/subscribers",
+            serde_json::json!({"name": "", "email": "ursula_le_guin@gmail.com"}),
+            "validation_error",
+        ),
+        (
+            "/subscribers",
+            serde_json::json!({"email": "ursula_le_guin@gmail.com"}),
+            "invalid_request",
+        ),
+        (
+            "/issues",
+            serde_json::json!({
+                "title": "Newsletter title",
+                "html_content": "<p>Newsletter body as HTML</p>",
+                "text_content": "Newsletter body as plain text",
+                "segment": "beta AND"
+            }),
+            "validation_error",
+        ),
+        (
+            "/issues",
+            serde_json::json!({
+                "title": "Newsletter title",
+                "html_content": "<p>Newsletter body as HTML</p>",
+                "text_content": "Newsletter body as plain text",
+                "list_id": uuid::Uuid::new_v4()
+            }),
+            "validation_error",
+        ),
+    ];
+    for (path, body, expected_code) in test_cases {
+        let response = test_app.post_api(path, &body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 for {}",
+            body
+        );
+        assert_eq!(error_code(response).await, expected_code);
+    }
+
+    let response = test_app.get_api("/subscribers?limit=0").await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(error_code(response).await, "validation_error");
+
+    let response = test_app.get_api("/issues/not-a-uuid").await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(error_code(response).await, "invalid_request");
+}
+
+#[tokio::test]
+async fn api_posts_need_the_csrf_token() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let response = test_app
+        .api_client
+        .post(format!("{}/api/v1/issues", &test_app.address))
+        .json(&issue_body())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(error_code(response).await, "forbidden");
+    let response = test_app.get_api("/issues").await;
+    let page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(page["issues"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn issues_need_a_title_and_content() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    for field in ["title", "html_content", "text_content"] {
+        let mut body = issue_body();
+        body[field] = serde_json::json!("  ");
+        let response = test_app.post_api("/issues", &body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Blank {} was accepted",
+            field
+        );
+        assert_eq!(error_code(response).await, "validation_error");
+    }
+    let response = test_app.get_api("/issues").await;
+    let page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(page["issues"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn issues_are_drafts_until_they_are_published() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_api("/issues", &issue_body()).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert!(issue["published_at"].is_null());
+    let issue_path = format!("/issues/{}", issue["newsletter_issue_id"].as_str().unwrap());
+
+    // Nothing is sent for a draft
+    test_app.dispatch_all_pending_emails().await;
+    let response = test_app.get_api(&format!("{}/stats", issue_path)).await;
+    let stats: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(stats["queued"], 0);
+    assert_eq!(stats["outcomes"], serde_json::json!({}));
+
+    let response = test_app
+        .post_api(&format!("{}/publish", issue_path), &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert!(published["published_at"].is_string());
+    let response = test_app.get_api(&format!("{}/stats", issue_path)).await;
+    let stats: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(stats["queued"], 1);
+
+    test_app.dispatch_all_pending_emails().await;
+    let response = test_app.get_api(&format!("{}/stats", issue_path)).await;
+    let stats: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(stats["queued"], 0);
+    assert_eq!(stats["outcomes"], serde_json::json!({"sent": 1}));
+
+    let response = test_app.get_api("/issues").await;
+    let page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(page["issues"], serde_json::json!([published]));
+}
+
+#[tokio::test]
+async fn issues_are_listed_a_page_at_a_time() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    test_app.post_api("/issues", &issue_body()).await;
+    test_app.post_api("/issues", &issue_body()).await;
+
+    let response = test_app.get_api("/issues?limit=1").await;
+    let first_page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(first_page["issues"].as_array().unwrap().len(), 1);
+    let cursor = &first_page["next_cursor"];
+
+    let response = test_app
+        .api_client
+        .get(format!("{}/api/v1/issues", &test_app.address))
+        .query(&[
+            ("limit", "1"),
+            (
+                "after_created_at",
+                cursor["after_created_at"].as_str().unwrap(),
+            ),
+            ("after_id", cursor["after_id"].as_str().unwrap()),
+        ])
+        .send()
+        .await
+        .unwrap();
+    let second_page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(second_page["issues"].as_array().unwrap().len(), 1);
+    assert_ne!(second_page["issues"][0], first_page["issues"][0]);
+    assert!(second_page["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn issues_cannot_be_published_twice() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let response = test_app.post_api("/issues", &issue_body()).await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let publish_path = format!(
+        "/issues/{}/publish",
+        issue["newsletter_issue_id"].as_str().unwrap()
+    );
+
+    let response = test_app
+        .post_api(&publish_path, &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = test_app
+        .post_api(&publish_path, &serde_json::json!({}))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(error_code(response).await, "already_published");
+}
+
+#[tokio::test]
+async fn viewers_can_read_the_stats_of_an_issue_but_not_create_issues() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let response = test_app.post_api("/issues", &issue_body()).await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    test_app.post_logout().await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&test_app.db_pool).await;
+    test_app.login_as(&viewer).await;
+
+    let response = test_app
+        .get_api(&format!(
+            "/issues/{}/stats",
+            issue["newsletter_issue_id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = test_app.post_api("/issues", &issue_body()).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(error_code(response).await, "forbidden");
+    let response = test_app.get_api("/subscribers").await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(error_code(response).await, "forbidden");
+}
@@ -0,0 +1,77 @@
+use crate::helpers::spawn_app;
+use reqwest::header::HeaderMap;
+
+fn assert_security_headers(headers: &HeaderMap) {
+    let csp = headers["Content-Security-Policy"].to_str().unwrap();
+    assert!(csp.contains("default-src 'self'"));
+    assert!(csp.contains("frame-ancestors 'none'"));
+    assert_eq!(headers["X-Frame-Options"], "DENY");
+    assert_eq!(headers["Referrer-Policy"], "same-origin");
+    assert_eq!(headers["X-Content-Type-Options"], "nosniff");
+}
+
+#[tokio::test]
+async fn public_pages_are_sent_with_security_headers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for path in ["/", "/login"] {
+        // Act
+        let response = app
+            .api_client
+            .get(format!("{}{}", &app.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // Assert
+        assert_security_headers(response.headers());
+    }
+}
+
+#[tokio::test]
+async fn admin_pages_are_sent_with_security_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    // Act
+    let response = app.get_admin_dashboard().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_security_headers(response.headers());
+}
+
+#[tokio::test]
+async fn redirects_are_sent_with_security_headers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_dashboard().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    assert_security_headers(response.headers());
+}
+
+#[tokio::test]
+async fn hsts_is_off_outside_of_production() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/login", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response
+        .headers()
+        .get("Strict-Transport-Security")
+        .is_none());
+}
@@ -0,0 +1,151 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscriber_emails(test_app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch subscribers")
+        .into_iter()
+        .map(|r| r.email)
+        .collect()
+}
+
+async fn tag_subscriber(test_app: &TestApp, email: &str, tag: &str) {
+    let response = test_app
+        .post_admin_tags(&serde_json::json!({
+            "email": email,
+            "tag": tag,
+            "action": "add",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/tags");
+}
+
+#[tokio::test]
+async fn an_admin_can_tag_and_untag_a_subscriber() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let email = subscriber_emails(&test_app).await.pop().unwrap();
+    test_app.login().await;
+
+    tag_subscriber(&test_app, &email, "beta testers").await;
+    let html_page = test_app.get_admin_tags_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>{} has been tagged beta testers.</i></p>",
+        email
+    )));
+    assert!(html_page.contains("<li>beta testers (1 subscribers)</li>"));
+
+    let response = test_app
+        .post_admin_tags(&serde_json::json!({
+            "email": email,
+            "tag": "beta testers",
+            "action": "remove",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/tags");
+    let html_page = test_app.get_admin_tags_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>{} is no longer tagged beta testers.</i></p>",
+        email
+    )));
+    assert!(!html_page.contains("<li>beta testers"));
+}
+
+#[tokio::test]
+async fn tagging_an_unknown_subscriber_is_rejected() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    tag_subscriber(&test_app, "nobody@example.com", "beta").await;
+
+    let html_page = test_app.get_admin_tags_html().await;
+    assert!(html_page
+        .contains("<p><i>There is no subscriber with the email nobody@example.com.</i></p>"));
+}
+
+#[tokio::test]
+async fn newsletters_are_only_delivered_to_subscribers_matching_the_segment() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_confirmed_subscriber(&test_app).await;
+    let emails = subscriber_emails(&test_app).await;
+    test_app.login().await;
+    tag_subscriber(&test_app, &emails[0], "beta").await;
+    tag_subscriber(&test_app, &emails[1], "beta").await;
+    tag_subscriber(&test_app, &emails[1], "paid").await;
+
+    // The audience preview counts the matching subscribers before sending
+    let html_page = test_app
+        .get_admin_newsletters_audience_html(&[("segment", "beta AND NOT paid")])
+        .await;
+    assert!(html_page.contains("<p><i>This issue will be delivered to 1 subscribers.</i></p>"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "segment": "beta AND NOT paid",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    // The confirmation emails were received first
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], emails[0]);
+
+    let segment = sqlx::query!("SELECT segment FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .segment;
+    assert_eq!(segment.as_deref(), Some(r#"("beta" AND NOT "paid")"#));
+}
+
+#[tokio::test]
+async fn newsletters_with_an_invalid_segment_are_rejected() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.login().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "segment": "beta AND",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = test_app.get_admin_newsletters_html().await;
+    assert!(html_page.contains("<p><i>The segment expression ended unexpectedly.</i></p>"));
+    test_app.dispatch_all_pending_emails().await;
+}
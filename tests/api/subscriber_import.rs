@@ -0,0 +1,253 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::{any, body_partial_json, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscriber_statuses(test_app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.status))
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_import_subscribers("confirmed", "email,name\na@example.com,a")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn imported_rows_are_validated_and_duplicates_and_suppressed_addresses_are_skipped() {
+    let test_app = spawn_app().await;
+    sqlx::query!(
+        "INSERT INTO suppressed_emails (email, reason, suppressed_at) \
+        VALUES ('suppressed@example.com', 'hard_bounce', now())"
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    test_app.login().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let csv = "name,email\n\
+        Ursula Le Guin,ursula@example.com\n\
+        \"Butler, Octavia\",octavia@example.com\n\
+        \n\
+        Not an email,not-an-email\n\
+        Ursula again,URSULA@example.com\n\
+        Suppressed,suppressed@example.com\n";
+    let response = test_app.post_import_subscribers("confirmed", csv).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<p>Imported 2 subscribers.</p>"));
+    assert!(html_page
+        .contains("<p>Skipped 1 duplicates, 1 suppressed addresses and 1 invalid rows.</p>"));
+    assert!(html_page.contains("<tr><td>5</td><td>not-an-email</td>"));
+    assert!(html_page
+        .contains("<tr><td>6</td><td>URSULA@example.com</td><td>Already subscribed</td></tr>"));
+    assert!(
+        html_page.contains("<tr><td>7</td><td>suppressed@example.com</td><td>Suppressed</td></tr>")
+    );
+
+    assert_eq!(
+        subscriber_statuses(&test_app).await,
+        vec![
+            ("octavia@example.com".to_string(), "confirmed".to_string()),
+            ("ursula@example.com".to_string(), "confirmed".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn quoted_fields_may_span_lines() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let csv = "email,name\r\n\
+        a@example.com,\"Ursula\r\nLe Guin\"\r\n\
+        not-an-email,b\r\n\
+        c@example.com,c";
+    let response = test_app.post_import_subscribers("confirmed", csv).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<p>Imported 2 subscribers.</p>"));
+    assert!(html_page.contains("<tr><td>4</td><td>not-an-email</td>"));
+    let name = sqlx::query!("SELECT name FROM subscriptions WHERE email = 'a@example.com'")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .name;
+    assert_eq!(name, "Ursula\r\nLe Guin");
+}
+
+#[tokio::test]
+async fn existing_subscribers_are_added_to_the_list_they_are_imported_into() {
+    let test_app = spawn_app().await;
+    let list_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, name, created_at) VALUES ($1, 'Rust weekly', now())",
+        list_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    test_app.login().await;
+    test_app
+        .post_import_subscribers("confirmed", "email,name\na@example.com,a")
+        .await;
+
+    let response = test_app
+        .post_import_subscribers_to_list(list_id, "confirmed", "email,name\nA@example.com,a")
+        .await;
+
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<p>Imported 1 subscribers.</p>"));
+    let n_lists = sqlx::query!(
+        r#"SELECT count(*) AS "count!" FROM list_subscriptions WHERE status = 'confirmed'"#
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_lists, 2);
+
+    // Importing them into the same list again is a duplicate
+    let response = test_app
+        .post_import_subscribers_to_list(list_id, "confirmed", "email,name\na@example.com,a")
+        .await;
+    let html_page = response.text().await.unwrap();
+    assert!(
+        html_page.contains("<tr><td>2</td><td>a@example.com</td><td>Already subscribed</td></tr>")
+    );
+}
+
+#[tokio::test]
+async fn pending_imports_queue_confirmation_emails() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let response = test_app
+        .post_import_subscribers(
+            "pending_confirmation",
+            "email,name\na@example.com,a\nb@example.com,b",
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    // Nothing is sent while the upload is being processed
+    assert!(test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = test_app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let statuses: Vec<String> = subscriber_statuses(&test_app)
+        .await
+        .into_iter()
+        .map(|(_, status)| status)
+        .collect();
+    assert!(statuses.contains(&"confirmed".to_string()));
+    assert!(statuses.contains(&"pending_confirmation".to_string()));
+}
+
+#[tokio::test]
+async fn a_failing_confirmation_email_does_not_hold_up_newsletters() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.login().await;
+    test_app
+        .post_import_subscribers("pending_confirmation", "email,name\na@example.com,a")
+        .await;
+
+    Mock::given(path("/email"))
+        .and(body_partial_json(
+            serde_json::json!({"To": "a@example.com"}),
+        ))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(5)
+        .mount(&test_app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    test_app.dispatch_all_pending_emails().await;
+    let n_queued = sqlx::query!(r#"SELECT count(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+
+    // The confirmation email is given up on after a few attempts
+    for _ in 0..10 {
+        test_app.expire_retry_delays().await;
+        test_app.dispatch_all_pending_emails().await;
+    }
+    let n_queued = sqlx::query!(r#"SELECT count(*) AS "count!" FROM confirmation_email_queue"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn a_csv_without_email_and_name_columns_is_rejected() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let response = test_app
+        .post_import_subscribers("confirmed", "address,full name\na@example.com,a")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(subscriber_statuses(&test_app).await.is_empty());
+}
@@ -0,0 +1,374 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::confirmation_throttle::MAX_CONFIRMATION_EMAILS_PER_WINDOW;
+use zero2prod::mailing_lists::{insert_list, DEFAULT_LIST_ID};
+
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+}
+
+async fn get_subscriber(test_app: &TestApp) -> Subscriber {
+    let r = sqlx::query!("SELECT id, email, name FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch subscriber");
+    Subscriber {
+        id: r.id,
+        email: r.email,
+        name: r.name,
+    }
+}
+
+/// The form as rendered for a subscriber on the default list, with `overrides` applied
+fn preferences_form(
+    test_app: &TestApp,
+    subscriber: &Subscriber,
+    overrides: serde_json::Value,
+) -> serde_json::Value {
+    let mut form = serde_json::json!({
+        "subscriber_id": subscriber.id,
+        "signature": test_app.manage_links.signature(subscriber.id),
+        "name": subscriber.name,
+        "email": subscriber.email,
+        "paused_until": "",
+        format!("list_{}", DEFAULT_LIST_ID): "on",
+    });
+    for (key, value) in overrides.as_object().unwrap() {
+        if value.is_null() {
+            form.as_object_mut().unwrap().remove(key);
+        } else {
+            form[key] = value.clone();
+        }
+    }
+    form
+}
+
+async fn publish_newsletter(test_app: &TestApp) {
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn newsletter_issues_link_to_the_preference_center() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = get_subscriber(&test_app).await;
+    test_app.login().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    publish_newsletter(&test_app).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // The confirmation email carries a manage link too - look at the newsletter only
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let email_request = email_requests
+        .iter()
+        .find(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            body["Subject"] == "Newsletter title"
+        })
+        .expect("The newsletter was not sent");
+    let manage_links = test_app.get_manage_links(email_request);
+    assert_eq!(manage_links.html, manage_links.plain_text);
+
+    let response = reqwest::get(manage_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&subscriber.email));
+}
+
+#[tokio::test]
+async fn a_link_with_an_invalid_signature_is_rejected() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = get_subscriber(&test_app).await;
+    let forged_signature = test_app.manage_links.signature(Uuid::new_v4());
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/manage?subscriber_id={}&signature={}",
+        test_app.address, subscriber.id, forged_signature
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let form = preferences_form(
+        &test_app,
+        &subscriber,
+        serde_json::json!({ "signature": forged_signature, "name": "mallory" }),
+    );
+    let response = test_app.post_manage_subscription(&form).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_subscriber(&test_app).await.name, subscriber.name);
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = get_subscriber(&test_app).await;
+
+    let form = preferences_form(
+        &test_app,
+        &subscriber,
+        serde_json::json!({ "name": "Ursula K. Le Guin" }),
+    );
+    let response = test_app.post_manage_subscription(&form).await;
+    assert_is_redirect_to(&response, &test_app.manage_links.path(subscriber.id));
+
+    let html_page = test_app.get_manage_subscription_html(subscriber.id).await;
+    assert!(html_page.contains("<p><i>Your preferences have been updated.</i></p>"));
+    assert_eq!(get_subscriber(&test_app).await.name, "Ursula K. Le Guin");
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_rejected() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = get_subscriber(&test_app).await;
+
+    let form = preferences_form(&test_app, &subscriber, serde_json::json!({ "name": "" }));
+    let response = test_app.post_manage_subscription(&form).await;
+    assert_is_redirect_to(&response, &test_app.manage_links.path(subscriber.id));
+
+    let html_page = test_app.get_manage_subscription_html(subscriber.id).await;
+    assert!(html_page.contains(" is not a valid subscriber name."));
+    assert_eq!(get_subscriber(&test_app).await.name, subscriber.name);
+}
+
+#[tokio::test]
+async fn paused_and_unsubscribed_subscribers_do_not_receive_newsletters() {
+    let overrides = vec![
+        serde_json::json!({ "paused_until": "2999-01-01" }),
+        serde_json::json!({ format!("list_{}", DEFAULT_LIST_ID): null }),
+    ];
+    for overrides in overrides {
+        let test_app = spawn_app().await;
+        create_confirmed_subscriber(&test_app).await;
+        let subscriber = get_subscriber(&test_app).await;
+        test_app.login().await;
+
+        let form = preferences_form(&test_app, &subscriber, overrides.clone());
+        let response = test_app.post_manage_subscription(&form).await;
+        assert_is_redirect_to(&response, &test_app.manage_links.path(subscriber.id));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&test_app.email_server)
+            .await;
+        publish_newsletter(&test_app).await;
+        test_app.dispatch_all_pending_emails().await;
+    }
+}
+
+#[tokio::test]
+async fn a_pause_must_end_in_the_future() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = get_subscriber(&test_app).await;
+
+    let form = preferences_form(
+        &test_app,
+        &subscriber,
+        serde_json::json!({ "paused_until": "2000-01-01" }),
+    );
+    test_app.post_manage_subscription(&form).await;
+
+    let html_page = test_app.get_manage_subscription_html(subscriber.id).await;
+    assert!(html_page.contains("<p><i>The pause must end in the future.</i></p>"));
+}
+
+#[tokio::test]
+async fn an_email_change_only_applies_once_the_new_address_is_confirmed() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = get_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let form = preferences_form(
+        &test_app,
+        &subscriber,
+        serde_json::json!({ "email": "new.address@example.com" }),
+    );
+    let response = test_app.post_manage_subscription(&form).await;
+    assert_is_redirect_to(&response, &test_app.manage_links.path(subscriber.id));
+    assert_eq!(get_subscriber(&test_app).await.email, subscriber.email);
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "new.address@example.com");
+
+    let confirmation_links = test_app.get_confirmation_links(&email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        get_subscriber(&test_app).await.email,
+        "new.address@example.com"
+    );
+}
+
+#[tokio::test]
+async fn email_change_links_do_not_expire_with_confirmation_links() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = get_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let form = preferences_form(
+        &test_app,
+        &subscriber,
+        serde_json::json!({ "email": "new.address@example.com" }),
+    );
+    test_app.post_manage_subscription(&form).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '2 days'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = test_app.get_confirmation_links(&email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        get_subscriber(&test_app).await.email,
+        "new.address@example.com"
+    );
+}
+
+#[tokio::test]
+async fn asking_again_for_the_same_address_resends_the_same_link() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = get_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+    let form = preferences_form(
+        &test_app,
+        &subscriber,
+        serde_json::json!({ "email": "new.address@example.com" }),
+    );
+    test_app.post_manage_subscription(&form).await;
+    test_app.post_manage_subscription(&form).await;
+
+    // The confirmation of the subscription itself was sent first
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let n_requests = email_requests.len();
+    let first_link = test_app
+        .get_confirmation_links(&email_requests[n_requests - 2])
+        .html;
+    let second_link = test_app
+        .get_confirmation_links(&email_requests[n_requests - 1])
+        .html;
+    assert_eq!(first_link, second_link);
+    let n_tokens = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "n!" FROM subscription_tokens WHERE new_email IS NOT NULL"#
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_tokens, 1);
+}
+
+#[tokio::test]
+async fn email_change_confirmations_are_throttled_per_address() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = get_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(MAX_CONFIRMATION_EMAILS_PER_WINDOW as u64)
+        .mount(&test_app.email_server)
+        .await;
+    let form = preferences_form(
+        &test_app,
+        &subscriber,
+        serde_json::json!({ "email": "new.address@example.com" }),
+    );
+    for _ in 0..MAX_CONFIRMATION_EMAILS_PER_WINDOW {
+        test_app.post_manage_subscription(&form).await;
+    }
+    let response = test_app.post_manage_subscription(&form).await;
+    assert_is_redirect_to(&response, &test_app.manage_links.path(subscriber.id));
+
+    let html_page = test_app.get_manage_subscription_html(subscriber.id).await;
+    assert!(
+        html_page.contains("Too many confirmation emails were sent to new.address@example.com.")
+    );
+}
+
+#[tokio::test]
+async fn pending_subscribers_cannot_join_other_lists() {
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(&test_app).await;
+    let subscriber = get_subscriber(&test_app).await;
+    let other_list_id = insert_list(&test_app.db_pool, "Rust weekly").await.unwrap();
+
+    let form = preferences_form(
+        &test_app,
+        &subscriber,
+        serde_json::json!({ format!("list_{}", other_list_id): "on" }),
+    );
+    let response = test_app.post_manage_subscription(&form).await;
+    assert_is_redirect_to(&response, &test_app.manage_links.path(subscriber.id));
+
+    let html_page = test_app.get_manage_subscription_html(subscriber.id).await;
+    assert!(html_page.contains("Please confirm your subscription before joining other lists."));
+    let n_memberships = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "n!" FROM list_subscriptions WHERE list_id = $1"#,
+        other_list_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_memberships, 0);
+}
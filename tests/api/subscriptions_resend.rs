@@ -0,0 +1,178 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app};
+
+#[tokio::test]
+async fn resending_a_confirmation_email_reuses_the_pending_link() {
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let response = test_app
+        .post_resend_confirmation("ursula_le_guin@gmail.com")
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("we have sent a new confirmation link"));
+
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let first_link = test_app.get_confirmation_links(&email_requests[0]);
+    let second_link = test_app.get_confirmation_links(&email_requests[1]);
+    assert_eq!(first_link.html, second_link.html);
+
+    // The resent link confirms the subscription
+    let response = reqwest::get(second_link.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resending_to_an_unknown_address_gives_the_same_response_and_sends_nothing() {
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_resend_confirmation("nobody@example.com")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("we have sent a new confirmation link"));
+}
+
+#[tokio::test]
+async fn resending_to_an_invalid_address_returns_a_400() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.post_resend_confirmation("not-an-email").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_throttled_per_address() {
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    test_app.post_subscriptions(body.into()).await;
+    let response = test_app
+        .post_resend_confirmation("ursula_le_guin@gmail.com")
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = test_app
+        .post_resend_confirmation("URSULA_LE_GUIN@gmail.com")
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.text().await.unwrap().contains("try again later"));
+
+    let response = test_app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn the_throttle_window_slides() {
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    for _ in 0..3 {
+        test_app.post_subscriptions(body.into()).await;
+    }
+    // Age the earliest send out of the window
+    sqlx::query!(
+        r#"
+        UPDATE confirmation_email_sends SET sent_at = now() - interval '2 hours'
+        WHERE sent_at = (SELECT MIN(sent_at) FROM confirmation_email_sends)
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    let response = test_app
+        .post_resend_confirmation("ursula_le_guin@gmail.com")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        test_app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .len(),
+        4
+    );
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_and_a_fresh_one_is_resent() {
+    let test_app = spawn_app().await;
+    let expired_link = create_unconfirmed_subscriber(&test_app).await;
+
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '2 days'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(expired_link.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.post_resend_confirmation(&email).await;
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let fresh_link = test_app.get_confirmation_links(&email_request);
+    assert_ne!(fresh_link.html, expired_link.html);
+    let response = reqwest::get(fresh_link.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,124 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn suppress(test_app: &TestApp, email: &str, reason: &str) {
+    sqlx::query!(
+        "INSERT INTO suppressed_emails (email, reason, suppressed_at) VALUES ($1, $2, now())",
+        email,
+        reason
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .expect("Failed to suppress email");
+}
+
+#[tokio::test]
+async fn suppressed_emails_cannot_subscribe_again() {
+    let test_app = spawn_app().await;
+    suppress(&test_app, "Ursula_Le_Guin@gmail.com", "complained").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_emails() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    suppress(&test_app, &subscriber.email, "unsubscribed").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.login().await;
+    test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn a_hard_bounce_adds_the_address_to_the_suppression_list() {
+    let test_app = spawn_app().await;
+    test_app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Bounce",
+            "MessageID": Uuid::new_v4().to_string(),
+            "Type": "HardBounce",
+            "Email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    let saved = sqlx::query!("SELECT email, reason FROM suppressed_emails")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch the suppressed email");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.reason, "bounced");
+}
+
+#[tokio::test]
+async fn an_admin_can_remove_a_suppressed_email() {
+    let test_app = spawn_app().await;
+    suppress(&test_app, "ursula_le_guin@gmail.com", "bounced").await;
+    test_app.login().await;
+
+    let html_page = test_app.get_admin_suppressions_html().await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+
+    let response = test_app
+        .post_remove_suppression(&serde_json::json!({
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    let html_page = test_app.get_admin_suppressions_html().await;
+    assert!(html_page.contains("<p><i>ursula_le_guin@gmail.com can be mailed again.</i></p>"));
+
+    // The address can now subscribe again
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    test_app.post_subscriptions(body.into()).await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_suppression_list() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_admin_suppressions().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,212 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use secrecy::Secret;
+use zero2prod::authentication::{totp_code, totp_step, MAX_FAILED_LOGIN_ATTEMPTS};
+
+struct TwoFactor {
+    secret: Secret<Vec<u8>>,
+    recovery_codes: Vec<String>,
+}
+
+impl TwoFactor {
+    // The code of the current step was used up by the enrolment, the next one is still accepted
+    fn next_code(&self) -> String {
+        totp_code(&self.secret, totp_step(Utc::now()) + 1)
+    }
+}
+
+fn text_between<'a>(html: &'a str, start: &str, end: &str) -> Vec<&'a str> {
+    html.split(start)
+        .skip(1)
+        .map(|s| s.split(end).next().unwrap())
+        .collect()
+}
+
+async fn enable_two_factor(test_app: &TestApp) -> TwoFactor {
+    test_app.post_admin_two_factor("enroll", "").await;
+    let html_page = test_app.get_admin_two_factor_html().await;
+    let key = text_between(&html_page, "Or type in this key: <code>", "</code>")[0];
+    let secret = Secret::new(BASE32_NOPAD.decode(key.as_bytes()).unwrap());
+
+    let code = totp_code(&secret, totp_step(Utc::now()));
+    let response = test_app.post_admin_two_factor("confirm", &code).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let recovery_codes = text_between(&response.text().await.unwrap(), "<li><code>", "</code>")
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+    TwoFactor {
+        secret,
+        recovery_codes,
+    }
+}
+
+#[tokio::test]
+async fn enrolment_shows_a_qr_code_and_needs_a_valid_code() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let html_page = test_app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("Two-factor authentication is off."));
+
+    let response = test_app.post_admin_two_factor("enroll", "").await;
+    assert_is_redirect_to(&response, "/admin/two_factor");
+    let html_page = test_app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("<svg"));
+    assert!(html_page.contains(&format!(
+        "otpauth://totp/zero2prod:{}?secret=",
+        test_app.test_user.username
+    )));
+
+    let response = test_app.post_admin_two_factor("confirm", "000000").await;
+    assert_is_redirect_to(&response, "/admin/two_factor");
+    let html_page = test_app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("The code is not valid."));
+    let user = sqlx::query!(
+        "SELECT totp_enabled FROM users WHERE user_id = $1",
+        test_app.test_user.user_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert!(!user.totp_enabled);
+}
+
+#[tokio::test]
+async fn confirming_the_enrolment_turns_two_factor_on_and_shows_recovery_codes() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+
+    let two_factor = enable_two_factor(&test_app).await;
+
+    assert_eq!(two_factor.recovery_codes.len(), 10);
+    let html_page = test_app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("You have 10 unused recovery codes."));
+    // The secret is stored encrypted
+    let user = sqlx::query!(
+        "SELECT totp_enabled, totp_secret FROM users WHERE user_id = $1",
+        test_app.test_user.user_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert!(user.totp_enabled);
+    let stored_secret = user.totp_secret.unwrap();
+    let secret = secrecy::ExposeSecret::expose_secret(&two_factor.secret);
+    assert!(!stored_secret
+        .windows(secret.len())
+        .any(|window| window == secret.as_slice()));
+}
+
+#[tokio::test]
+async fn login_asks_for_the_second_factor_once_enabled() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let two_factor = enable_two_factor(&test_app).await;
+    test_app.post_logout().await;
+
+    let response = test_app.login().await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+    // The password alone does not log in
+    let response = test_app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(test_app
+        .get_login_two_factor_html()
+        .await
+        .contains("authenticator app"));
+
+    let response = test_app
+        .post_login_two_factor(&two_factor.next_code())
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = test_app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", test_app.test_user.username)));
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let two_factor = enable_two_factor(&test_app).await;
+    test_app.post_logout().await;
+    let code = two_factor.next_code();
+    test_app.login().await;
+    test_app.post_login_two_factor(&code).await;
+    test_app.post_logout().await;
+
+    test_app.login().await;
+    let response = test_app.post_login_two_factor(&code).await;
+
+    assert_is_redirect_to(&response, "/login/two_factor");
+    let html_page = test_app.get_login_two_factor_html().await;
+    assert!(html_page.contains("Authentication failed"));
+}
+
+#[tokio::test]
+async fn a_recovery_code_logs_in_once() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let two_factor = enable_two_factor(&test_app).await;
+    test_app.post_logout().await;
+    let recovery_code = &two_factor.recovery_codes[0];
+
+    test_app.login().await;
+    let response = test_app
+        .post_login_two_factor(&recovery_code.to_uppercase())
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    test_app.post_logout().await;
+
+    test_app.login().await;
+    let response = test_app.post_login_two_factor(recovery_code).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+}
+
+#[tokio::test]
+async fn wrong_codes_count_towards_the_lockout() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let two_factor = enable_two_factor(&test_app).await;
+    test_app.post_logout().await;
+
+    test_app.login().await;
+    for _ in 0..MAX_FAILED_LOGIN_ATTEMPTS {
+        test_app.post_login_two_factor("000000").await;
+    }
+    let response = test_app
+        .post_login_two_factor(&two_factor.next_code())
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    let response = test_app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn two_factor_can_be_turned_off_with_a_valid_code() {
+    let test_app = spawn_app().await;
+    test_app.login().await;
+    let two_factor = enable_two_factor(&test_app).await;
+
+    let response = test_app.post_admin_two_factor("disable", "000000").await;
+    assert_is_redirect_to(&response, "/admin/two_factor");
+    assert!(test_app
+        .get_admin_two_factor_html()
+        .await
+        .contains("The code is not valid."));
+
+    let response = test_app
+        .post_admin_two_factor("disable", &two_factor.next_code())
+        .await;
+    assert_is_redirect_to(&response, "/admin/two_factor");
+    assert!(test_app
+        .get_admin_two_factor_html()
+        .await
+        .contains("Two-factor authentication is off."));
+    test_app.post_logout().await;
+
+    let response = test_app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
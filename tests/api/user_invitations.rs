@@ -0,0 +1,255 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const INVITED_EMAIL: &str = "new-admin@example.com";
+
+// Invites INVITED_EMAIL as an editor and returns the token from the email
+async fn invite_editor(test_app: &TestApp) -> String {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.login().await;
+    let response = test_app
+        .post_admin_user_invite(INVITED_EMAIL, "editor")
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    test_app.post_logout().await;
+
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let invitation_link = test_app.get_invitation_links(email_request).html;
+    invitation_link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+async fn accept(
+    test_app: &TestApp,
+    token: &str,
+    username: &str,
+    password: &str,
+) -> reqwest::Response {
+    test_app
+        .post_accept_invitation(&serde_json::json!({
+            "token": token,
+            "username": username,
+            "password": password,
+            "password_check": password,
+        }))
+        .await
+}
+
+#[tokio::test]
+async fn owners_can_invite_users_by_email() {
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.login().await;
+
+    let response = test_app
+        .post_admin_user_invite(INVITED_EMAIL, "viewer")
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = test_app.get_admin_users_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>An invitation to join as viewer has been sent to {}.</i></p>",
+        INVITED_EMAIL
+    )));
+    assert!(html_page.contains(&format!("<td>{}</td>", INVITED_EMAIL)));
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], INVITED_EMAIL);
+}
+
+#[tokio::test]
+async fn invitees_choose_their_username_and_password() {
+    let test_app = spawn_app().await;
+    let token = invite_editor(&test_app).await;
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    let html_page = test_app
+        .api_client
+        .get(format!(
+            "{}/invitations/accept?token={}",
+            &test_app.address, token
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(&format!(
+        "{} invited {} to join as editor.",
+        test_app.test_user.username, INVITED_EMAIL
+    )));
+
+    let response = accept(&test_app, &token, &username, &password).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = test_app
+        .post_login(&serde_json::json!({ "username": &username, "password": &password }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = test_app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("You are logged in as editor."));
+    let email = sqlx::query!("SELECT email FROM users WHERE username = $1", username)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    assert_eq!(email.as_deref(), Some(INVITED_EMAIL));
+
+    // Invitations are single-use
+    let response = accept(&test_app, &token, "someone-else", &password).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("The invitation is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn a_taken_username_keeps_the_invitation_pending() {
+    let test_app = spawn_app().await;
+    let token = invite_editor(&test_app).await;
+    let password = Uuid::new_v4().to_string();
+
+    let response = accept(&test_app, &token, &test_app.test_user.username, &password).await;
+
+    assert_is_redirect_to(&response, &format!("/invitations/accept?token={}", token));
+    let response = accept(&test_app, &token, &Uuid::new_v4().to_string(), &password).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("Your account has been created. You can now log in."));
+}
+
+#[tokio::test]
+async fn revoked_invitations_cannot_be_accepted() {
+    let test_app = spawn_app().await;
+    let token = invite_editor(&test_app).await;
+    let invitation_id = sqlx::query!("SELECT invitation_id FROM user_invitations")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .invitation_id;
+    test_app.login().await;
+
+    let response = test_app.post_revoke_invitation(invitation_id).await;
+
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = test_app.get_admin_users_html().await;
+    assert!(html_page.contains(&format!(
+        "The invitation of {} has been revoked.",
+        INVITED_EMAIL
+    )));
+    let response = accept(
+        &test_app,
+        &token,
+        &Uuid::new_v4().to_string(),
+        &Uuid::new_v4().to_string(),
+    )
+    .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("The invitation is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn expired_invitations_cannot_be_accepted() {
+    let test_app = spawn_app().await;
+    let token = invite_editor(&test_app).await;
+    sqlx::query!("UPDATE user_invitations SET created_at = now() - interval '8 days'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let response = accept(
+        &test_app,
+        &token,
+        &Uuid::new_v4().to_string(),
+        &Uuid::new_v4().to_string(),
+    )
+    .await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("The invitation is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn only_owners_can_invite_users() {
+    let test_app = spawn_app().await;
+    let editor = TestUser::with_role("editor");
+    editor.store(&test_app.db_pool).await;
+    test_app.login_as(&editor).await;
+
+    let response = test_app
+        .post_admin_user_invite(INVITED_EMAIL, "owner")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn addresses_of_existing_users_cannot_be_invited() {
+    let test_app = spawn_app().await;
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        INVITED_EMAIL,
+        test_app.test_user.user_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.login().await;
+
+    let response = test_app
+        .post_admin_user_invite(&INVITED_EMAIL.to_uppercase(), "viewer")
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = test_app.get_admin_users_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>{} already belongs to an admin user.</i></p>",
+        INVITED_EMAIL.to_uppercase()
+    )));
+    assert!(!html_page.contains(&format!("<td>{}</td>", INVITED_EMAIL)));
+}
+
+#[tokio::test]
+async fn the_invitation_email_is_sent_once_the_invitation_is_stored() {
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.login().await;
+
+    let response = test_app
+        .post_admin_user_invite(INVITED_EMAIL, "viewer")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 500);
+    // The stored invitation can be revoked, or replaced by sending it again
+    let html_page = test_app.get_admin_users_html().await;
+    assert!(html_page.contains(&format!("<td>{}</td>", INVITED_EMAIL)));
+}